@@ -0,0 +1,863 @@
+use axum::{
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        OriginalUri, Path, Query, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use log::{debug, info};
+use uuid::Uuid;
+
+use crate::{
+    api::AdminAuth,
+    handlers::{employee_changes, expected_version, query_employees, requested_fields},
+    models::{
+        change_models::{ChangesQuery, ChangesResponse},
+        employee_models::{
+            CreateEmployeeSchema, Employee, EmployeeCredentialsResponse, EmployeePageResponse,
+            EmployeeResponse, QueryOptions, UpdateEmployeeSchema,
+        },
+        view_models::FieldsQuery,
+    },
+    utils::{
+        errors::{ApiError, DataStoreError, Problem},
+        etag::etag,
+        password_utils::generate_random_password,
+        state::AppState,
+    },
+    views::Projection,
+};
+
+pub const EMPLOYEES_PATH: &str = "/api/v2/employees";
+
+fn not_found(id: &str) -> ApiError {
+    DataStoreError::EmployeeNotFound { id: id.to_string() }.into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/employees",
+    tag = "v2",
+    // the v1 handler has the same name
+    operation_id = "create_employee_v2",
+    security(("basic_auth" = [])),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Retries sent with the same key get the first response again"),
+    ),
+    request_body = CreateEmployeeSchema,
+    responses(
+        (status = 201, description = "Employee created", body = EmployeeResponse, headers(
+            ("Location" = String, description = "URI of the employee"),
+            ("ETag" = String, description = "Version of the employee"),
+        )),
+        (status = 400, description = "Invalid JSON", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "An employee has the same name", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Unknown field or invalid employee", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn create_employee(
+    State(state): State<AppState>,
+    AdminAuth(admin): AdminAuth,
+    request: Result<Json<CreateEmployeeSchema>, JsonRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Json(request) = request?;
+
+    let employee = Employee {
+        id: Some(Uuid::new_v4().to_string()),
+        first_name: request.first_name,
+        last_name: request.last_name,
+        personal_email: request.personal_email,
+        avaya_email: None,
+        age: request.age,
+        diploma: request.diploma,
+        onboarded: Some(false),
+        handle: None,
+        password: None,
+        secure_password: Some(false),
+        version: 0,
+        archived: None,
+    };
+    employee.validate()?;
+    if state
+        .file_manager
+        .check_employee_exists(&employee.first_name, &employee.last_name)
+    {
+        return Err(DataStoreError::EmployeeAlreadyExists {
+            first_name: employee.first_name,
+            last_name: employee.last_name,
+        }
+        .into());
+    }
+
+    let employee = state.file_manager.add_employee(employee, &admin).await?;
+    let id = employee.id.clone().unwrap_or_default();
+    info!("Employee {id} created by {admin}");
+    Ok((
+        StatusCode::CREATED,
+        [
+            (header::LOCATION, format!("{EMPLOYEES_PATH}/{id}")),
+            (header::ETAG, etag(employee.version)),
+        ],
+        Json(EmployeeResponse {
+            message: "Employee created successfully".to_string(),
+            data: Projection::Admin.view(&employee),
+        }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/employees",
+    tag = "v2",
+    security(("basic_auth" = [])),
+    params(QueryOptions, FieldsQuery),
+    responses(
+        (status = 200, description = "A page of employees, without passwords", body = EmployeePageResponse,
+            headers(("Link" = String, description = "Links to the other pages"))),
+        (status = 400, description = "Invalid query", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list_employees(
+    State(state): State<AppState>,
+    _: AdminAuth,
+    OriginalUri(uri): OriginalUri,
+    opts: Result<Query<QueryOptions>, QueryRejection>,
+    fields: Result<Query<FieldsQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let fields = requested_fields(fields)?;
+    let (link, json_response) = query_employees(&state, opts, &fields, &uri)?;
+    debug!("{json_response:?}");
+    Ok((link, Json(json_response)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/employees/changes",
+    tag = "v2",
+    security(("basic_auth" = [])),
+    params(ChangesQuery),
+    responses(
+        (status = 200, description = "Changes since the cursor, in commit order", body = ChangesResponse,
+            headers(("Link" = String, description = "Link to the next changes, when there are more"))),
+        (status = 400, description = "Invalid cursor or limit", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list_changes(
+    State(state): State<AppState>,
+    _: AdminAuth,
+    OriginalUri(uri): OriginalUri,
+    query: Result<Query<ChangesQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (headers, json_response) = employee_changes(&state, query, &uri)?;
+    debug!("{json_response:?}");
+    Ok((headers, Json(json_response)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/employees/{id}",
+    tag = "v2",
+    // the v1 handler has the same name
+    operation_id = "get_employee_v2",
+    security(("basic_auth" = [])),
+    params(("id" = String, Path, description = "Employee id"), FieldsQuery),
+    responses(
+        (status = 200, description = "The employee, without password", body = EmployeeResponse,
+            headers(("ETag" = String, description = "Version of the employee"))),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "No such employee", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_employee(
+    State(state): State<AppState>,
+    _: AdminAuth,
+    Path(id): Path<String>,
+    fields: Result<Query<FieldsQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let fields = requested_fields(fields)?;
+    let employee = state
+        .file_manager
+        .get_employee(&id)
+        .ok_or_else(|| not_found(&id))?;
+    Ok((
+        [(header::ETAG, etag(employee.version))],
+        Json(EmployeeResponse {
+            message: "Employee found".to_string(),
+            data: fields.view(&employee),
+        }),
+    ))
+}
+
+// apply a merge patch to the personal data, the client must send the ETag
+// of the version it edited in If-Match
+#[utoipa::path(
+    patch,
+    path = "/api/v2/employees/{id}",
+    tag = "v2",
+    security(("basic_auth" = [])),
+    params(("id" = String, Path, description = "Employee id"), ("If-Match" = String, Header, description = "ETag of the employee, or `*`")),
+    request_body(content = UpdateEmployeeSchema, content_type = "application/merge-patch+json"),
+    responses(
+        (status = 200, description = "Employee updated", body = EmployeeResponse,
+            headers(("ETag" = String, description = "New version of the employee"))),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "No such employee", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "Another employee has the new name", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The employee changed meanwhile", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Unknown field or invalid employee", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn update_employee(
+    State(state): State<AppState>,
+    AdminAuth(admin): AdminAuth,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    patch: Result<Json<UpdateEmployeeSchema>, JsonRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let expected_version = expected_version(&headers)?;
+    let Json(patch) = patch?;
+
+    let current = state
+        .file_manager
+        .get_employee(&id)
+        .ok_or_else(|| not_found(&id))?;
+    let employee = patch
+        .apply(current.clone())
+        .map_err(|field| ApiError::Validation(format!("{field} cannot be removed")))?;
+    employee.validate()?;
+    let renamed =
+        (&employee.first_name, &employee.last_name) != (&current.first_name, &current.last_name);
+    if renamed
+        && state
+            .file_manager
+            .check_employee_exists(&employee.first_name, &employee.last_name)
+    {
+        return Err(DataStoreError::EmployeeAlreadyExists {
+            first_name: employee.first_name,
+            last_name: employee.last_name,
+        }
+        .into());
+    }
+
+    let employee = state
+        .file_manager
+        .update_employee(&id, employee, expected_version, &admin)
+        .await?;
+    Ok((
+        [(header::ETAG, etag(employee.version))],
+        Json(EmployeeResponse {
+            message: format!("Employee {id:?} updated successfully"),
+            data: Projection::Admin.view(&employee),
+        }),
+    ))
+}
+
+// move the employee to the trash, `:restore` takes it back
+#[utoipa::path(
+    delete,
+    path = "/api/v2/employees/{id}",
+    tag = "v2",
+    security(("basic_auth" = [])),
+    params(("id" = String, Path, description = "Employee id")),
+    responses(
+        (status = 204, description = "Employee moved to the trash"),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "No such employee", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn delete_employee(
+    State(state): State<AppState>,
+    AdminAuth(admin): AdminAuth,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .file_manager
+        .archive_employee(&id, "Deleted through the API", &admin)
+        .await?;
+    info!("Employee {id} deleted by {admin}");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// `POST /api/v2/employees/{id}:{action}`, the actions that cannot be
+// expressed as a change of the personal data
+#[utoipa::path(
+    post,
+    path = "/api/v2/employees/{id}:{action}",
+    tag = "v2",
+    security(("basic_auth" = [])),
+    params(
+        ("id" = String, Path, description = "Employee id"),
+        ("action" = String, Path, description = "`onboard`, `reset-password` or `restore`"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the employee, or `*`; required by `onboard` and `reset-password`"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Retries sent with the same key get the first response again"),
+    ),
+    responses(
+        (status = 200, description = "Action done; `password` is only set by `onboard` and `reset-password`",
+            body = EmployeeCredentialsResponse,
+            headers(("ETag" = String, description = "New version of the employee"))),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "No such employee or action", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "Already onboarded, or not onboarded yet", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The employee changed meanwhile", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn employee_action(
+    State(state): State<AppState>,
+    AdminAuth(admin): AdminAuth,
+    Path(target): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let unknown_action = || {
+        ApiError::NotFound(format!(
+            "Unknown action in {target:?}, expected {{id}}:onboard, {{id}}:reset-password or {{id}}:restore"
+        ))
+    };
+    let (id, action) = target.rsplit_once(':').ok_or_else(unknown_action)?;
+    match action {
+        "onboard" => onboard(&state, &admin, id, &headers).await,
+        "reset-password" => reset_password(&state, &admin, id, &headers).await,
+        "restore" => restore(&state, &admin, id).await,
+        _ => Err(unknown_action()),
+    }
+}
+
+// give the employee a handle, a corporate email and a first password
+async fn onboard(
+    state: &AppState,
+    admin: &str,
+    id: &str,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    let expected_version = expected_version(headers)?;
+    let employee = state
+        .file_manager
+        .get_employee(id)
+        .ok_or_else(|| not_found(id))?;
+    if employee.onboarded == Some(true) {
+        return Err(DataStoreError::AlreadyOnboarded { id: id.to_string() }.into());
+    }
+
+    let password = generate_random_password().await;
+    let employee = state
+        .file_manager
+        .onboard_employee(employee, password.clone(), expected_version, admin)
+        .await?;
+    Ok(credentials_response(
+        "Employee onboarded successfully",
+        employee,
+        password,
+    ))
+}
+
+// replace the password with a generated one the employee has to secure again
+async fn reset_password(
+    state: &AppState,
+    admin: &str,
+    id: &str,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    let expected_version = expected_version(headers)?;
+    let employee = state
+        .file_manager
+        .get_employee(id)
+        .ok_or_else(|| not_found(id))?;
+    if employee.onboarded != Some(true) {
+        return Err(DataStoreError::NotOnboarded { id: id.to_string() }.into());
+    }
+
+    let password = generate_random_password().await;
+    let employee = Employee {
+        password: Some(password.clone()),
+        secure_password: Some(false),
+        ..employee
+    };
+    let employee = state
+        .file_manager
+        .update_employee(id, employee, expected_version, admin)
+        .await?;
+    Ok(credentials_response(
+        "Password reset successfully",
+        employee,
+        password,
+    ))
+}
+
+async fn restore(state: &AppState, admin: &str, id: &str) -> Result<Response, ApiError> {
+    let employee = state
+        .file_manager
+        .restore_archived_employee(id, admin)
+        .await?;
+    Ok((
+        [(header::ETAG, etag(employee.version))],
+        Json(EmployeeResponse {
+            message: format!("Employee {id:?} restored successfully"),
+            data: Projection::Admin.view(&employee),
+        }),
+    )
+        .into_response())
+}
+
+fn credentials_response(message: &str, employee: Employee, password: String) -> Response {
+    (
+        [(header::ETAG, etag(employee.version))],
+        Json(EmployeeCredentialsResponse {
+            message: message.to_string(),
+            data: Projection::Admin.view(&employee),
+            password,
+        }),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Method, Request},
+    };
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        testing::{ada, app, basic_auth, send},
+        utils::request_id::REQUEST_ID_HEADER,
+    };
+
+    #[tokio::test]
+    async fn test_create_get_update_and_delete() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+
+        let mut with_password = ada();
+        with_password["password"] = json!("chosen");
+        let (status, _, _) = send(
+            &app,
+            Method::POST,
+            EMPLOYEES_PATH,
+            None,
+            Some(with_password),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, headers, body) =
+            send(&app, Method::POST, EMPLOYEES_PATH, None, Some(ada())).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(headers[header::ETAG], "\"1\"");
+        let id = body["data"]["id"].as_str().unwrap().to_string();
+        let uri = headers[header::LOCATION].to_str().unwrap().to_string();
+        assert_eq!(uri, format!("{EMPLOYEES_PATH}/{id}"));
+
+        let (status, _, _) = send(&app, Method::POST, EMPLOYEES_PATH, None, Some(ada())).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _, body) = send(&app, Method::GET, EMPLOYEES_PATH, None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"], 1);
+
+        let patch = json!({"age": 37, "personal_email": null});
+        let (status, _, _) = send(&app, Method::PATCH, &uri, None, Some(patch.clone())).await;
+        assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+        let (status, headers, body) = send(
+            &app,
+            Method::PATCH,
+            &uri,
+            Some("\"1\""),
+            Some(patch.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::ETAG], "\"2\"");
+        assert_eq!(body["data"]["age"], 37);
+        assert_eq!(body["data"]["first_name"], "Ada");
+        let (status, _, _) = send(&app, Method::PATCH, &uri, Some("\"1\""), Some(patch)).await;
+        assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+
+        // credentials and required fields are out of reach of a patch
+        for patch in [
+            json!({"handle": "root"}),
+            json!({"first_name": null}),
+            json!({"age": 12}),
+        ] {
+            let (status, _, _) = send(&app, Method::PATCH, &uri, Some("*"), Some(patch)).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        let (status, _, _) = send(&app, Method::DELETE, &uri, None, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _, _) = send(&app, Method::GET, &uri, None, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _, body) =
+            send(&app, Method::POST, &format!("{uri}:restore"), None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["version"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_onboard_and_reset_password_actions() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let (_, headers, _) = send(&app, Method::POST, EMPLOYEES_PATH, None, Some(ada())).await;
+        let uri = headers[header::LOCATION].to_str().unwrap().to_string();
+
+        let (status, _, _) = send(
+            &app,
+            Method::POST,
+            &format!("{uri}:reset-password"),
+            Some("*"),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, headers, body) = send(
+            &app,
+            Method::POST,
+            &format!("{uri}:onboard"),
+            Some("\"1\""),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::ETAG], "\"2\"");
+        assert_eq!(body["data"]["handle"], "alovelace");
+        assert_eq!(body["data"]["avaya_email"], "alovelace@avaya.com");
+        assert_eq!(body["data"]["password"], Value::Null);
+        let first_password = body["password"].as_str().unwrap().to_string();
+
+        let (status, _, _) = send(
+            &app,
+            Method::POST,
+            &format!("{uri}:onboard"),
+            Some("*"),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _, body) = send(
+            &app,
+            Method::POST,
+            &format!("{uri}:reset-password"),
+            Some("\"2\""),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(body["password"], first_password);
+
+        let (status, _, _) = send(
+            &app,
+            Method::POST,
+            &format!("{uri}:promote"),
+            Some("*"),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _, body) = send(&app, Method::GET, &uri, None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["password"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_list_filters_sorts_and_pages() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        for (first_name, last_name, age) in [
+            ("Grace", "Hopper", 40),
+            ("Ada", "Lovelace", 36),
+            ("Alan", "Turing", 41),
+            ("Edsger", "Dijkstra", 40),
+            ("Barbara", "Liskov", 35),
+        ] {
+            let employee = json!({
+                "first_name": first_name, "last_name": last_name, "age": age, "diploma": "PhD"
+            });
+            send(&app, Method::POST, EMPLOYEES_PATH, None, Some(employee)).await;
+        }
+        let (_, _, body) = send(
+            &app,
+            Method::GET,
+            &format!("{EMPLOYEES_PATH}?q=turing"),
+            None,
+            None,
+        )
+        .await;
+        let turing = body["employees"][0]["id"].as_str().unwrap().to_string();
+        send(
+            &app,
+            Method::POST,
+            &format!("{EMPLOYEES_PATH}/{turing}:onboard"),
+            Some("*"),
+            None,
+        )
+        .await;
+        let names = |body: &Value| -> Vec<String> {
+            body["employees"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|employee| employee["first_name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let (status, _, body) = send(&app, Method::GET, EMPLOYEES_PATH, None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(names(&body), ["Ada", "Alan", "Barbara", "Edsger", "Grace"]);
+        assert_eq!(body["total"], 5);
+        assert_eq!(body["page"], 1);
+
+        let uri = format!("{EMPLOYEES_PATH}?onboarded=false&sort=-age,last_name&per_page=2");
+        let (_, headers, body) = send(&app, Method::GET, &uri, None, None).await;
+        assert_eq!(names(&body), ["Edsger", "Grace"]);
+        assert_eq!(
+            (body["total"].as_u64(), body["results"].as_u64()),
+            (Some(4), Some(2))
+        );
+        let link = headers[header::LINK].to_str().unwrap();
+        let next = format!("<{EMPLOYEES_PATH}?onboarded=false&sort=-age,last_name&per_page=2&page=2>; rel=\"next\"");
+        assert!(link.contains(&next), "{link}");
+        assert!(!link.contains("rel=\"prev\""));
+
+        let (_, headers, body) =
+            send(&app, Method::GET, &format!("{uri}&page=2"), None, None).await;
+        assert_eq!(names(&body), ["Ada", "Barbara"]);
+        assert!(headers[header::LINK]
+            .to_str()
+            .unwrap()
+            .contains("rel=\"prev\""));
+        let (_, _, body) = send(&app, Method::GET, &format!("{uri}&page=3"), None, None).await;
+        assert_eq!(body["results"], 0);
+
+        // the creation order
+        let (_, _, body) = send(
+            &app,
+            Method::GET,
+            &format!("{EMPLOYEES_PATH}?sort=created_at&onboarded=true&secure_password=false"),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(names(&body), ["Alan"]);
+        let (_, _, body) = send(
+            &app,
+            Method::GET,
+            &format!("{EMPLOYEES_PATH}?sort=-created_at"),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(names(&body), ["Barbara", "Edsger", "Alan", "Ada", "Grace"]);
+
+        for query in ["page=0", "per_page=1000", "sort=salary", "onboarded=maybe"] {
+            let (status, _, body) = send(
+                &app,
+                Method::GET,
+                &format!("{EMPLOYEES_PATH}?{query}"),
+                None,
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+            assert_eq!(body["code"], "bad_request");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cursor_pages_and_change_feed() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let create = |first_name: &str| json!({"first_name": first_name, "last_name": "Doe", "age": 30, "diploma": "PhD"});
+        for first_name in ["Bob", "Dan", "Eve"] {
+            send(
+                &app,
+                Method::POST,
+                EMPLOYEES_PATH,
+                None,
+                Some(create(first_name)),
+            )
+            .await;
+        }
+        let (_, _, body) = send(
+            &app,
+            Method::GET,
+            &format!("{EMPLOYEES_PATH}/changes"),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(body["results"], 3);
+        let since = body["next_cursor"].as_str().unwrap().to_string();
+
+        let uri = format!("{EMPLOYEES_PATH}?per_page=2");
+        let (_, _, body) = send(&app, Method::GET, &uri, None, None).await;
+        assert_eq!(body["employees"][1]["first_name"], "Dan");
+        let cursor = body["next_cursor"].as_str().unwrap().to_string();
+
+        // an employee added before the position of the cursor shifts nothing
+        let (_, headers, _) = send(
+            &app,
+            Method::POST,
+            EMPLOYEES_PATH,
+            None,
+            Some(create("Ann")),
+        )
+        .await;
+        let ann = headers[header::LOCATION].to_str().unwrap().to_string();
+        let (status, headers, body) = send(
+            &app,
+            Method::GET,
+            &format!("{uri}&cursor={cursor}"),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"], 1);
+        assert_eq!(body["employees"][0]["first_name"], "Eve");
+        assert_eq!(body["total"], 4);
+        assert_eq!(body["page"], Value::Null);
+        assert_eq!(body["next_cursor"], Value::Null);
+        assert!(!headers[header::LINK]
+            .to_str()
+            .unwrap()
+            .contains("rel=\"next\""));
+
+        for query in [
+            format!("cursor={cursor}&page=2"),
+            format!("cursor={cursor}&sort=-age"),
+            "cursor=bogus".to_string(),
+        ] {
+            let (status, _, _) = send(
+                &app,
+                Method::GET,
+                &format!("{EMPLOYEES_PATH}?{query}"),
+                None,
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        }
+
+        // Ann is created then deleted, Bob updated
+        let (_, _, body) = send(
+            &app,
+            Method::GET,
+            &format!("{EMPLOYEES_PATH}?q=bob"),
+            None,
+            None,
+        )
+        .await;
+        let bob = format!(
+            "{EMPLOYEES_PATH}/{}",
+            body["employees"][0]["id"].as_str().unwrap()
+        );
+        send(
+            &app,
+            Method::PATCH,
+            &bob,
+            Some("*"),
+            Some(json!({"age": 31})),
+        )
+        .await;
+        send(&app, Method::DELETE, &ann, None, None).await;
+
+        let feed = format!("{EMPLOYEES_PATH}/changes?since={since}&limit=1");
+        let (status, headers, body) = send(&app, Method::GET, &feed, None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["changes"][0]["change"], "updated");
+        assert_eq!(body["changes"][0]["employee"]["age"], 31);
+        assert_eq!(body["changes"][0]["employee"]["password"], Value::Null);
+        let next = body["next_cursor"].as_str().unwrap();
+        assert!(headers[header::LINK].to_str().unwrap().contains(next));
+
+        let feed = format!("{EMPLOYEES_PATH}/changes?since={next}");
+        let (_, headers, body) = send(&app, Method::GET, &feed, None, None).await;
+        assert_eq!(body["results"], 1);
+        assert_eq!(body["changes"][0]["change"], "deleted");
+        assert_eq!(body["changes"][0]["id"], ann.rsplit('/').next().unwrap());
+        assert_eq!(body["changes"][0]["employee"], Value::Null);
+        assert!(headers.get(header::LINK).is_none());
+
+        // nothing new, the cursor stays put
+        let next = body["next_cursor"].as_str().unwrap();
+        let feed = format!("{EMPLOYEES_PATH}/changes?since={next}");
+        let (_, _, body) = send(&app, Method::GET, &feed, None, None).await;
+        assert_eq!(body["results"], 0);
+        assert_eq!(body["next_cursor"], next);
+        let (status, _, _) = send(
+            &app,
+            Method::GET,
+            &format!("{EMPLOYEES_PATH}/changes?since={cursor}"),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_problem_documents() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let request = Request::get(EMPLOYEES_PATH)
+            .header(header::AUTHORIZATION, basic_auth("admin:wrong"))
+            .header(REQUEST_ID_HEADER, "trace-42")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "trace-42");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let problem: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["status"], 401);
+        assert_eq!(problem["title"], "Unauthorized");
+        assert_eq!(problem["code"], "invalid_credentials");
+        assert_eq!(problem["request_id"], "trace-42");
+
+        send(&app, Method::POST, EMPLOYEES_PATH, None, Some(ada())).await;
+        let (status, headers, problem) =
+            send(&app, Method::POST, EMPLOYEES_PATH, None, Some(ada())).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(problem["code"], "employee_already_exists");
+        // a request id is generated when the client sends none
+        assert_eq!(
+            problem["request_id"],
+            headers[REQUEST_ID_HEADER].to_str().unwrap()
+        );
+
+        let (status, _, problem) = send(
+            &app,
+            Method::POST,
+            EMPLOYEES_PATH,
+            None,
+            Some(json!({"first_name": "Ada"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem["code"], "validation_failed");
+
+        let (status, _, problem) = send(
+            &app,
+            Method::PATCH,
+            &format!("{EMPLOYEES_PATH}/missing"),
+            None,
+            Some(json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+        assert_eq!(problem["code"], "if_match_required");
+    }
+}
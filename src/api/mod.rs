@@ -0,0 +1,41 @@
+// REST /api/v2: one resource per collection, strict input schemas and
+// status codes that follow the HTTP semantics
+pub mod employees;
+pub mod openapi;
+
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use axum_auth::AuthBasic;
+
+use crate::utils::{
+    errors::ApiError,
+    password_utils::{is_hashed_password, verify_hashed_password},
+    state::AppState,
+};
+
+// Id of the admin whose basic auth credentials came with the request, the
+// request is rejected with 401 otherwise.
+pub struct AdminAuth(pub String);
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminAuth {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, ApiError> {
+        let AuthBasic((id, password)) = AuthBasic::from_request_parts(parts, state)
+            .await
+            .map_err(|_| ApiError::Unauthorized)?;
+        let hashed_password = state
+            .file_manager
+            .get_admin_by_id(&id)
+            .and_then(|admin| admin.password)
+            // clear text passwords of older releases are hashed by `data migrate`
+            .filter(|hashed_password| is_hashed_password(hashed_password));
+
+        if let (Some(hashed_password), Some(password)) = (hashed_password, password) {
+            if verify_hashed_password(password, hashed_password).await {
+                return Ok(AdminAuth(id));
+            }
+        }
+        Err(ApiError::Unauthorized)
+    }
+}
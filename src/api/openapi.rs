@@ -0,0 +1,254 @@
+use axum::Json;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+use crate::{api::employees, handlers};
+
+pub const OPENAPI_PATH: &str = "/api/openapi.json";
+pub const DOCS_PATH: &str = "/api/docs";
+
+// OpenAPI 3 document of the REST API, generated from the handlers and the
+// models they exchange
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "red_carpet",
+        description = "Onboarding of new employees. Errors are `application/problem+json` documents."
+    ),
+    paths(
+        handlers::health_checker,
+        handlers::create_employee,
+        handlers::employees_list,
+        handlers::employees_changes,
+        handlers::import_employees,
+        handlers::onboard_employees,
+        handlers::export_employees,
+        handlers::get_employee,
+        handlers::generate_handle_and_password,
+        handlers::update_employee_by_id,
+        handlers::get_employee_history,
+        handlers::restore_employee_revision,
+        handlers::create_backup,
+        handlers::backups_list,
+        employees::create_employee,
+        employees::list_employees,
+        employees::list_changes,
+        employees::get_employee,
+        employees::update_employee,
+        employees::delete_employee,
+        employees::employee_action,
+    ),
+    modifiers(&BasicAuth),
+    tags(
+        (name = "v1", description = "First API, kept for the existing integrations"),
+        (name = "v2", description = "Employees resource with strict schemas and actions"),
+    )
+)]
+pub struct ApiDoc;
+
+struct BasicAuth;
+
+impl Modify for BasicAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "basic_auth",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Basic).build()),
+        );
+    }
+}
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+// Swagger UI, bundled in the binary; the document is fetched relative to the
+// page so it also works under the prefix of an embedding application
+pub fn swagger_ui() -> SwaggerUi {
+    SwaggerUi::new(DOCS_PATH).config(Config::new(["../openapi.json"]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{header, Method, Request, StatusCode},
+    };
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use tempfile::TempDir;
+    use tokio::sync::Mutex;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        config::Config,
+        database::{
+            file_manager::FileManager,
+            persistence::{create_persistence_store, DataPaths},
+        },
+        routes::{define_routes, load_templates},
+        utils::state::AppState,
+    };
+
+    const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+    // path with every parameter replaced by `{}`, `:id` in axum and `{id}`
+    // in OpenAPI; the rest of a segment from a parameter on is one axum
+    // parameter, e.g. `{id}:{action}` or the `:action` of `employees:import`
+    fn normalize(path: &str) -> String {
+        path.split('/')
+            .map(|segment| match segment.find([':', '{']) {
+                Some(at) => format!("{}{{}}", &segment[..at]),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    // (method, path) of every API route declared in routes.rs
+    fn declared_routes() -> BTreeSet<(String, String)> {
+        let source = include_str!("../routes.rs");
+        let mut routes = BTreeSet::new();
+        for call in source.split(".route(").skip(1) {
+            let Some(rest) = call.trim_start().strip_prefix('"') else {
+                continue;
+            };
+            let (path, rest) = rest.split_once('"').unwrap();
+            if !path.starts_with("/api/") || path == OPENAPI_PATH {
+                continue;
+            }
+            // the method router, up to the end of the `.route(` call
+            let mut depth = 1;
+            let end = rest
+                .char_indices()
+                .find(|(_, c)| {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    depth == 0
+                })
+                .unwrap()
+                .0;
+            let methods = &rest[..end];
+            for method in METHODS {
+                let declared = methods.match_indices(&format!("{method}(")).any(|(at, _)| {
+                    !methods[..at].ends_with(|c: char| c.is_alphanumeric() || c == '_')
+                });
+                if declared {
+                    routes.insert((method.to_string(), normalize(path)));
+                }
+            }
+        }
+        routes
+    }
+
+    fn documented_operations() -> Vec<(String, String)> {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let mut operations = vec![];
+        let mut operation_ids = BTreeSet::new();
+        for (path, item) in spec["paths"].as_object().unwrap() {
+            for method in METHODS {
+                if let Some(operation) = item.get(method) {
+                    let operation_id = operation["operationId"].as_str().unwrap();
+                    assert!(
+                        operation_ids.insert(operation_id.to_string()),
+                        "operationId {operation_id} is not unique"
+                    );
+                    operations.push((method.to_string(), path.clone()));
+                }
+            }
+        }
+        operations
+    }
+
+    async fn app(dir: &TempDir) -> axum::Router {
+        let paths = DataPaths::new(dir.path());
+        create_persistence_store(&paths).unwrap();
+        let state = AppState {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            file_manager: Arc::new(FileManager::new(&paths, None).unwrap()),
+            config: Arc::new(Config::default()),
+        };
+        define_routes(state, load_templates(vec![], "").unwrap())
+    }
+
+    #[test]
+    fn test_spec_matches_routes() {
+        let documented: BTreeSet<(String, String)> = documented_operations()
+            .into_iter()
+            .map(|(method, path)| (method, normalize(&path)))
+            .collect();
+        let declared = declared_routes();
+        assert!(declared.len() > 10, "{declared:?}");
+        assert_eq!(
+            declared.difference(&documented).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "routes missing from the OpenAPI document"
+        );
+        assert_eq!(
+            documented.difference(&declared).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "documented operations without a route"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_documented_operations_are_served() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        for (method, path) in documented_operations() {
+            let uri = path.replace("{revision}", "1").replace(['{', '}'], "");
+            let request = Request::builder()
+                .method(Method::from_bytes(method.to_uppercase().as_bytes()).unwrap())
+                .uri(&uri)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            // the router answers an unknown route with an empty 404 and an
+            // unknown method with 405; the handlers with a problem document
+            let routed = match response.status() {
+                StatusCode::METHOD_NOT_ALLOWED => false,
+                StatusCode::NOT_FOUND => {
+                    response.headers().get(header::CONTENT_TYPE)
+                        == Some(&"application/problem+json".parse().unwrap())
+                }
+                _ => true,
+            };
+            assert!(routed, "{method} {uri}: {}", response.status());
+        }
+
+        let response = app
+            .clone()
+            .oneshot(Request::get(OPENAPI_PATH).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let spec: Value = serde_json::from_slice(&body).unwrap();
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+        assert!(spec["components"]["schemas"]["EmployeeRequestBody"].is_object());
+        // responses document the projection, which has no password
+        let view = &spec["components"]["schemas"]["EmployeeView"];
+        assert!(view["properties"]["handle"].is_object());
+        assert!(view["properties"]["password"].is_null());
+
+        let response = app
+            .oneshot(
+                Request::get(format!("{DOCS_PATH}/"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
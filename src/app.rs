@@ -0,0 +1,432 @@
+use std::{collections::HashMap, io, path::Path, sync::Arc};
+
+use axum::Router;
+use axum_server::Handle;
+use log::{error, info};
+use tokio::{
+    sync::Mutex,
+    task::{JoinHandle, JoinSet},
+};
+
+use crate::{
+    config::Config,
+    database::{
+        backup::spawn_backup_job,
+        file_manager::FileManager,
+        persistence::{create_admin, load_sessions, open_file_manager, save_sessions},
+        retention::spawn_purge_job,
+    },
+    listeners::{bind_listeners, serve, Listener},
+    models::admin_models::Admin,
+    routes::{define_routes, load_templates},
+    tls::{load_tls, redirect_router, spawn_reload_job, with_hsts},
+    utils::{errors::BuildError, password_utils::validate_token_expiration, state::AppState},
+};
+
+// Entry point to embed Red Carpet in another axum application:
+//
+//     let onboarding = RedCarpet::builder().config(config).base_path("/onboarding").build().await?;
+//     let app = Router::new().nest("/onboarding", onboarding);
+pub struct RedCarpet;
+
+impl RedCarpet {
+    pub fn builder() -> RedCarpetBuilder {
+        RedCarpetBuilder::default()
+    }
+
+    // build the application from `config` and serve it on the configured
+    // listeners, over HTTPS when a certificate is configured, until SIGINT or
+    // SIGTERM; in-flight requests are given the configured drain timeout
+    // before the data store is closed
+    pub async fn serve(config: Config) -> Result<(), BuildError> {
+        let app = RedCarpet::builder()
+            .config(config.clone())
+            .build_app()
+            .await?;
+        match config.tls.files() {
+            Some((cert, key)) => serve_https(&app, &config, cert, key).await?,
+            None => serve_http(&app, &config).await?,
+        }
+        app.shutdown().await;
+        Ok(())
+    }
+}
+
+async fn serve_http(app: &RedCarpetApp, config: &Config) -> Result<(), BuildError> {
+    let drain_timeout = config.server.shutdown_timeout();
+    let listeners = bind_listeners(&config.server).await?;
+    let shutdown = async move {
+        let signal = shutdown_signal().await;
+        info!("Received {signal}, draining in-flight requests for up to {drain_timeout:?}");
+    };
+    serve(listeners, app.router(), shutdown, drain_timeout).await;
+    Ok(())
+}
+
+async fn serve_https(
+    app: &RedCarpetApp,
+    config: &Config,
+    cert: &Path,
+    key: &Path,
+) -> Result<(), BuildError> {
+    let drain_timeout = config.server.shutdown_timeout();
+    let tls = load_tls(cert, key).await?;
+    let mut listeners = vec![];
+    for listener in bind_listeners(&config.server).await? {
+        // unix sockets are rejected with TLS by the config validation, a
+        // socket inherited from systemd can still be one
+        let Listener::Tcp(listener) = listener else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "HTTPS is only served on TCP sockets",
+            )
+            .into());
+        };
+        listeners.push(listener.into_std()?);
+    }
+    // redirects go to the port of the first listener
+    let https_port = match listeners.first() {
+        Some(listener) => listener.local_addr()?.port(),
+        None => 443,
+    };
+    let reload_job = spawn_reload_job(tls.clone(), cert.to_path_buf(), key.to_path_buf());
+
+    let handle = Handle::new();
+    let redirect_handle = Handle::new();
+    if let Some(redirect_bind) = config.tls.redirect_bind {
+        info!("Redirecting http://{redirect_bind} to HTTPS");
+        let server = axum_server::bind(redirect_bind)
+            .handle(redirect_handle.clone())
+            .serve(redirect_router(https_port).into_make_service());
+        tokio::spawn(async move {
+            if let Err(error) = server.await {
+                error!("HTTPS redirect listener failed: {error}");
+            }
+        });
+    }
+
+    let mut servers = JoinSet::new();
+    for listener in listeners {
+        info!("listening on: https://{}", listener.local_addr()?);
+        servers.spawn(
+            axum_server::from_tcp_rustls(listener, tls.clone())
+                .handle(handle.clone())
+                .serve(app.router().into_make_service()),
+        );
+    }
+
+    let shutdown_handle = handle.clone();
+    tokio::spawn(async move {
+        let signal = shutdown_signal().await;
+        info!("Received {signal}, draining in-flight requests for up to {drain_timeout:?}");
+        redirect_handle.shutdown();
+        shutdown_handle.graceful_shutdown(Some(drain_timeout));
+    });
+
+    let mut result = Ok(());
+    while let Some(served) = servers.join_next().await {
+        if let Ok(Err(error)) = served {
+            error!("HTTPS listener failed: {error}");
+            result = Err(error);
+            handle.shutdown();
+        }
+    }
+    reload_job.abort();
+    result?;
+    info!("HTTPS server stopped");
+    Ok(())
+}
+
+// resolves with the name of the first shutdown signal received
+async fn shutdown_signal() -> &'static str {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("unable to listen for SIGINT");
+        "SIGINT"
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("unable to listen for SIGTERM")
+            .recv()
+            .await;
+        "SIGTERM"
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<&'static str>();
+
+    tokio::select! {
+        signal = interrupt => signal,
+        signal = terminate => signal,
+    }
+}
+
+// A built application: its routes, plus what `shutdown` needs to stop it
+// cleanly. Hosts embedding the router call `shutdown` once they stopped
+// serving it.
+pub struct RedCarpetApp {
+    router: Router,
+    state: AppState,
+    jobs: Vec<JoinHandle<()>>,
+}
+
+impl RedCarpetApp {
+    pub fn router(&self) -> Router {
+        self.router.clone()
+    }
+
+    // finish the write or backup in progress, stop the background jobs and
+    // save the sessions when they are persisted
+    pub async fn shutdown(self) {
+        info!("Shutting down");
+        if let Err(error) = self.state.file_manager.close().await {
+            error!("Unable to flush the data store: {error}");
+        }
+        for job in &self.jobs {
+            job.abort();
+        }
+        info!("Stopped {} background jobs", self.jobs.len());
+
+        let config = &self.state.config;
+        if config.session.persist && !self.state.file_manager.is_read_only() {
+            let sessions = self.state.sessions.lock().await;
+            match save_sessions(&config.data_paths().sessions_file, &sessions) {
+                Ok(()) => info!("Saved {} sessions", sessions.len()),
+                Err(error) => error!("Unable to save the sessions: {error}"),
+            }
+        }
+        info!("Shutdown complete");
+    }
+}
+
+pub struct RedCarpetBuilder {
+    config: Config,
+    file_manager: Option<Arc<FileManager>>,
+    templates: Vec<(String, String)>,
+    base_path: String,
+    background_jobs: bool,
+}
+
+impl Default for RedCarpetBuilder {
+    fn default() -> Self {
+        RedCarpetBuilder {
+            config: Config::default(),
+            file_manager: None,
+            templates: vec![],
+            base_path: String::new(),
+            background_jobs: true,
+        }
+    }
+}
+
+impl RedCarpetBuilder {
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    // use an already open data store instead of opening the data directory
+    // of the config
+    pub fn file_manager(mut self, file_manager: Arc<FileManager>) -> Self {
+        self.file_manager = Some(file_manager);
+        self
+    }
+
+    // replace a built-in template, e.g. `base.html` to match the look of the
+    // hosting application
+    pub fn template(mut self, name: impl Into<String>, content: impl Into<String>) -> Self {
+        self.templates.push((name.into(), content.into()));
+        self
+    }
+
+    // path the router is nested under, prefixed to the links of the pages
+    pub fn base_path(mut self, base_path: impl Into<String>) -> Self {
+        self.base_path = base_path.into();
+        self
+    }
+
+    // purge the trash and take backups on schedule, enabled by default
+    pub fn background_jobs(mut self, enabled: bool) -> Self {
+        self.background_jobs = enabled;
+        self
+    }
+
+    // routes of the application, for hosts that do not need `shutdown`
+    pub async fn build(self) -> Result<Router, BuildError> {
+        Ok(self.build_app().await?.router)
+    }
+
+    // open the data store unless one was given, seed the admin, start the
+    // background jobs and restore the persisted sessions
+    pub async fn build_app(self) -> Result<RedCarpetApp, BuildError> {
+        let config = Arc::new(self.config);
+        config.validate()?;
+
+        let file_manager = match self.file_manager {
+            Some(file_manager) => file_manager,
+            None => Arc::new(open_file_manager(
+                &config.data_paths(),
+                config.data.read_only,
+            )?),
+        };
+
+        let mut jobs = vec![];
+        // a read-only instance can inspect the data of a running server
+        if !file_manager.is_read_only() {
+            let admin = Admin {
+                id: config.admin.id.clone(),
+                password: Some(config.admin.password.clone()),
+            };
+            if create_admin(&file_manager, admin).await? {
+                info!("Admin created successfully");
+            } else {
+                info!("Admin already exists");
+            }
+
+            if self.background_jobs {
+                jobs.push(spawn_purge_job(
+                    file_manager.clone(),
+                    config.trash.retention_days,
+                ));
+                jobs.extend(spawn_backup_job(
+                    file_manager.clone(),
+                    config.backup_settings(),
+                ));
+            }
+        }
+
+        let mut sessions = HashMap::new();
+        if config.session.persist {
+            let lifetime = config.session.lifetime();
+            for (key, token) in load_sessions(&config.data_paths().sessions_file)? {
+                // sessions that expired while the server was stopped are dropped
+                if validate_token_expiration(token.clone(), lifetime).await {
+                    sessions.insert(key, token);
+                }
+            }
+            info!("Restored {} sessions", sessions.len());
+        }
+
+        let tera = load_templates(self.templates, &self.base_path)?;
+        let state = AppState {
+            sessions: Arc::new(Mutex::new(sessions)),
+            file_manager,
+            config,
+        };
+        let mut router = define_routes(state.clone(), tera);
+        if state.config.tls.files().is_some() && state.config.tls.hsts_max_age_secs > 0 {
+            router = with_hsts(router, state.config.tls.hsts_max_age_secs);
+        }
+        Ok(RedCarpetApp {
+            router,
+            state,
+            jobs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use http_body_util::BodyExt;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::utils::password_utils::generate_session_token;
+
+    async fn get(app: &Router, uri: &str) -> (StatusCode, String) {
+        let response = app
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_router_nested_with_template_override() {
+        let dir = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.data.dir = dir.path().to_path_buf();
+
+        let onboarding = RedCarpet::builder()
+            .config(config)
+            .base_path("/onboarding")
+            .template("index.html", "custom home page")
+            .background_jobs(false)
+            .build()
+            .await
+            .unwrap();
+        let app = Router::new().nest("/onboarding", onboarding);
+
+        let (status, body) = get(&app, "/onboarding/login").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(r#"action="/onboarding/employee/login""#));
+        assert!(body.contains(r#"href="/onboarding/styles.css""#));
+        assert!(body.contains(r#"href="/onboarding""#));
+
+        let (status, body) = get(&app, "/onboarding").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "custom home page");
+
+        let (status, _) = get(&app, "/login").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_store_and_persists_sessions() {
+        let dir = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.data.dir = dir.path().to_path_buf();
+        config.session.persist = true;
+
+        let app = RedCarpet::builder()
+            .config(config.clone())
+            .background_jobs(false)
+            .build_app()
+            .await
+            .unwrap();
+        let token = generate_session_token("jdoe".to_string()).await;
+        app.state
+            .sessions
+            .lock()
+            .await
+            .insert("jdoe".to_string(), token.clone());
+        let file_manager = app.state.file_manager.clone();
+        app.shutdown().await;
+
+        let result = file_manager
+            .add_admin(Admin {
+                id: "late".to_string(),
+                password: None,
+            })
+            .await;
+        assert!(result.is_err());
+
+        // the data directory lock is released with the last reference
+        drop(file_manager);
+        let app = RedCarpet::builder()
+            .config(config)
+            .background_jobs(false)
+            .build_app()
+            .await
+            .unwrap();
+        let sessions = app.state.sessions.lock().await;
+        assert_eq!(sessions.get("jdoe"), Some(&token));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_config_is_rejected() {
+        let mut config = Config::default();
+        config.session.lifetime_secs = 0;
+        let result = RedCarpet::builder().config(config).build().await;
+        assert!(matches!(result, Err(BuildError::Config(_))));
+    }
+}
@@ -0,0 +1,144 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+
+use crate::config::{Config, Listen, CONFIG_ENV, DEFAULT_CONFIG_FILE};
+
+/// Avaya Red Carpet onboarding server
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// TOML config file, `red_carpet.toml` is read when present
+    #[arg(long, env = CONFIG_ENV)]
+    pub config: Option<PathBuf>,
+
+    /// Address the server listens on
+    #[arg(long)]
+    pub bind: Option<SocketAddr>,
+
+    /// Address to listen on, `host:port`, `unix:PATH` or `systemd`; repeat
+    /// it to listen on several addresses, replaces --bind
+    #[arg(long)]
+    pub listen: Vec<Listen>,
+
+    /// Directory holding the data files
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+
+    /// Open the data without locking it and refuse every change
+    #[arg(long)]
+    pub read_only: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Run the web server, the default when no command is given
+    Serve,
+    /// Manage the administrators
+    #[command(subcommand)]
+    Admin(AdminCommand),
+    /// Import and export employees
+    #[command(subcommand)]
+    Employees(EmployeesCommand),
+    /// Check and maintain the data files
+    #[command(subcommand)]
+    Data(DataCommand),
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum AdminCommand {
+    /// Create an administrator
+    Create {
+        /// Administrator id, used to log in
+        id: String,
+        /// Password of the administrator, generated and printed when omitted
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Replace the password of an administrator
+    ResetPassword {
+        /// Administrator id
+        id: String,
+        /// New password, generated and printed when omitted
+        #[arg(long)]
+        password: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum EmployeesCommand {
+    /// Add the employees of a JSON array, in the format of the create
+    /// employee API; nothing is imported unless every employee is valid
+    Import {
+        /// JSON file to import, `-` reads standard input
+        file: PathBuf,
+    },
+    /// Write the employees as a JSON array, without their passwords
+    Export {
+        /// File to write, standard output when omitted
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Also export the employees in the trash
+        #[arg(long)]
+        include_archived: bool,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum DataCommand {
+    /// Check the consistency of the data files, fails when a problem is found
+    Verify,
+    /// Rewrite the data files written by an older release in the current format
+    Migrate,
+    /// Encrypt every record again with a new data key, and with the new
+    /// master key when one is configured
+    Reencrypt,
+    /// Verify a backup and restore it in place of the current data
+    Restore {
+        /// Backup file to restore
+        backup: PathBuf,
+    },
+}
+
+impl Command {
+    // commands that only read the data can run next to the server
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Command::Employees(EmployeesCommand::Export { .. })
+                | Command::Data(DataCommand::Verify)
+        )
+    }
+}
+
+impl Cli {
+    // an explicit config file must exist, the default one is optional
+    pub fn config_file(&self) -> Option<PathBuf> {
+        match &self.config {
+            Some(path) => Some(path.clone()),
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_FILE);
+                path.exists().then_some(path)
+            }
+        }
+    }
+
+    // flags given on the command line take precedence over every other layer
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(bind) = self.bind {
+            config.server.bind = bind;
+        }
+        if !self.listen.is_empty() {
+            config.server.listen = self.listen.clone();
+        }
+        if let Some(data_dir) = &self.data_dir {
+            config.data.dir = data_dir.clone();
+        }
+        if self.read_only {
+            config.data.read_only = true;
+        }
+    }
+}
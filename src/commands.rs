@@ -0,0 +1,227 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use log::info;
+use uuid::Uuid;
+
+use crate::{
+    cli::{AdminCommand, Command, DataCommand, EmployeesCommand},
+    config::Config,
+    database::{
+        backup::restore_backup,
+        encryption::MasterKey,
+        file_manager::FileManager,
+        persistence::{
+            create_admin, migrate_admin_passwords, open_file_manager, reset_admin_password,
+        },
+    },
+    models::{
+        admin_models::Admin,
+        employee_models::{Employee, EmployeeRequestBody},
+    },
+    utils::{errors::DataStoreError, password_utils::generate_random_password},
+};
+
+// actor recorded in the employee history for changes made from the command line
+const CLI_ACTOR: &str = "cli";
+
+// Administrative commands run against the data files, without the server.
+// Results go to standard output so runbooks can capture them.
+pub async fn run(command: Command, config: &Config) -> Result<(), DataStoreError> {
+    // the server must be stopped, restoring takes the data directory lock
+    if let Command::Data(DataCommand::Restore { backup }) = &command {
+        let backup_dir = config.backup_settings().dir;
+        let files = restore_backup(backup, &config.data.dir, &backup_dir)?;
+        println!("Restored {files:?} from backup {backup:?}");
+        return Ok(());
+    }
+
+    let read_only = config.data.read_only || command.is_read_only();
+    let file_manager = open_file_manager(&config.data_paths(), read_only)?;
+    match command {
+        Command::Admin(command) => run_admin(&file_manager, command).await,
+        Command::Employees(command) => run_employees(&file_manager, command).await,
+        Command::Data(command) => run_data(&file_manager, command).await,
+        Command::Serve => unreachable!("the server is started by main"),
+    }
+}
+
+async fn run_admin(
+    file_manager: &FileManager,
+    command: AdminCommand,
+) -> Result<(), DataStoreError> {
+    match command {
+        AdminCommand::Create { id, password } => {
+            let (password, generated) = password_or_generated(password).await;
+            let admin = Admin {
+                id: id.clone(),
+                password: Some(password.clone()),
+            };
+            if !create_admin(file_manager, admin).await? {
+                return Err(DataStoreError::AdminAlreadyExists { id });
+            }
+            println!("Admin {id} created");
+            if generated {
+                println!("Password: {password}");
+            }
+        }
+        AdminCommand::ResetPassword { id, password } => {
+            let (password, generated) = password_or_generated(password).await;
+            if !reset_admin_password(file_manager, &id, password.clone()).await? {
+                return Err(DataStoreError::AdminNotFound { id });
+            }
+            println!("Password of admin {id} reset");
+            if generated {
+                println!("Password: {password}");
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn password_or_generated(password: Option<String>) -> (String, bool) {
+    match password {
+        Some(password) => (password, false),
+        None => (generate_random_password().await, true),
+    }
+}
+
+async fn run_employees(
+    file_manager: &FileManager,
+    command: EmployeesCommand,
+) -> Result<(), DataStoreError> {
+    match command {
+        EmployeesCommand::Import { file } => {
+            let employees = read_import(&file)?;
+            let imported = file_manager.import_employees(employees, CLI_ACTOR).await?;
+            println!("Imported {} employees", imported.len());
+        }
+        EmployeesCommand::Export {
+            output,
+            include_archived,
+        } => {
+            let mut employees = file_manager.list_employees();
+            if include_archived {
+                employees.extend(file_manager.list_archived_employees());
+            }
+            for employee in &mut employees {
+                employee.password = None;
+            }
+            let content = serde_json::to_string_pretty(&employees)?;
+            match output {
+                Some(output) => {
+                    fs::write(&output, content)?;
+                    info!("Exported {} employees to {:?}", employees.len(), output);
+                }
+                None => println!("{content}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+// employees to import, checked with the same rules as the web form
+fn read_import(file: &Path) -> Result<Vec<Employee>, DataStoreError> {
+    let mut content = String::new();
+    if file == Path::new("-") {
+        io::stdin().read_to_string(&mut content)?;
+    } else {
+        content = fs::read_to_string(file)?;
+    }
+    let bodies: Vec<EmployeeRequestBody> = serde_json::from_str(&content)?;
+
+    bodies
+        .into_iter()
+        .map(|body| {
+            let employee = Employee {
+                id: Some(Uuid::new_v4().to_string()),
+                first_name: body.first_name,
+                last_name: body.last_name,
+                personal_email: body.personal_email,
+                avaya_email: None,
+                age: body.age,
+                diploma: body.diploma,
+                onboarded: Some(false),
+                handle: None,
+                password: None,
+                secure_password: Some(false),
+                version: 0,
+                archived: None,
+            };
+            employee.validate()?;
+            Ok(employee)
+        })
+        .collect()
+}
+
+async fn run_data(file_manager: &FileManager, command: DataCommand) -> Result<(), DataStoreError> {
+    match command {
+        DataCommand::Verify => {
+            let problems = file_manager.verify()?;
+            if problems.is_empty() {
+                println!("Data is consistent");
+            } else {
+                for problem in &problems {
+                    println!("{problem}");
+                }
+                return Err(DataStoreError::Inconsistent {
+                    problems: problems.len(),
+                });
+            }
+        }
+        DataCommand::Migrate => {
+            let employees = file_manager.migrate().await?;
+            let admins = migrate_admin_passwords(file_manager).await?;
+            println!(
+                "Migrated {} employees and {} admins",
+                employees.len(),
+                admins.len()
+            );
+        }
+        // rotates the data key, and the master key when a new one is configured
+        DataCommand::Reencrypt => {
+            let new_master_key = MasterKey::new_from_env()?;
+            file_manager.reencrypt(new_master_key).await?;
+            println!("Employees re-encrypted successfully");
+        }
+        DataCommand::Restore { .. } => unreachable!("restored before opening the data"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_import_is_validated() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("employees.json");
+        fs::write(
+            &file,
+            r#"[
+                {"first_name": "Ada", "last_name": "Lovelace", "personal_email": null, "age": 36, "diploma": "Maths"},
+                {"first_name": "Young", "last_name": "One", "personal_email": null, "age": 17, "diploma": "None"}
+            ]"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            read_import(&file),
+            Err(DataStoreError::NoOldEnough { .. })
+        ));
+
+        fs::write(
+            &file,
+            r#"[{"first_name": "Ada", "last_name": "Lovelace", "personal_email": null, "age": 36, "diploma": "Maths"}]"#,
+        )
+        .unwrap();
+        let employees = read_import(&file).unwrap();
+        assert_eq!(employees.len(), 1);
+        assert_eq!(employees[0].onboarded, Some(false));
+    }
+}
@@ -0,0 +1,651 @@
+use std::{
+    env, fmt, fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::Cli,
+    database::{
+        backup::{BackupSettings, DEFAULT_BACKUP_DIR},
+        persistence::{DataPaths, DEFAULT_DATA_DIR},
+    },
+    utils::errors::ConfigError,
+};
+
+pub const CONFIG_ENV: &str = "RED_CARPET_CONFIG";
+// read from the working directory when no config file is given
+pub const DEFAULT_CONFIG_FILE: &str = "red_carpet.toml";
+
+pub const BIND_ENV: &str = "RED_CARPET_BIND";
+pub const LISTEN_ENV: &str = "RED_CARPET_LISTEN";
+pub const UNIX_SOCKET_MODE_ENV: &str = "RED_CARPET_UNIX_SOCKET_MODE";
+pub const SHUTDOWN_TIMEOUT_SECS_ENV: &str = "RED_CARPET_SHUTDOWN_TIMEOUT_SECS";
+pub const TLS_CERT_ENV: &str = "RED_CARPET_TLS_CERT";
+pub const TLS_KEY_ENV: &str = "RED_CARPET_TLS_KEY";
+pub const TLS_REDIRECT_BIND_ENV: &str = "RED_CARPET_TLS_REDIRECT_BIND";
+pub const TLS_HSTS_MAX_AGE_SECS_ENV: &str = "RED_CARPET_TLS_HSTS_MAX_AGE_SECS";
+pub const DATA_DIR_ENV: &str = "RED_CARPET_DATA_DIR";
+pub const READ_ONLY_ENV: &str = "RED_CARPET_READ_ONLY";
+pub const ADMIN_ID_ENV: &str = "RED_CARPET_ADMIN_ID";
+pub const ADMIN_PASSWORD_ENV: &str = "RED_CARPET_ADMIN_PASSWORD";
+pub const SESSION_LIFETIME_SECS_ENV: &str = "RED_CARPET_SESSION_LIFETIME_SECS";
+pub const SESSION_PERSIST_ENV: &str = "RED_CARPET_SESSION_PERSIST";
+pub const TRASH_RETENTION_DAYS_ENV: &str = "RED_CARPET_TRASH_RETENTION_DAYS";
+pub const BACKUP_DIR_ENV: &str = "RED_CARPET_BACKUP_DIR";
+pub const BACKUP_INTERVAL_HOURS_ENV: &str = "RED_CARPET_BACKUP_INTERVAL_HOURS";
+pub const BACKUP_RETENTION_ENV: &str = "RED_CARPET_BACKUP_RETENTION";
+pub const IDEMPOTENCY_WINDOW_SECS_ENV: &str = "RED_CARPET_IDEMPOTENCY_WINDOW_SECS";
+
+// Settings of the server, layered from lowest to highest priority: built-in
+// defaults, the TOML config file, `RED_CARPET_*` environment variables and
+// command line flags.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub data: DataConfig,
+    pub admin: AdminConfig,
+    pub session: SessionConfig,
+    pub trash: TrashConfig,
+    pub backup: BackupConfig,
+    pub idempotency: IdempotencyConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: SocketAddr,
+    // every address the server listens on, replaces `bind` when not empty
+    pub listen: Vec<Listen>,
+    // octal permissions of the unix sockets created by the server
+    pub unix_socket_mode: String,
+    // time given to in-flight requests on shutdown before they are dropped
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            listen: vec![],
+            unix_socket_mode: "660".to_string(),
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+
+    pub fn listeners(&self) -> Vec<Listen> {
+        if self.listen.is_empty() {
+            vec![Listen::Tcp(self.bind)]
+        } else {
+            self.listen.clone()
+        }
+    }
+
+    pub fn socket_mode(&self) -> Result<u32, String> {
+        match u32::from_str_radix(&self.unix_socket_mode, 8) {
+            Ok(mode) if mode <= 0o777 => Ok(mode),
+            _ => Err(format!(
+                "expected octal permissions such as 660, found {:?}",
+                self.unix_socket_mode
+            )),
+        }
+    }
+}
+
+// An address the server accepts connections on, written `0.0.0.0:8080`,
+// `unix:/run/red_carpet/red_carpet.sock` or `systemd` for the sockets passed
+// by systemd socket activation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Listen {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+    Systemd,
+}
+
+impl FromStr for Listen {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "systemd" {
+            return Ok(Listen::Systemd);
+        }
+        if let Some(path) = value.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("unix: requires the path of the socket".to_string());
+            }
+            return Ok(Listen::Unix(PathBuf::from(path)));
+        }
+        value
+            .parse()
+            .map(Listen::Tcp)
+            .map_err(|_| format!("expected host:port, unix:PATH or systemd, found {value:?}"))
+    }
+}
+
+impl TryFrom<String> for Listen {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Listen> for String {
+    fn from(listen: Listen) -> Self {
+        listen.to_string()
+    }
+}
+
+impl fmt::Display for Listen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listen::Tcp(address) => write!(f, "{address}"),
+            Listen::Unix(path) => write!(f, "unix:{}", path.display()),
+            Listen::Systemd => write!(f, "systemd"),
+        }
+    }
+}
+
+// HTTPS is served when both a certificate and a key are configured
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    // PEM certificate chain and private key, reloaded when they change
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    // plain HTTP listener redirecting every request to HTTPS
+    pub redirect_bind: Option<SocketAddr>,
+    // 0 disables the Strict-Transport-Security header
+    pub hsts_max_age_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            cert: None,
+            key: None,
+            redirect_bind: None,
+            hsts_max_age_secs: 365 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl TlsConfig {
+    // certificate and key files, when HTTPS is enabled
+    pub fn files(&self) -> Option<(&Path, &Path)> {
+        Some((self.cert.as_deref()?, self.key.as_deref()?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataConfig {
+    pub dir: PathBuf,
+    // open the data without locking it and refuse every change
+    pub read_only: bool,
+}
+
+impl Default for DataConfig {
+    fn default() -> Self {
+        DataConfig {
+            dir: PathBuf::from(DEFAULT_DATA_DIR),
+            read_only: false,
+        }
+    }
+}
+
+// administrator created on the first start, an existing one is left untouched
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    pub id: String,
+    pub password: String,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        AdminConfig {
+            id: "admin".to_string(),
+            password: "admin".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    pub lifetime_secs: u64,
+    // keep the sessions in the data directory across restarts
+    pub persist: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            lifetime_secs: 60 * 60,
+            persist: false,
+        }
+    }
+}
+
+impl SessionConfig {
+    pub fn lifetime(&self) -> Duration {
+        Duration::from_secs(self.lifetime_secs)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrashConfig {
+    // days an archived employee is kept before it is purged
+    pub retention_days: i64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig { retention_days: 30 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    // `backups` inside the data directory when not set
+    pub dir: Option<PathBuf>,
+    // 0 disables the scheduled backups
+    pub interval_hours: u64,
+    // number of backups kept, the oldest are removed first
+    pub retention: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            dir: None,
+            interval_hours: 24,
+            retention: 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdempotencyConfig {
+    // time the response to a request with an `Idempotency-Key` is replayed
+    // to its retries; 0 ignores the header
+    pub window_secs: u64,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        IdempotencyConfig {
+            window_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+impl IdempotencyConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+}
+
+impl Config {
+    // load every layer and validate the result, called once at startup
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let mut config = match cli.config_file() {
+            Some(path) => Self::from_file(&path)?,
+            None => Self::default(),
+        };
+        config.apply_env(|name| env::var(name).ok())?;
+        cli.apply(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&content).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    // override the settings with the `RED_CARPET_*` variables returned by `var`
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        if let Some(bind) = env_value(&var, BIND_ENV, str::parse)? {
+            self.server.bind = bind;
+        }
+        if let Some(listen) = env_value(&var, LISTEN_ENV, parse_list)? {
+            self.server.listen = listen;
+        }
+        if let Some(mode) = var(UNIX_SOCKET_MODE_ENV) {
+            self.server.unix_socket_mode = mode;
+        }
+        if let Some(timeout) = env_value(&var, SHUTDOWN_TIMEOUT_SECS_ENV, str::parse)? {
+            self.server.shutdown_timeout_secs = timeout;
+        }
+        if let Some(cert) = var(TLS_CERT_ENV) {
+            self.tls.cert = Some(PathBuf::from(cert));
+        }
+        if let Some(key) = var(TLS_KEY_ENV) {
+            self.tls.key = Some(PathBuf::from(key));
+        }
+        if let Some(bind) = env_value(&var, TLS_REDIRECT_BIND_ENV, str::parse)? {
+            self.tls.redirect_bind = Some(bind);
+        }
+        if let Some(max_age) = env_value(&var, TLS_HSTS_MAX_AGE_SECS_ENV, str::parse)? {
+            self.tls.hsts_max_age_secs = max_age;
+        }
+        if let Some(dir) = var(DATA_DIR_ENV) {
+            self.data.dir = PathBuf::from(dir);
+        }
+        if let Some(read_only) = env_value(&var, READ_ONLY_ENV, parse_bool)? {
+            self.data.read_only = read_only;
+        }
+        if let Some(id) = var(ADMIN_ID_ENV) {
+            self.admin.id = id;
+        }
+        if let Some(password) = var(ADMIN_PASSWORD_ENV) {
+            self.admin.password = password;
+        }
+        if let Some(lifetime) = env_value(&var, SESSION_LIFETIME_SECS_ENV, str::parse)? {
+            self.session.lifetime_secs = lifetime;
+        }
+        if let Some(persist) = env_value(&var, SESSION_PERSIST_ENV, parse_bool)? {
+            self.session.persist = persist;
+        }
+        if let Some(days) = env_value(&var, TRASH_RETENTION_DAYS_ENV, str::parse)? {
+            self.trash.retention_days = days;
+        }
+        if let Some(dir) = var(BACKUP_DIR_ENV) {
+            self.backup.dir = Some(PathBuf::from(dir));
+        }
+        if let Some(hours) = env_value(&var, BACKUP_INTERVAL_HOURS_ENV, str::parse)? {
+            self.backup.interval_hours = hours;
+        }
+        if let Some(retention) = env_value(&var, BACKUP_RETENTION_ENV, str::parse)? {
+            self.backup.retention = retention;
+        }
+        if let Some(window) = env_value(&var, IDEMPOTENCY_WINDOW_SECS_ENV, str::parse)? {
+            self.idempotency.window_secs = window;
+        }
+        Ok(())
+    }
+
+    // report every invalid setting at once rather than the first one
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let listeners = self.server.listeners();
+        for (index, listen) in listeners.iter().enumerate() {
+            if listeners[..index].contains(listen) {
+                problems.push(format!("server.listen contains {listen} twice"));
+            }
+        }
+        if let Err(error) = self.server.socket_mode() {
+            problems.push(format!("server.unix_socket_mode: {error}"));
+        }
+        if self.tls.files().is_some()
+            && listeners
+                .iter()
+                .any(|listen| matches!(listen, Listen::Unix(_)))
+        {
+            problems.push("tls is only served on TCP listeners, not on unix sockets".to_string());
+        }
+        if self.tls.cert.is_some() != self.tls.key.is_some() {
+            problems.push("tls.cert and tls.key must be set together".to_string());
+        }
+        if let Some(redirect_bind) = self.tls.redirect_bind {
+            if self.tls.files().is_none() {
+                problems.push("tls.redirect_bind requires tls.cert and tls.key".to_string());
+            }
+            if listeners.contains(&Listen::Tcp(redirect_bind)) {
+                problems
+                    .push("tls.redirect_bind must differ from the server listeners".to_string());
+            }
+        }
+        if self.data.dir.as_os_str().is_empty() {
+            problems.push("data.dir must not be empty".to_string());
+        }
+        if self.admin.id.trim().is_empty() {
+            problems.push("admin.id must not be empty".to_string());
+        }
+        if self.admin.password.is_empty() {
+            problems.push("admin.password must not be empty".to_string());
+        }
+        if self.session.lifetime_secs == 0 {
+            problems.push("session.lifetime_secs must be greater than 0".to_string());
+        }
+        if self.trash.retention_days < 0 {
+            problems.push(format!(
+                "trash.retention_days must not be negative, found {}",
+                self.trash.retention_days
+            ));
+        }
+        if self.backup.retention == 0 {
+            problems.push("backup.retention must keep at least 1 backup".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems.join("; ")))
+        }
+    }
+
+    pub fn data_paths(&self) -> DataPaths {
+        DataPaths::new(&self.data.dir)
+    }
+
+    pub fn backup_settings(&self) -> BackupSettings {
+        let interval_hours = self.backup.interval_hours;
+        BackupSettings {
+            dir: self
+                .backup
+                .dir
+                .clone()
+                .unwrap_or(self.data.dir.join(DEFAULT_BACKUP_DIR)),
+            interval: (interval_hours > 0).then(|| Duration::from_secs(interval_hours * 60 * 60)),
+            retention: self.backup.retention,
+        }
+    }
+}
+
+fn env_value<T, E: std::fmt::Display>(
+    var: &impl Fn(&str) -> Option<String>,
+    name: &'static str,
+    parse: impl Fn(&str) -> Result<T, E>,
+) -> Result<Option<T>, ConfigError> {
+    match var(name) {
+        Some(value) => match parse(value.trim()) {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(error) => Err(ConfigError::Env {
+                name,
+                value,
+                reason: error.to_string(),
+            }),
+        },
+        None => Ok(None),
+    }
+}
+
+// comma separated values, e.g. `127.0.0.1:8080,unix:/run/red_carpet.sock`
+fn parse_list<T: FromStr>(value: &str) -> Result<Vec<T>, T::Err> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" | "" => Ok(false),
+        _ => Err("expected true or false".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use clap::Parser;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn env_from(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_layers_override_each_other() {
+        let dir = TempDir::new().unwrap();
+        let config_file = dir.path().join("red_carpet.toml");
+        fs::write(
+            &config_file,
+            r#"
+            [server]
+            bind = "127.0.0.1:9000"
+
+            [data]
+            dir = "/srv/red-carpet"
+
+            [admin]
+            id = "root"
+            password = "from-file"
+
+            [session]
+            lifetime_secs = 600
+            "#,
+        )
+        .unwrap();
+
+        let mut config = Config::from_file(&config_file).unwrap();
+        assert_eq!(config.server.bind, "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(config.session.lifetime(), Duration::from_secs(600));
+        // sections missing from the file keep their defaults
+        assert_eq!(config.trash, TrashConfig::default());
+
+        config
+            .apply_env(env_from(&[
+                (ADMIN_PASSWORD_ENV, "from-env"),
+                (DATA_DIR_ENV, "/var/lib/red-carpet"),
+                (READ_ONLY_ENV, "1"),
+                (IDEMPOTENCY_WINDOW_SECS_ENV, "600"),
+            ]))
+            .unwrap();
+        assert_eq!(config.admin.id, "root");
+        assert_eq!(config.idempotency.window(), Duration::from_secs(600));
+        assert_eq!(config.admin.password, "from-env");
+        assert!(config.data.read_only);
+
+        let cli = Cli::parse_from(["red_carpet", "--data-dir", "/tmp/data"]);
+        cli.apply(&mut config);
+        assert_eq!(config.data.dir, PathBuf::from("/tmp/data"));
+        assert_eq!(config.server.bind, "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(
+            config.backup_settings().dir,
+            PathBuf::from("/tmp/data").join(DEFAULT_BACKUP_DIR)
+        );
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_settings_are_rejected() {
+        let dir = TempDir::new().unwrap();
+        let config_file = dir.path().join("red_carpet.toml");
+        fs::write(&config_file, "[session]\nlifetime = 600\n").unwrap();
+        assert!(matches!(
+            Config::from_file(&config_file),
+            Err(ConfigError::Parse { .. })
+        ));
+
+        let mut config = Config::default();
+        let error = config
+            .apply_env(env_from(&[(BIND_ENV, "localhost")]))
+            .unwrap_err();
+        assert!(matches!(error, ConfigError::Env { name: BIND_ENV, .. }));
+
+        config.session.lifetime_secs = 0;
+        config.trash.retention_days = -1;
+        config.tls.cert = Some(PathBuf::from("cert.pem"));
+        config.admin.password = String::new();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("session.lifetime_secs"));
+        assert!(error.contains("trash.retention_days"));
+        assert!(error.contains("admin.password"));
+        assert!(error.contains("tls.cert and tls.key"));
+    }
+
+    #[test]
+    fn test_listeners() {
+        let mut config = Config::default();
+        assert_eq!(
+            config.server.listeners(),
+            vec![Listen::Tcp("0.0.0.0:8080".parse().unwrap())]
+        );
+
+        config
+            .apply_env(env_from(&[(
+                LISTEN_ENV,
+                "127.0.0.1:8080, unix:/run/red_carpet.sock,systemd",
+            )]))
+            .unwrap();
+        assert_eq!(
+            config.server.listeners(),
+            vec![
+                Listen::Tcp("127.0.0.1:8080".parse().unwrap()),
+                Listen::Unix(PathBuf::from("/run/red_carpet.sock")),
+                Listen::Systemd,
+            ]
+        );
+        assert_eq!(config.server.socket_mode(), Ok(0o660));
+        assert!(config.validate().is_ok());
+
+        let content = toml::to_string(&config).unwrap();
+        assert!(content.contains(r#""unix:/run/red_carpet.sock""#));
+        assert_eq!(toml::from_str::<Config>(&content).unwrap(), config);
+
+        let error = config
+            .apply_env(env_from(&[(LISTEN_ENV, "unix:")]))
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ConfigError::Env {
+                name: LISTEN_ENV,
+                ..
+            }
+        ));
+
+        config.server.listen.push(Listen::Systemd);
+        config.server.unix_socket_mode = "999".to_string();
+        config.tls.cert = Some(PathBuf::from("cert.pem"));
+        config.tls.key = Some(PathBuf::from("key.pem"));
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("systemd twice"));
+        assert!(error.contains("server.unix_socket_mode"));
+        assert!(error.contains("not on unix sockets"));
+    }
+}
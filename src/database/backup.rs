@@ -0,0 +1,314 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, SubsecRound, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::task::JoinHandle;
+
+use crate::database::file_manager::FileManager;
+use crate::database::lock::DataDirLock;
+use crate::database::writer::write_atomically;
+use crate::models::backup_models::BackupInfo;
+
+// inside the data directory unless configured otherwise
+pub const DEFAULT_BACKUP_DIR: &str = "backups";
+
+const BACKUP_PREFIX: &str = "red-carpet-";
+const BACKUP_EXTENSION: &str = ".json.gz";
+const CHECKSUM_EXTENSION: &str = ".sha256";
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%3fZ";
+
+#[derive(Debug, Clone)]
+pub struct BackupSettings {
+    pub dir: PathBuf,
+    // None disables the scheduled backups
+    pub interval: Option<Duration>,
+    // number of backups kept, the oldest are removed first
+    pub retention: usize,
+}
+
+// Content of a backup file, before compression. Files are kept verbatim, so
+// encrypted fields stay encrypted in the backup.
+#[derive(Debug, Deserialize, Serialize)]
+struct BackupBundle {
+    created_at: DateTime<Utc>,
+    files: BTreeMap<String, String>,
+}
+
+fn invalid_backup(path: &Path, reason: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("backup {path:?} is not valid: {reason}"),
+    )
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn checksum_path(backup_path: &Path) -> PathBuf {
+    let mut path = backup_path.as_os_str().to_owned();
+    path.push(CHECKSUM_EXTENSION);
+    PathBuf::from(path)
+}
+
+// write a compressed snapshot of `files` to `backup_dir`, next to a
+// `sha256sum` compatible checksum file
+pub fn create_backup(backup_dir: &Path, files: &[PathBuf]) -> io::Result<BackupInfo> {
+    // the name keeps milliseconds only
+    let created_at = Utc::now().trunc_subsecs(3);
+    let mut bundle = BackupBundle {
+        created_at,
+        files: BTreeMap::new(),
+    };
+    for file in files {
+        let name = file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"))?;
+        bundle
+            .files
+            .insert(name.to_string(), fs::read_to_string(file)?);
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&serde_json::to_vec(&bundle)?)?;
+    let compressed = encoder.finish()?;
+    let sha256 = sha256_hex(&compressed);
+
+    fs::create_dir_all(backup_dir)?;
+    let name = format!(
+        "{BACKUP_PREFIX}{}{BACKUP_EXTENSION}",
+        created_at.format(TIMESTAMP_FORMAT)
+    );
+    let path = backup_dir.join(&name);
+    fs::write(&path, &compressed)?;
+    // the checksum is written last, a backup without one is incomplete
+    write_atomically(&checksum_path(&path), &format!("{sha256}  {name}\n"))?;
+
+    info!("Created backup {:?}", path);
+    Ok(BackupInfo {
+        name,
+        created_at,
+        size: compressed.len() as u64,
+        sha256,
+    })
+}
+
+// complete backups in `backup_dir`, newest first
+pub fn list_backups(backup_dir: &Path) -> io::Result<Vec<BackupInfo>> {
+    if !backup_dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut backups = vec![];
+    for entry in fs::read_dir(backup_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(timestamp) = name
+            .strip_prefix(BACKUP_PREFIX)
+            .and_then(|name| name.strip_suffix(BACKUP_EXTENSION))
+        else {
+            continue;
+        };
+        let Ok(created_at) = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT) else {
+            continue;
+        };
+        let Ok(checksum) = fs::read_to_string(checksum_path(&entry.path())) else {
+            continue;
+        };
+        backups.push(BackupInfo {
+            name,
+            created_at: created_at.and_utc(),
+            size: entry.metadata()?.len(),
+            sha256: checksum
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        });
+    }
+    backups.sort_by_key(|backup| Reverse(backup.created_at));
+    Ok(backups)
+}
+
+// remove all but the `keep` newest backups, returning the removed names
+pub fn prune_backups(backup_dir: &Path, keep: usize) -> io::Result<Vec<String>> {
+    let mut removed = vec![];
+    for backup in list_backups(backup_dir)?.into_iter().skip(keep) {
+        let path = backup_dir.join(&backup.name);
+        fs::remove_file(checksum_path(&path))?;
+        fs::remove_file(&path)?;
+        removed.push(backup.name);
+    }
+    Ok(removed)
+}
+
+// read a backup, checking its checksum and content
+fn read_backup(path: &Path) -> io::Result<BackupBundle> {
+    let compressed = fs::read(path)?;
+    let checksum = fs::read_to_string(checksum_path(path))
+        .map_err(|error| invalid_backup(path, format!("checksum file: {error}")))?;
+    let expected = checksum.split_whitespace().next().unwrap_or_default();
+    if sha256_hex(&compressed) != expected {
+        return Err(invalid_backup(path, "checksum mismatch"));
+    }
+
+    let mut content = Vec::new();
+    GzDecoder::new(compressed.as_slice())
+        .read_to_end(&mut content)
+        .map_err(|error| invalid_backup(path, error))?;
+    let bundle: BackupBundle =
+        serde_json::from_slice(&content).map_err(|error| invalid_backup(path, error))?;
+
+    for (name, content) in &bundle.files {
+        if Path::new(name).file_name().and_then(|file| file.to_str()) != Some(name.as_str()) {
+            return Err(invalid_backup(path, format!("unexpected file {name:?}")));
+        }
+        if !content.trim().is_empty() {
+            serde_json::from_str::<serde_json::Value>(content)
+                .map_err(|error| invalid_backup(path, format!("{name}: {error}")))?;
+        }
+    }
+    Ok(bundle)
+}
+
+// Replace the files of `data_dir` with the ones of the backup, holding the
+// data directory lock so no server can run meanwhile. Nothing is written
+// unless the whole backup verifies, and the current files are backed up to
+// `backup_dir` first so the restore can be undone.
+pub fn restore_backup(path: &Path, data_dir: &Path, backup_dir: &Path) -> io::Result<Vec<String>> {
+    let _lock = DataDirLock::acquire(data_dir)?;
+    let bundle = read_backup(path)?;
+
+    let current: Vec<PathBuf> = bundle
+        .files
+        .keys()
+        .map(|name| data_dir.join(name))
+        .filter(|file| file.exists())
+        .collect();
+    let previous = create_backup(backup_dir, &current)?;
+    info!("Previous data saved to backup {}", previous.name);
+
+    info!("Restoring backup {:?} taken at {}", path, bundle.created_at);
+    for (name, content) in &bundle.files {
+        write_atomically(&data_dir.join(name), content)?;
+    }
+    Ok(bundle.files.into_keys().collect())
+}
+
+// take a backup on every interval and apply the retention policy
+pub fn spawn_backup_job(
+    file_manager: Arc<FileManager>,
+    settings: BackupSettings,
+) -> Option<JoinHandle<()>> {
+    let interval = settings.interval?;
+    info!(
+        "Backing up to {:?} every {} hours, keeping {}",
+        settings.dir,
+        interval.as_secs() / 3600,
+        settings.retention
+    );
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // the first tick completes immediately, no backup at startup
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(error) = file_manager.backup(&settings).await {
+                error!("Scheduled backup failed: {error}");
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn data_files(dir: &Path) -> Vec<PathBuf> {
+        fs::create_dir_all(dir).unwrap();
+        let employees = dir.join("employees.json");
+        let admins = dir.join("admin.json");
+        fs::write(&employees, r#"{"1": {"first_name": "John"}}"#).unwrap();
+        fs::write(&admins, "").unwrap();
+        vec![employees, admins]
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let dir = TempDir::new().unwrap();
+        let data_dir = dir.path().join("data");
+        let backup_dir = dir.path().join("backups");
+        let files = data_files(&data_dir);
+
+        let backup = create_backup(&backup_dir, &files).unwrap();
+        assert_eq!(list_backups(&backup_dir).unwrap(), vec![backup.clone()]);
+
+        std::thread::sleep(Duration::from_millis(5));
+        fs::write(&files[0], "{}").unwrap();
+        let restored =
+            restore_backup(&backup_dir.join(&backup.name), &data_dir, &backup_dir).unwrap();
+        assert_eq!(restored, vec!["admin.json", "employees.json"]);
+        assert!(fs::read_to_string(&files[0]).unwrap().contains("John"));
+
+        // the replaced data was saved first
+        let backups = list_backups(&backup_dir).unwrap();
+        assert_eq!(backups.len(), 2);
+        let previous = backup_dir.join(&backups[0].name);
+        restore_backup(&previous, &data_dir, &backup_dir).unwrap();
+        assert_eq!(fs::read_to_string(&files[0]).unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_corrupted_backup_is_not_restored() {
+        let dir = TempDir::new().unwrap();
+        let data_dir = dir.path().join("data");
+        let backup_dir = dir.path().join("backups");
+        let files = data_files(&data_dir);
+
+        let backup = create_backup(&backup_dir, &files).unwrap();
+        let path = backup_dir.join(&backup.name);
+        let mut content = fs::read(&path).unwrap();
+        let last = content.len() - 1;
+        content[last] ^= 0xff;
+        fs::write(&path, content).unwrap();
+
+        fs::write(&files[0], "{}").unwrap();
+        let error = restore_backup(&path, &data_dir, &backup_dir).unwrap_err();
+        assert!(error.to_string().contains("checksum mismatch"));
+        assert_eq!(fs::read_to_string(&files[0]).unwrap(), "{}");
+        assert_eq!(list_backups(&backup_dir).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_prune_keeps_newest_backups() {
+        let dir = TempDir::new().unwrap();
+        let backup_dir = dir.path().join("backups");
+        let files = data_files(&dir.path().join("data"));
+
+        let mut names = vec![];
+        for _ in 0..3 {
+            names.push(create_backup(&backup_dir, &files).unwrap().name);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        let removed = prune_backups(&backup_dir, 2).unwrap();
+        assert_eq!(removed, vec![names[0].clone()]);
+        let kept: Vec<String> = list_backups(&backup_dir)
+            .unwrap()
+            .into_iter()
+            .map(|backup| backup.name)
+            .collect();
+        assert_eq!(kept, vec![names[2].clone(), names[1].clone()]);
+    }
+}
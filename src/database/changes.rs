@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::employee_store::EmployeeStore,
+    models::change_models::{Change, ChangeKind},
+};
+
+// Latest change of every employee that ever existed, in commit order. An
+// employee changed again moves to the end with a new sequence number, so
+// reading the changes after a sequence returns each employee changed since
+// then once.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChangeLog {
+    last_sequence: u64,
+    changes: Vec<Change>,
+}
+
+impl ChangeLog {
+    // log of a store written before the change feed existed: every employee
+    // outside the trash is created, in id order
+    pub fn from_store(store: &EmployeeStore) -> Self {
+        let mut log = ChangeLog::default();
+        log.record(&EmployeeStore::default(), store);
+        log
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    // append the changes from `before` to `after`, the stores of one commit
+    pub fn record(&mut self, before: &EmployeeStore, after: &EmployeeStore) {
+        let mut changed: Vec<(&String, ChangeKind, u64)> = vec![];
+        for (id, employee) in after.as_map() {
+            let kind = match before.get(id) {
+                None if employee.archived.is_none() => ChangeKind::Created,
+                None => continue,
+                Some(previous)
+                    if previous.version == employee.version
+                        && previous.archived == employee.archived =>
+                {
+                    continue
+                }
+                Some(previous) => {
+                    match (previous.archived.is_some(), employee.archived.is_some()) {
+                        (false, false) => ChangeKind::Updated,
+                        (false, true) => ChangeKind::Deleted,
+                        (true, false) => ChangeKind::Created,
+                        // changes in the trash are not visible
+                        (true, true) => continue,
+                    }
+                }
+            };
+            changed.push((id, kind, employee.version));
+        }
+        for (id, employee) in before.as_map() {
+            // a purged employee was deleted when it went to the trash
+            if after.get(id).is_none() && employee.archived.is_none() {
+                changed.push((id, ChangeKind::Deleted, employee.version));
+            }
+        }
+        if changed.is_empty() {
+            return;
+        }
+        changed.sort_by(|a, b| a.0.cmp(b.0));
+
+        let ids: HashSet<&String> = changed.iter().map(|(id, _, _)| *id).collect();
+        self.changes.retain(|change| !ids.contains(&change.id));
+        let timestamp = Utc::now();
+        for (id, change, version) in changed {
+            self.last_sequence += 1;
+            self.changes.push(Change {
+                sequence: self.last_sequence,
+                id: id.clone(),
+                change,
+                version,
+                timestamp,
+            });
+        }
+    }
+
+    // at most `limit` changes committed after `sequence`, and whether more
+    // follow them
+    pub fn since(&self, sequence: u64, limit: usize) -> (Vec<Change>, bool) {
+        let start = self
+            .changes
+            .partition_point(|change| change.sequence <= sequence);
+        let changes: Vec<Change> = self.changes[start..].iter().take(limit).cloned().collect();
+        let more = self.changes.len() - start > changes.len();
+        (changes, more)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::employee_models::{ArchiveInfo, Employee};
+
+    fn employee(id: &str, version: u64) -> Employee {
+        Employee {
+            id: Some(id.to_string()),
+            first_name: id.to_string(),
+            last_name: "Doe".to_string(),
+            personal_email: None,
+            avaya_email: None,
+            age: 30,
+            diploma: "MSc".to_string(),
+            onboarded: Some(false),
+            handle: None,
+            password: None,
+            secure_password: Some(false),
+            version,
+            archived: None,
+        }
+    }
+
+    fn store(employees: Vec<Employee>) -> EmployeeStore {
+        EmployeeStore::from_map(
+            employees
+                .into_iter()
+                .map(|employee| (employee.id.clone().unwrap(), employee))
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    fn summary(changes: &[Change]) -> Vec<(u64, &str, ChangeKind)> {
+        changes
+            .iter()
+            .map(|change| (change.sequence, change.id.as_str(), change.change))
+            .collect()
+    }
+
+    #[test]
+    fn test_record_and_read_since() {
+        let first = store(vec![employee("a", 1), employee("b", 1)]);
+        let mut log = ChangeLog::from_store(&first);
+        let (changes, more) = log.since(0, 10);
+        assert_eq!(
+            summary(&changes),
+            [(1, "a", ChangeKind::Created), (2, "b", ChangeKind::Created)]
+        );
+        assert!(!more);
+
+        let mut archived = employee("b", 2);
+        archived.archived = Some(ArchiveInfo {
+            reason: "left".to_string(),
+            actor: "admin".to_string(),
+            archived_at: Utc::now(),
+        });
+        let second = store(vec![employee("a", 2), archived, employee("c", 1)]);
+        log.record(&first, &second);
+        // nothing changed
+        log.record(&second, &second);
+        let third = store(vec![employee("a", 2), employee("c", 1)]);
+        log.record(&second, &third);
+
+        let (changes, more) = log.since(0, 2);
+        assert_eq!(
+            summary(&changes),
+            [(3, "a", ChangeKind::Updated), (4, "b", ChangeKind::Deleted)]
+        );
+        assert!(more);
+        let (changes, more) = log.since(4, 2);
+        assert_eq!(summary(&changes), [(5, "c", ChangeKind::Created)]);
+        assert!(!more);
+        assert!(log.since(5, 2).0.is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{models::employee_models::Employee, utils::errors::DataStoreError};
+
+// normalize an email for lookups: trimmed and lower case
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+// normalize a full name for lookups: words trimmed, collapsed and lower case
+pub fn normalize_name(first_name: &str, last_name: &str) -> String {
+    format!("{first_name} {last_name}")
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// In-memory employee records plus the secondary indexes derived from them.
+// Every mutation goes through `insert` / `remove`, so the indexes can never
+// drift from the primary map.
+#[derive(Debug, Default, Clone)]
+pub struct EmployeeStore {
+    employees: HashMap<String, Employee>,
+    by_handle: HashMap<String, String>,
+    by_avaya_email: HashMap<String, String>,
+    by_personal_email: HashMap<String, HashSet<String>>,
+    by_name: HashMap<String, HashSet<String>>,
+}
+
+impl EmployeeStore {
+    pub fn from_map(employees: HashMap<String, Employee>) -> Result<Self, DataStoreError> {
+        let mut store = EmployeeStore::default();
+        for (id, employee) in employees {
+            store.insert(&id, employee)?;
+        }
+        Ok(store)
+    }
+
+    pub fn as_map(&self) -> &HashMap<String, Employee> {
+        &self.employees
+    }
+
+    pub fn len(&self) -> usize {
+        self.employees.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.employees.is_empty()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Employee> {
+        self.employees.get(id)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.employees.contains_key(id)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Employee> {
+        self.employees.values()
+    }
+
+    pub fn get_by_handle(&self, handle: &str) -> Option<&Employee> {
+        self.by_handle
+            .get(handle)
+            .and_then(|id| self.employees.get(id))
+    }
+
+    pub fn get_by_avaya_email(&self, email: &str) -> Option<&Employee> {
+        self.by_avaya_email
+            .get(&normalize_email(email))
+            .and_then(|id| self.employees.get(id))
+    }
+
+    pub fn find_by_personal_email(&self, email: &str) -> Vec<&Employee> {
+        self.lookup_many(&self.by_personal_email, &normalize_email(email))
+    }
+
+    pub fn find_by_name(&self, first_name: &str, last_name: &str) -> Vec<&Employee> {
+        self.lookup_many(&self.by_name, &normalize_name(first_name, last_name))
+    }
+
+    fn lookup_many<'a>(
+        &'a self,
+        index: &'a HashMap<String, HashSet<String>>,
+        key: &str,
+    ) -> Vec<&'a Employee> {
+        index
+            .get(key)
+            .map(|ids| ids.iter().filter_map(|id| self.employees.get(id)).collect())
+            .unwrap_or_default()
+    }
+
+    // insert or replace an employee, rejecting handles or corporate emails
+    // that already belong to another record
+    pub fn insert(
+        &mut self,
+        id: &str,
+        employee: Employee,
+    ) -> Result<Option<Employee>, DataStoreError> {
+        if let Some(handle) = &employee.handle {
+            if let Some(owner) = self.by_handle.get(handle) {
+                if owner != id {
+                    return Err(DataStoreError::HandleAlreadyTaken {
+                        handle: handle.clone(),
+                    });
+                }
+            }
+        }
+        if let Some(email) = &employee.avaya_email {
+            if let Some(owner) = self.by_avaya_email.get(&normalize_email(email)) {
+                if owner != id {
+                    return Err(DataStoreError::EmailAlreadyTaken {
+                        email: email.clone(),
+                    });
+                }
+            }
+        }
+
+        let previous = self.remove(id);
+        self.index(id, &employee);
+        self.employees.insert(id.to_string(), employee);
+        Ok(previous)
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Employee> {
+        let employee = self.employees.remove(id)?;
+        self.unindex(id, &employee);
+        Some(employee)
+    }
+
+    fn index(&mut self, id: &str, employee: &Employee) {
+        if let Some(handle) = &employee.handle {
+            self.by_handle.insert(handle.clone(), id.to_string());
+        }
+        if let Some(email) = &employee.avaya_email {
+            self.by_avaya_email
+                .insert(normalize_email(email), id.to_string());
+        }
+        if let Some(email) = &employee.personal_email {
+            self.by_personal_email
+                .entry(normalize_email(email))
+                .or_default()
+                .insert(id.to_string());
+        }
+        self.by_name
+            .entry(normalize_name(&employee.first_name, &employee.last_name))
+            .or_default()
+            .insert(id.to_string());
+    }
+
+    fn unindex(&mut self, id: &str, employee: &Employee) {
+        if let Some(handle) = &employee.handle {
+            self.by_handle.remove(handle);
+        }
+        if let Some(email) = &employee.avaya_email {
+            self.by_avaya_email.remove(&normalize_email(email));
+        }
+        if let Some(email) = &employee.personal_email {
+            Self::unindex_many(&mut self.by_personal_email, &normalize_email(email), id);
+        }
+        Self::unindex_many(
+            &mut self.by_name,
+            &normalize_name(&employee.first_name, &employee.last_name),
+            id,
+        );
+    }
+
+    fn unindex_many(index: &mut HashMap<String, HashSet<String>>, key: &str, id: &str) {
+        if let Some(ids) = index.get_mut(key) {
+            ids.remove(id);
+            if ids.is_empty() {
+                index.remove(key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn employee(id: &str, first_name: &str, last_name: &str) -> Employee {
+        Employee {
+            id: Some(id.to_string()),
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            personal_email: Some(format!("{first_name}.{last_name}@Example.com")),
+            avaya_email: None,
+            age: 30,
+            diploma: "MSc".to_string(),
+            onboarded: Some(false),
+            handle: None,
+            password: None,
+            secure_password: Some(false),
+            version: 0,
+            archived: None,
+        }
+    }
+
+    fn onboarded(mut employee: Employee, handle: &str) -> Employee {
+        employee.handle = Some(handle.to_string());
+        employee.avaya_email = Some(format!("{handle}@avaya.com"));
+        employee.onboarded = Some(true);
+        employee
+    }
+
+    #[test]
+    fn test_lookup_by_indexes() {
+        let mut store = EmployeeStore::default();
+        store
+            .insert("1", onboarded(employee("1", "John", "Doe"), "jdoe"))
+            .unwrap();
+        store.insert("2", employee("2", "Jane", "Doe")).unwrap();
+
+        assert_eq!(store.get_by_handle("jdoe").unwrap().first_name, "John");
+        assert_eq!(
+            store
+                .get_by_avaya_email("JDoe@Avaya.com")
+                .unwrap()
+                .first_name,
+            "John"
+        );
+        assert_eq!(
+            store.find_by_personal_email(" jane.doe@example.COM").len(),
+            1
+        );
+        assert_eq!(store.find_by_name("  JANE ", "doe").len(), 1);
+        assert!(store.get_by_handle("jane").is_none());
+    }
+
+    #[test]
+    fn test_update_reindexes_record() {
+        let mut store = EmployeeStore::default();
+        store.insert("1", employee("1", "John", "Doe")).unwrap();
+        store
+            .insert("1", onboarded(employee("1", "Johnny", "Doe"), "jdoe"))
+            .unwrap();
+
+        assert!(store.find_by_name("John", "Doe").is_empty());
+        assert_eq!(store.find_by_name("Johnny", "Doe").len(), 1);
+        assert!(store.get_by_handle("jdoe").is_some());
+
+        store.remove("1");
+        assert!(store.get_by_handle("jdoe").is_none());
+        assert!(store.find_by_name("Johnny", "Doe").is_empty());
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_handle_and_email_must_be_unique() {
+        let mut store = EmployeeStore::default();
+        store
+            .insert("1", onboarded(employee("1", "John", "Doe"), "jdoe"))
+            .unwrap();
+
+        let duplicate_handle = store.insert("2", onboarded(employee("2", "Jim", "Doe"), "jdoe"));
+        assert!(matches!(
+            duplicate_handle,
+            Err(DataStoreError::HandleAlreadyTaken { .. })
+        ));
+
+        let mut duplicate_email = onboarded(employee("3", "Joe", "Doe"), "jdoe2");
+        duplicate_email.avaya_email = Some("JDOE@avaya.com".to_string());
+        assert!(matches!(
+            store.insert("3", duplicate_email),
+            Err(DataStoreError::EmailAlreadyTaken { .. })
+        ));
+
+        // a rejected insert leaves the store untouched
+        assert_eq!(store.len(), 1);
+        assert!(store.find_by_name("Jim", "Doe").is_empty());
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use aes_gcm::aead::{Aead, OsRng, Payload};
+use aes_gcm::{AeadCore, Aes256Gcm, Key, KeyInit, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::database::writer::write_atomically;
+use crate::models::{employee_models::Employee, history_models::Revision};
+
+pub const ENCRYPTION_KEY_ENV: &str = "RED_CARPET_ENCRYPTION_KEY";
+pub const ENCRYPTION_KEY_FILE_ENV: &str = "RED_CARPET_ENCRYPTION_KEY_FILE";
+// master key to switch to when re-encrypting
+pub const NEW_ENCRYPTION_KEY_ENV: &str = "RED_CARPET_NEW_ENCRYPTION_KEY";
+pub const NEW_ENCRYPTION_KEY_FILE_ENV: &str = "RED_CARPET_NEW_ENCRYPTION_KEY_FILE";
+
+// employee fields that are never written to disk in clear text
+pub const ENCRYPTED_FIELDS: [&str; 6] = [
+    "first_name",
+    "last_name",
+    "personal_email",
+    "age",
+    "diploma",
+    "password",
+];
+
+// sealed values look like `enc:v1:<key id>:<base64 nonce + ciphertext>`
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// Key encryption key, supplied by the operator. It only wraps the data keys
+// stored in the key ring and never touches employee data directly.
+pub struct MasterKey(Key<Aes256Gcm>);
+
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MasterKey(********)")
+    }
+}
+
+impl MasterKey {
+    // 32 random bytes, base64 encoded, e.g. `openssl rand -base64 32`
+    pub fn from_base64(encoded: &str) -> io::Result<Self> {
+        let bytes = STANDARD
+            .decode(encoded.trim())
+            .map_err(|error| invalid_data(format!("invalid encryption key: {error}")))?;
+        if bytes.len() != 32 {
+            return Err(invalid_data(format!(
+                "invalid encryption key: expected 32 bytes, found {}",
+                bytes.len()
+            )));
+        }
+        Ok(MasterKey(*Key::<Aes256Gcm>::from_slice(&bytes)))
+    }
+
+    pub fn generate() -> Self {
+        MasterKey(Aes256Gcm::generate_key(OsRng))
+    }
+
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(self.0)
+    }
+
+    // key from the environment variable, or else from the file it points to
+    pub fn from_env() -> io::Result<Option<Self>> {
+        Self::from_env_vars(ENCRYPTION_KEY_ENV, ENCRYPTION_KEY_FILE_ENV)
+    }
+
+    pub fn new_from_env() -> io::Result<Option<Self>> {
+        Self::from_env_vars(NEW_ENCRYPTION_KEY_ENV, NEW_ENCRYPTION_KEY_FILE_ENV)
+    }
+
+    fn from_env_vars(key_env: &str, key_file_env: &str) -> io::Result<Option<Self>> {
+        if let Ok(encoded) = env::var(key_env) {
+            return Self::from_base64(&encoded).map(Some);
+        }
+        if let Ok(key_file) = env::var(key_file_env) {
+            let encoded = fs::read_to_string(&key_file).map_err(|error| {
+                io::Error::new(
+                    error.kind(),
+                    format!("unable to read encryption key file {key_file:?}: {error}"),
+                )
+            })?;
+            return Self::from_base64(&encoded).map(Some);
+        }
+        Ok(None)
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.0)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct WrappedKey {
+    // data key sealed with the master key
+    key: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct KeyRing {
+    active: String,
+    keys: BTreeMap<String, WrappedKey>,
+}
+
+// Envelope encryption of the sensitive employee fields. Data keys live in
+// the key ring file, wrapped by the master key; new values are always sealed
+// with the active data key, older ones stay readable until re-encrypted.
+pub struct FieldEncryption {
+    master: MasterKey,
+    key_ring_path: PathBuf,
+    key_ring: KeyRing,
+    keys: BTreeMap<String, Key<Aes256Gcm>>,
+}
+
+impl fmt::Debug for FieldEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldEncryption")
+            .field("key_ring_path", &self.key_ring_path)
+            .field("active", &self.key_ring.active)
+            .finish()
+    }
+}
+
+impl FieldEncryption {
+    // load the key ring, creating it with a first data key when `create` is set
+    pub fn open(key_ring_path: &Path, master: MasterKey, create: bool) -> io::Result<Self> {
+        let content = match fs::read_to_string(key_ring_path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+
+        if content.trim().is_empty() {
+            if !create {
+                return Err(invalid_data(format!(
+                    "encryption key ring {key_ring_path:?} not found"
+                )));
+            }
+            let mut encryption = FieldEncryption {
+                master,
+                key_ring_path: key_ring_path.to_path_buf(),
+                key_ring: KeyRing {
+                    active: String::new(),
+                    keys: BTreeMap::new(),
+                },
+                keys: BTreeMap::new(),
+            };
+            encryption.add_data_key()?;
+            write_atomically(key_ring_path, &encryption.key_ring_content()?)?;
+            info!("Created encryption key ring {:?}", key_ring_path);
+            return Ok(encryption);
+        }
+
+        let key_ring: KeyRing = serde_json::from_str(&content)?;
+        let cipher = master.cipher();
+        let mut keys = BTreeMap::new();
+        for (id, wrapped) in &key_ring.keys {
+            let key = open_sealed(&cipher, &wrapped.key, &key_aad(id)).map_err(|_| {
+                invalid_data(format!(
+                    "unable to unwrap data key {id:?}: wrong encryption key?"
+                ))
+            })?;
+            if key.len() != 32 {
+                return Err(invalid_data(format!("data key {id:?} is corrupted")));
+            }
+            keys.insert(id.clone(), *Key::<Aes256Gcm>::from_slice(&key));
+        }
+        if !keys.contains_key(&key_ring.active) {
+            return Err(invalid_data(format!(
+                "active data key {:?} is missing from the key ring",
+                key_ring.active
+            )));
+        }
+        info!("Loaded {} data keys", keys.len());
+
+        Ok(FieldEncryption {
+            master,
+            key_ring_path: key_ring_path.to_path_buf(),
+            key_ring,
+            keys,
+        })
+    }
+
+    pub fn key_ring_path(&self) -> &Path {
+        &self.key_ring_path
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        &self.key_ring.active
+    }
+
+    // generate a data key and make it the active one
+    fn add_data_key(&mut self) -> io::Result<()> {
+        let id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+        let key = Aes256Gcm::generate_key(OsRng);
+        let wrapped = seal(&self.master.cipher(), &key, &key_aad(&id))?;
+        self.key_ring.keys.insert(
+            id.clone(),
+            WrappedKey {
+                key: wrapped,
+                created_at: Utc::now(),
+            },
+        );
+        self.keys.insert(id.clone(), key);
+        self.key_ring.active = id;
+        Ok(())
+    }
+
+    // copy of this key ring with a fresh active data key, wrapped by `master`
+    // (or the current master key); older data keys are kept so existing
+    // values stay readable while they are re-encrypted
+    pub fn rotated(&self, master: Option<MasterKey>) -> io::Result<Self> {
+        let master = master.unwrap_or(MasterKey(self.master.0));
+        let cipher = master.cipher();
+        let mut key_ring = self.key_ring.clone();
+        for (id, key) in &self.keys {
+            key_ring.keys.get_mut(id).unwrap().key = seal(&cipher, key, &key_aad(id))?;
+        }
+        let mut rotated = FieldEncryption {
+            master,
+            key_ring_path: self.key_ring_path.clone(),
+            key_ring,
+            keys: self.keys.clone(),
+        };
+        rotated.add_data_key()?;
+        Ok(rotated)
+    }
+
+    // drop every data key except the active one
+    pub fn retain_active_key(&mut self) {
+        let active = self.key_ring.active.clone();
+        self.key_ring.keys.retain(|id, _| *id == active);
+        self.keys.retain(|id, _| *id == active);
+    }
+
+    pub fn key_ring_content(&self) -> io::Result<String> {
+        Ok(serde_json::to_string_pretty(&self.key_ring)?)
+    }
+
+    fn seal_value(&self, value: &Value, aad: &str) -> io::Result<Value> {
+        if value.is_null() {
+            return Ok(Value::Null);
+        }
+        let id = &self.key_ring.active;
+        let cipher = Aes256Gcm::new(&self.keys[id]);
+        let sealed = seal(&cipher, value.to_string().as_bytes(), aad)?;
+        Ok(Value::String(format!("{PREFIX}{id}:{sealed}")))
+    }
+
+    fn open_value(&self, value: Value, aad: &str) -> io::Result<Value> {
+        let Some((id, sealed)) = sealed_parts(&value) else {
+            // written before encryption was enabled
+            return Ok(value);
+        };
+        let key = self
+            .keys
+            .get(id)
+            .ok_or_else(|| invalid_data(format!("unknown data key {id:?}")))?;
+        let plain = open_sealed(&Aes256Gcm::new(key), sealed, aad)
+            .map_err(|_| invalid_data(format!("unable to decrypt {aad}")))?;
+        Ok(serde_json::from_slice(&plain)?)
+    }
+}
+
+// associated data binding a sealed value to its employee and field, so
+// values cannot be swapped between records
+fn field_aad(id: &str, field: &str) -> String {
+    format!("employee:{id}:{field}")
+}
+
+fn key_aad(id: &str) -> String {
+    format!("data-key:{id}")
+}
+
+fn seal(cipher: &Aes256Gcm, plain: &[u8], aad: &str) -> io::Result<String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: plain,
+        aad: aad.as_bytes(),
+    };
+    let ciphertext = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| invalid_data("encryption failed"))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    Ok(STANDARD.encode(sealed))
+}
+
+fn open_sealed(cipher: &Aes256Gcm, sealed: &str, aad: &str) -> Result<Vec<u8>, aes_gcm::Error> {
+    let sealed = STANDARD.decode(sealed).map_err(|_| aes_gcm::Error)?;
+    if sealed.len() < NONCE_LEN {
+        return Err(aes_gcm::Error);
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: aad.as_bytes(),
+    };
+    cipher.decrypt(Nonce::from_slice(nonce), payload)
+}
+
+fn sealed_parts(value: &Value) -> Option<(&str, &str)> {
+    value.as_str()?.strip_prefix(PREFIX)?.split_once(':')
+}
+
+fn seal_fields(
+    encryption: Option<&FieldEncryption>,
+    id: &str,
+    mut value: Value,
+) -> io::Result<Value> {
+    let Some(encryption) = encryption else {
+        return Ok(value);
+    };
+    for field in ENCRYPTED_FIELDS {
+        if let Some(slot) = value.get_mut(field) {
+            *slot = encryption.seal_value(slot, &field_aad(id, field))?;
+        }
+    }
+    Ok(value)
+}
+
+fn open_fields(
+    encryption: Option<&FieldEncryption>,
+    id: &str,
+    mut value: Value,
+) -> io::Result<Value> {
+    for field in ENCRYPTED_FIELDS {
+        if let Some(slot) = value.get_mut(field) {
+            *slot = open_field(encryption, slot.take(), &field_aad(id, field))?;
+        }
+    }
+    Ok(value)
+}
+
+fn open_field(encryption: Option<&FieldEncryption>, value: Value, aad: &str) -> io::Result<Value> {
+    match encryption {
+        Some(encryption) => encryption.open_value(value, aad),
+        None if sealed_parts(&value).is_some() => Err(invalid_data(format!(
+            "{aad} is encrypted but no encryption key is configured \
+             (set {ENCRYPTION_KEY_ENV} or {ENCRYPTION_KEY_FILE_ENV})"
+        ))),
+        None => Ok(value),
+    }
+}
+
+// sensitive fields of a stored employee that are not encrypted
+pub fn clear_text_fields(value: &Value) -> Vec<&'static str> {
+    ENCRYPTED_FIELDS
+        .into_iter()
+        .filter(|field| {
+            value
+                .get(field)
+                .is_some_and(|slot| !slot.is_null() && sealed_parts(slot).is_none())
+        })
+        .collect()
+}
+
+// employee as written to disk
+pub fn seal_employee(
+    encryption: Option<&FieldEncryption>,
+    employee: &Employee,
+) -> io::Result<Value> {
+    let id = employee.id.clone().unwrap_or_default();
+    seal_fields(encryption, &id, serde_json::to_value(employee)?)
+}
+
+// employee as read from disk
+pub fn open_employee(encryption: Option<&FieldEncryption>, value: Value) -> io::Result<Employee> {
+    let id = value
+        .get("id")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    Ok(serde_json::from_value(open_fields(
+        encryption, &id, value,
+    )?)?)
+}
+
+// revision as written to disk; the diff of an encrypted field is sealed too
+pub fn seal_revision(
+    encryption: Option<&FieldEncryption>,
+    id: &str,
+    revision: &Revision,
+) -> io::Result<Value> {
+    let mut value = serde_json::to_value(revision)?;
+    value["snapshot"] = seal_employee(encryption, &revision.snapshot)?;
+    if let Some(encryption) = encryption {
+        for change in value["changes"].as_array_mut().unwrap() {
+            let field = change["field"].as_str().unwrap_or_default().to_string();
+            if ENCRYPTED_FIELDS.contains(&field.as_str()) {
+                let aad = field_aad(id, &format!("history:{field}"));
+                change["old"] = encryption.seal_value(&change["old"], &aad)?;
+                change["new"] = encryption.seal_value(&change["new"], &aad)?;
+            }
+        }
+    }
+    Ok(value)
+}
+
+// revision as read from disk
+pub fn open_revision(
+    encryption: Option<&FieldEncryption>,
+    id: &str,
+    mut value: Value,
+) -> io::Result<Revision> {
+    value["snapshot"] = serde_json::to_value(open_employee(encryption, value["snapshot"].take())?)?;
+    if let Some(changes) = value.get_mut("changes").and_then(Value::as_array_mut) {
+        for change in changes {
+            let field = change["field"].as_str().unwrap_or_default().to_string();
+            if ENCRYPTED_FIELDS.contains(&field.as_str()) {
+                let aad = field_aad(id, &format!("history:{field}"));
+                change["old"] = open_field(encryption, change["old"].take(), &aad)?;
+                change["new"] = open_field(encryption, change["new"].take(), &aad)?;
+            }
+        }
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn employee() -> Employee {
+        Employee {
+            id: Some("1".to_string()),
+            first_name: "John".to_string(),
+            last_name: "Doe".to_string(),
+            personal_email: Some("john@example.com".to_string()),
+            avaya_email: None,
+            age: 30,
+            diploma: "MSc".to_string(),
+            onboarded: Some(false),
+            handle: Some("jdoe".to_string()),
+            password: None,
+            secure_password: Some(false),
+            version: 1,
+            archived: None,
+        }
+    }
+
+    fn encryption(dir: &TempDir) -> FieldEncryption {
+        FieldEncryption::open(&dir.path().join("keys.json"), MasterKey::generate(), true).unwrap()
+    }
+
+    #[test]
+    fn test_sensitive_fields_are_sealed() {
+        let dir = TempDir::new().unwrap();
+        let encryption = encryption(&dir);
+
+        let sealed = seal_employee(Some(&encryption), &employee()).unwrap();
+        let text = sealed.to_string();
+        assert!(!text.contains("John"));
+        assert!(!text.contains("john@example.com"));
+        assert!(sealed["age"].as_str().unwrap().starts_with(PREFIX));
+        // identifiers stay readable, null stays null
+        assert_eq!(sealed["handle"], "jdoe");
+        assert_eq!(sealed["password"], Value::Null);
+
+        assert_eq!(
+            open_employee(Some(&encryption), sealed).unwrap(),
+            employee()
+        );
+        assert_eq!(
+            open_employee(Some(&encryption), serde_json::to_value(employee()).unwrap()).unwrap(),
+            employee()
+        );
+    }
+
+    #[test]
+    fn test_sealed_values_are_bound_to_their_record() {
+        let dir = TempDir::new().unwrap();
+        let encryption = encryption(&dir);
+
+        let mut sealed = seal_employee(Some(&encryption), &employee()).unwrap();
+        sealed["id"] = Value::from("2");
+        assert!(open_employee(Some(&encryption), sealed.clone()).is_err());
+        // and cannot be read without a key
+        sealed["id"] = Value::from("1");
+        assert!(open_employee(None, sealed).is_err());
+    }
+
+    #[test]
+    fn test_key_ring_requires_its_master_key() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("keys.json");
+        let master = MasterKey::generate();
+        let encoded = master.to_base64();
+        let created = FieldEncryption::open(&path, master, true).unwrap();
+
+        let reopened =
+            FieldEncryption::open(&path, MasterKey::from_base64(&encoded).unwrap(), false).unwrap();
+        assert_eq!(reopened.active_key_id(), created.active_key_id());
+        assert!(FieldEncryption::open(&path, MasterKey::generate(), false).is_err());
+        assert!(MasterKey::from_base64("c2hvcnQ=").is_err());
+    }
+}
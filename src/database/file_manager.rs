@@ -1,168 +1,685 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::fs::OpenOptions;
-use std::io::{self, Read, Write};
-use std::sync::Mutex;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
 
+use chrono::{DateTime, Utc};
 use log::info;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::sync::Mutex;
 
+use crate::database::backup::{create_backup, prune_backups, BackupSettings};
+use crate::database::changes::ChangeLog;
+use crate::database::employee_store::EmployeeStore;
+use crate::database::encryption::{
+    clear_text_fields, open_employee, open_revision, seal_employee, seal_revision, FieldEncryption,
+    MasterKey,
+};
+use crate::database::history::{record_revision, EmployeeHistory};
+use crate::database::lock::DataDirLock;
+use crate::database::persistence::DataPaths;
+use crate::database::writer::StoreWriter;
 use crate::models::admin_models::Admin;
-use crate::models::employee_models::Employee;
+use crate::models::backup_models::BackupInfo;
+use crate::models::change_models::Change;
+use crate::models::employee_models::{ArchiveInfo, Employee, QueryOptions, SortKey, SortPosition};
+use crate::models::history_models::Revision;
+use crate::utils::errors::DataStoreError;
+use crate::utils::password_utils::{base_handle, is_hashed_password};
 
+// actor recorded for the revisions created by `migrate`
+const MIGRATION_ACTOR: &str = "migration";
+
+// Readers take a cheap `Arc` snapshot under a short read lock and never wait
+// on disk I/O. Writers are serialized by `write_gate`, build the next
+// snapshot, hand the file write to the writer actor and only publish the new
+// snapshot once it has been persisted.
+//
+// A read-write FileManager holds an advisory lock on the data directory so
+// no other process writes to the same files; a read-only one takes no lock
+// and refuses every write.
+//
+// `close` waits for the write in progress and refuses the later ones, so a
+// process shutting down never stops halfway through a write.
+//
+// With a master key configured, the sensitive employee fields are encrypted
+// in the files only; the snapshots in memory, and so the indexes, stay in
+// clear text.
 #[derive(Debug)]
 pub struct FileManager {
-    employees: Mutex<HashMap<String, Employee>>,
-    admins: Mutex<HashMap<String, Admin>>,
-    employee_file_path: String,
-    admin_file_path: String,
+    employees: RwLock<Arc<EmployeeStore>>,
+    admins: RwLock<Arc<HashMap<String, Admin>>>,
+    history: RwLock<Arc<EmployeeHistory>>,
+    changes: RwLock<Arc<ChangeLog>>,
+    write_gate: Mutex<()>,
+    writer: StoreWriter,
+    paths: DataPaths,
+    encryption: RwLock<Option<Arc<FieldEncryption>>>,
+    // None when the store was opened read-only
+    lock: Option<DataDirLock>,
+    closed: AtomicBool,
 }
 
 impl FileManager {
-    pub fn new(employee_file_path: &str, admin_file_path: &str) -> io::Result<Self> {
-        let employees = Self::load_employees_from_file(employee_file_path)?;
-        let admins = Self::load_admins_from_file(admin_file_path)?;
+    pub fn new(paths: &DataPaths, master_key: Option<MasterKey>) -> io::Result<Self> {
+        let lock = DataDirLock::acquire(&paths.data_dir)?;
+        let encryption = master_key
+            .map(|key| FieldEncryption::open(&paths.keys_file, key, true))
+            .transpose()?;
+        Self::open(paths, encryption, Some(lock))
+    }
+
+    // open the store without locking it, e.g. to inspect the data of a
+    // running server; every write fails with `DataStoreError::ReadOnly`
+    pub fn open_read_only(paths: &DataPaths, master_key: Option<MasterKey>) -> io::Result<Self> {
+        info!("Opening data store read-only");
+        let encryption = master_key
+            .map(|key| FieldEncryption::open(&paths.keys_file, key, false))
+            .transpose()?;
+        Self::open(paths, encryption, None)
+    }
+
+    fn open(
+        paths: &DataPaths,
+        encryption: Option<FieldEncryption>,
+        lock: Option<DataDirLock>,
+    ) -> io::Result<Self> {
+        let stored_employees: HashMap<String, Value> = Self::load_from_file(&paths.employee_file)?;
+        let employees = stored_employees
+            .into_iter()
+            .map(|(id, value)| Ok((id, open_employee(encryption.as_ref(), value)?)))
+            .collect::<io::Result<HashMap<String, Employee>>>()?;
+        let employees = EmployeeStore::from_map(employees)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))?;
+        let admins: HashMap<String, Admin> = Self::load_from_file(&paths.admin_file)?;
+        let stored_history: HashMap<String, Vec<Value>> =
+            Self::load_from_file(&paths.history_file)?;
+        let history = stored_history
+            .into_iter()
+            .map(|(id, revisions)| {
+                let revisions = revisions
+                    .into_iter()
+                    .map(|value| open_revision(encryption.as_ref(), &id, value))
+                    .collect::<io::Result<Vec<Revision>>>()?;
+                Ok((id, revisions))
+            })
+            .collect::<io::Result<EmployeeHistory>>()?;
+        // data written before the change feed existed has no change log yet
+        let mut changes: ChangeLog = match Self::load_from_file(&paths.changes_file) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => ChangeLog::default(),
+            changes => changes?,
+        };
+        if changes.is_empty() {
+            changes = ChangeLog::from_store(&employees);
+        }
         info!("Loaded {} employees", employees.len());
         info!("Loaded {} admins", admins.len());
+        info!("Loaded history of {} employees", history.len());
         Ok(FileManager {
-            employees: Mutex::new(employees),
-            admins: Mutex::new(admins),
-            employee_file_path: employee_file_path.to_string(),
-            admin_file_path: admin_file_path.to_string(),
+            employees: RwLock::new(Arc::new(employees)),
+            admins: RwLock::new(Arc::new(admins)),
+            history: RwLock::new(Arc::new(history)),
+            changes: RwLock::new(Arc::new(changes)),
+            write_gate: Mutex::new(()),
+            writer: StoreWriter::spawn()?,
+            paths: paths.clone(),
+            encryption: RwLock::new(encryption.map(Arc::new)),
+            lock,
+            closed: AtomicBool::new(false),
         })
     }
 
-    fn load_employees_from_file(file_path: &str) -> io::Result<HashMap<String, Employee>> {
-        let mut file = OpenOptions::new().read(true).open(file_path)?;
-        let mut content = String::new();
-        file.read_to_string(&mut content)?;
-        let employees: HashMap<String, Employee> = serde_json::from_str(&content)?;
-        Ok(employees)
+    pub fn paths(&self) -> &DataPaths {
+        &self.paths
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.lock.is_none()
+    }
+
+    // checked with the write gate held, so no write can start after `close`
+    fn check_writable(&self) -> Result<(), DataStoreError> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(DataStoreError::Closed);
+        }
+        match self.is_read_only() {
+            true => Err(DataStoreError::ReadOnly),
+            false => Ok(()),
+        }
+    }
+
+    // wait for the write in progress, if any, then refuse every later one
+    pub async fn close(&self) -> io::Result<()> {
+        let _gate = self.write_gate.lock().await;
+        self.closed.store(true, Ordering::SeqCst);
+        // a cancelled request may have left its write queued
+        self.writer.flush().await?;
+        info!("Data store closed");
+        Ok(())
     }
 
-    fn load_admins_from_file(file_path: &str) -> io::Result<HashMap<String, Admin>> {
+    // load a json document, treating an empty file as an empty document
+    fn load_from_file<T: DeserializeOwned + Default>(file_path: &Path) -> io::Result<T> {
         let mut file = OpenOptions::new().read(true).open(file_path)?;
         let mut content = String::new();
         file.read_to_string(&mut content)?;
-        let admins: HashMap<String, Admin> = serde_json::from_str(&content)?;
-        Ok(admins)
+        if content.trim().is_empty() {
+            return Ok(T::default());
+        }
+        Ok(serde_json::from_str(&content)?)
     }
 
-    // get employee by id
-    fn get_employee_by_id(&self, id: &str) -> Option<Employee> {
-        let employees = self.employees.lock().unwrap();
-        employees.get(id).cloned()
-    }
-
-    // save employee content to file
-    fn save_employee_content_to_file(&self, content: &str) -> io::Result<()> {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.employee_file_path)?;
-        file.write_all(content.as_bytes())?;
-        file.flush()?;
+    // current employees snapshot
+    fn employees(&self) -> Arc<EmployeeStore> {
+        self.employees.read().unwrap().clone()
+    }
+
+    // current admins snapshot
+    fn admins(&self) -> Arc<HashMap<String, Admin>> {
+        self.admins.read().unwrap().clone()
+    }
+
+    // current history snapshot
+    fn history(&self) -> Arc<EmployeeHistory> {
+        self.history.read().unwrap().clone()
+    }
+
+    // current change log snapshot
+    fn changes(&self) -> Arc<ChangeLog> {
+        self.changes.read().unwrap().clone()
+    }
+
+    fn encryption(&self) -> Option<Arc<FieldEncryption>> {
+        self.encryption.read().unwrap().clone()
+    }
+
+    // write history, change log and employees, sealing the sensitive fields;
+    // employees last, so a persisted change always has its revision and is
+    // never missing from the change feed
+    async fn persist_employees(
+        &self,
+        encryption: Option<&FieldEncryption>,
+        employees: &EmployeeStore,
+        history: &EmployeeHistory,
+        changes: &ChangeLog,
+    ) -> Result<(), DataStoreError> {
+        let mut stored_history = HashMap::new();
+        for (id, revisions) in history {
+            let revisions = revisions
+                .iter()
+                .map(|revision| seal_revision(encryption, id, revision))
+                .collect::<io::Result<Vec<Value>>>()?;
+            stored_history.insert(id, revisions);
+        }
+        let history_content = serde_json::to_string_pretty(&stored_history)?;
+        self.writer
+            .write(&self.paths.history_file, history_content)
+            .await?;
+        self.writer
+            .write(&self.paths.changes_file, serde_json::to_string(changes)?)
+            .await?;
+
+        let stored_employees = employees
+            .as_map()
+            .iter()
+            .map(|(id, employee)| Ok((id, seal_employee(encryption, employee)?)))
+            .collect::<io::Result<HashMap<&String, Value>>>()?;
+        let content = serde_json::to_string_pretty(&stored_employees)?;
+        self.writer
+            .write(&self.paths.employee_file, content)
+            .await?;
         Ok(())
     }
 
-    // save admin content to file
-    fn save_admins_content_to_file(&self, content: &str) -> io::Result<()> {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.admin_file_path)?;
-        file.write_all(content.as_bytes())?;
-        file.flush()?;
+    // snapshot of the data files, consistent because no write can run while
+    // the gate is held; older backups beyond the retention are removed
+    pub async fn backup(&self, settings: &BackupSettings) -> io::Result<BackupInfo> {
+        let _gate = self.write_gate.lock().await;
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(io::Error::other(DataStoreError::Closed.to_string()));
+        }
+
+        let mut files = vec![
+            self.paths.employee_file.clone(),
+            self.paths.admin_file.clone(),
+            self.paths.history_file.clone(),
+        ];
+        // data keys are needed to read back encrypted fields
+        for optional in [&self.paths.keys_file, &self.paths.changes_file] {
+            if optional.exists() {
+                files.push(optional.clone());
+            }
+        }
+
+        let settings = settings.clone();
+        tokio::task::spawn_blocking(move || {
+            let backup = create_backup(&settings.dir, &files)?;
+            for removed in prune_backups(&settings.dir, settings.retention)? {
+                info!("Removed backup {}", removed);
+            }
+            Ok(backup)
+        })
+        .await?
+    }
+
+    // seal every record again with a new data key, optionally wrapping the
+    // key ring with a new master key; once done only the new data key is
+    // kept. Also encrypts records written before encryption was enabled.
+    pub async fn reencrypt(&self, new_master_key: Option<MasterKey>) -> Result<(), DataStoreError> {
+        let _gate = self.write_gate.lock().await;
+        self.check_writable()?;
+
+        let current = self
+            .encryption()
+            .ok_or(DataStoreError::EncryptionDisabled)?;
+        let mut rotated = current.rotated(new_master_key)?;
+        let key_ring_path = rotated.key_ring_path().to_path_buf();
+
+        // old keys stay in the ring until every record uses the new one
+        self.writer
+            .write(&key_ring_path, rotated.key_ring_content()?)
+            .await?;
+        self.persist_employees(
+            Some(&rotated),
+            &self.employees(),
+            &self.history(),
+            &self.changes(),
+        )
+        .await?;
+        rotated.retain_active_key();
+        self.writer
+            .write(&key_ring_path, rotated.key_ring_content()?)
+            .await?;
+
+        info!(
+            "Re-encrypted employees with data key {}",
+            rotated.active_key_id()
+        );
+        *self.encryption.write().unwrap() = Some(Arc::new(rotated));
         Ok(())
     }
 
+    // get employee by id, unless it is in the trash
+    fn get_employee_by_id(&self, id: &str) -> Option<Employee> {
+        self.employees()
+            .get(id)
+            .filter(|employee| employee.archived.is_none())
+            .cloned()
+    }
+
+    // employees that are not in the trash
+    fn active_employees(&self) -> Vec<Employee> {
+        self.employees()
+            .values()
+            .filter(|employee| employee.archived.is_none())
+            .cloned()
+            .collect()
+    }
+
     // check employee exists by first name and last name
     pub fn check_employee_exists(&self, first_name: &str, last_name: &str) -> bool {
         info!("Checking if employee exists: {} {}", first_name, last_name);
-        let employees = self.employees.lock().unwrap();
-        employees
-            .values()
-            .any(|employee| employee.first_name == first_name && employee.last_name == last_name)
+        self.employees()
+            .find_by_name(first_name, last_name)
+            .iter()
+            .any(|employee| employee.archived.is_none())
+    }
+
+    // apply a change to copies of the store and history, persist them and
+    // publish them; on any error the published snapshots are left untouched
+    async fn mutate_employees<T>(
+        &self,
+        change: impl FnOnce(&mut EmployeeStore, &mut EmployeeHistory) -> Result<T, DataStoreError>,
+    ) -> Result<T, DataStoreError> {
+        let _gate = self.write_gate.lock().await;
+        self.check_writable()?;
+
+        let current = self.employees();
+        let mut next = (*current).clone();
+        let mut next_history = (*self.history()).clone();
+        let result = change(&mut next, &mut next_history)?;
+        let mut next_changes = (*self.changes()).clone();
+        next_changes.record(&current, &next);
+
+        self.persist_employees(
+            self.encryption().as_deref(),
+            &next,
+            &next_history,
+            &next_changes,
+        )
+        .await?;
+
+        *self.history.write().unwrap() = Arc::new(next_history);
+        *self.changes.write().unwrap() = Arc::new(next_changes);
+        *self.employees.write().unwrap() = Arc::new(next);
+        Ok(result)
     }
 
     // add employee
-    pub fn add_employee(&self, employee: Employee) -> io::Result<()> {
+    pub async fn add_employee(
+        &self,
+        employee: Employee,
+        actor: &str,
+    ) -> Result<Employee, DataStoreError> {
         info!("Adding employee: {:?}", employee);
-        let mut employees = self.employees.lock().unwrap();
-        employees.insert(employee.id.clone().unwrap(), employee);
-        let content = serde_json::to_string_pretty(&*employees)?;
-        self.save_employee_content_to_file(&content)
+        let id = employee.id.clone().unwrap();
+        self.mutate_employees(|employees, history| {
+            // checked again under the write gate, two requests for the same
+            // name may both have passed the check of their handler
+            let already_exists = employees
+                .find_by_name(&employee.first_name, &employee.last_name)
+                .iter()
+                .any(|existing| existing.archived.is_none());
+            if already_exists {
+                return Err(DataStoreError::EmployeeAlreadyExists {
+                    first_name: employee.first_name,
+                    last_name: employee.last_name,
+                });
+            }
+            let mut employee = employee;
+            employee.version = 1;
+            employees.insert(&id, employee.clone())?;
+            record_revision(history, None, &employee, actor, None);
+            Ok(employee)
+        })
+        .await
+    }
+
+    // add several employees in a single write: either all of them are stored
+    // or, on the first invalid one, none is
+    pub async fn import_employees(
+        &self,
+        employees: Vec<Employee>,
+        actor: &str,
+    ) -> Result<Vec<Employee>, DataStoreError> {
+        info!("Importing {} employees", employees.len());
+        self.mutate_employees(|store, history| {
+            let mut imported = Vec::with_capacity(employees.len());
+            for mut employee in employees {
+                let already_exists = store
+                    .find_by_name(&employee.first_name, &employee.last_name)
+                    .iter()
+                    .any(|existing| existing.archived.is_none());
+                if already_exists {
+                    return Err(DataStoreError::EmployeeAlreadyExists {
+                        first_name: employee.first_name,
+                        last_name: employee.last_name,
+                    });
+                }
+                let id = employee.id.clone().unwrap();
+                employee.version = 1;
+                store.insert(&id, employee.clone())?;
+                record_revision(history, None, &employee, actor, None);
+                imported.push(employee);
+            }
+            Ok(imported)
+        })
+        .await
+    }
+
+    // Onboard each `(id, password)` pending employee with a generated handle,
+    // chosen under the write gate so that no two employees of the batch, nor
+    // of concurrent writes, get the same one. An employee that cannot be
+    // onboarded gets its error in the results; the others are stored in a
+    // single write, or none is when the write fails.
+    pub async fn onboard_employees(
+        &self,
+        onboardings: Vec<(String, String)>,
+        actor: &str,
+    ) -> Result<Vec<Result<Employee, DataStoreError>>, DataStoreError> {
+        info!("Onboarding {} employees", onboardings.len());
+        self.mutate_employees(|store, history| {
+            let mut results = Vec::with_capacity(onboardings.len());
+            for (id, password) in onboardings {
+                let result = match store.get(&id).filter(|e| e.archived.is_none()) {
+                    None => Err(DataStoreError::EmployeeNotFound { id }),
+                    Some(employee) if employee.onboarded == Some(true) => {
+                        Err(DataStoreError::AlreadyOnboarded { id })
+                    }
+                    Some(employee) => {
+                        let employee = employee.clone();
+                        Self::apply_onboarding(store, history, employee, password, None, actor)
+                    }
+                };
+                results.push(result);
+            }
+            Ok(results)
+        })
+        .await
+    }
+
+    // Onboard `employee`, with the personal data it carries, under a handle
+    // chosen under the write gate as in `onboard_employees`.
+    pub async fn onboard_employee(
+        &self,
+        employee: Employee,
+        password: String,
+        expected_version: Option<u64>,
+        actor: &str,
+    ) -> Result<Employee, DataStoreError> {
+        info!("Onboarding employee: {:?}", employee.id);
+        self.mutate_employees(|store, history| {
+            Self::apply_onboarding(store, history, employee, password, expected_version, actor)
+        })
+        .await
+    }
+
+    // give `employee` a free handle, its corporate email and `password`
+    fn apply_onboarding(
+        store: &mut EmployeeStore,
+        history: &mut EmployeeHistory,
+        employee: Employee,
+        password: String,
+        expected_version: Option<u64>,
+        actor: &str,
+    ) -> Result<Employee, DataStoreError> {
+        let id = employee.id.clone().unwrap_or_default();
+        let handle = free_handle(store, &employee.first_name, &employee.last_name);
+        let employee = Employee {
+            avaya_email: Some(format!("{handle}@avaya.com")),
+            onboarded: Some(true),
+            handle: Some(handle),
+            password: Some(password),
+            secure_password: Some(false),
+            ..employee
+        };
+        Self::apply_update(store, history, &id, employee, expected_version, actor, None)
     }
 
     // add admin
-    pub fn add_admin(&self, admin: Admin) -> io::Result<()> {
+    pub async fn add_admin(&self, admin: Admin) -> io::Result<()> {
         info!("Adding admin: {:?}", admin);
-        let mut admins = self.admins.lock().unwrap();
-        admins.insert(admin.id.clone(), admin);
-        let content = serde_json::to_string_pretty(&*admins)?;
-        self.save_admins_content_to_file(&content)
+        let _gate = self.write_gate.lock().await;
+        self.check_writable()
+            .map_err(|error| io::Error::new(io::ErrorKind::PermissionDenied, error.to_string()))?;
+
+        let mut next = (*self.admins()).clone();
+        next.insert(admin.id.clone(), admin);
+        let content = serde_json::to_string_pretty(&next)?;
+        self.writer.write(&self.paths.admin_file, content).await?;
+
+        *self.admins.write().unwrap() = Arc::new(next);
+        Ok(())
     }
 
     // list employees sorted by first name
     pub fn list_employees(&self) -> Vec<Employee> {
         info!("Listing employees");
-        let employees = self.employees.lock().unwrap();
-        let mut vec_employees: Vec<Employee> = employees.values().cloned().collect();
-        vec_employees.sort_by(|a, b| a.first_name.cmp(&b.first_name));
+        let mut vec_employees = self.active_employees();
+        vec_employees.sort_by(|a, b| {
+            a.first_name
+                .cmp(&b.first_name)
+                .then_with(|| a.id.cmp(&b.id))
+        });
         vec_employees
     }
 
-    // paginate employees
-    pub fn paginate_employees(&self, page: usize, per_page: usize) -> Vec<Employee> {
-        info!("Paginating employees");
-        let employees = self.employees.lock().unwrap();
-        let employees: Vec<Employee> = employees.values().cloned().collect();
+    // employees outside the trash matching the filters of `query`, ordered by
+    // `sort` then by id, so that the same query always pages the same way;
+    // each comes with its position in that order
+    pub fn query_employees(
+        &self,
+        query: &QueryOptions,
+        sort: &[SortKey],
+    ) -> Vec<(SortPosition, Employee)> {
+        info!("Querying employees: {query:?}");
+        let history = self.history();
+        let mut employees: Vec<(SortPosition, Employee)> = self
+            .active_employees()
+            .into_iter()
+            .filter(|employee| query.matches(employee))
+            .map(|employee| {
+                // creation time of an employee is the time of its first revision
+                let created_at = employee
+                    .id
+                    .as_ref()
+                    .and_then(|id| history.get(id))
+                    .and_then(|revisions| revisions.first())
+                    .map(|revision| revision.timestamp);
+                (SortPosition::new(&employee, created_at), employee)
+            })
+            .collect();
+        employees.sort_by(|(a, _), (b, _)| a.compare(b, sort));
+        employees
+    }
 
-        // check if page is out of bounds
-        if page < 1 {
-            return vec![];
-        }
+    // at most `limit` changes committed after `sequence`, and whether more
+    // follow them
+    pub fn changes_since(&self, sequence: u64, limit: usize) -> (Vec<Change>, bool) {
+        self.changes().since(sequence, limit)
+    }
+
+    // update employee, optionally only if it is still at `expected_version`
+    pub async fn update_employee(
+        &self,
+        id: &str,
+        to_be_update_employee: Employee,
+        expected_version: Option<u64>,
+        actor: &str,
+    ) -> Result<Employee, DataStoreError> {
+        info!("Updating employee: {:?}", to_be_update_employee);
+        self.mutate_employees(|employees, history| {
+            Self::apply_update(
+                employees,
+                history,
+                id,
+                to_be_update_employee,
+                expected_version,
+                actor,
+                None,
+            )
+        })
+        .await
+    }
 
-        let start = (page - 1) * per_page;
-        let end = start + per_page;
+    fn apply_update(
+        employees: &mut EmployeeStore,
+        history: &mut EmployeeHistory,
+        id: &str,
+        mut employee: Employee,
+        expected_version: Option<u64>,
+        actor: &str,
+        restored_from: Option<u64>,
+    ) -> Result<Employee, DataStoreError> {
+        // if the employee is not found or in the trash, return an error
+        let current = employees
+            .get(id)
+            .filter(|employee| employee.archived.is_none())
+            .cloned()
+            .ok_or_else(|| DataStoreError::EmployeeNotFound { id: id.to_string() })?;
 
-        if start >= employees.len() {
-            return vec![];
+        if let Some(expected) = expected_version {
+            if expected != current.version {
+                return Err(DataStoreError::VersionConflict {
+                    id: id.to_string(),
+                    expected,
+                    actual: current.version,
+                });
+            }
         }
-        if end >= employees.len() {
-            return employees[start..].to_vec();
-        }
-        employees[start..end].to_vec()
+
+        employee.version = current.version + 1;
+        employee.archived = None;
+        employees.insert(id, employee.clone())?;
+        record_revision(history, Some(&current), &employee, actor, restored_from);
+        Ok(employee)
     }
 
-    // update employee
-    pub fn update_employee(&self, id: &str, to_be_update_employee: Employee) -> io::Result<()> {
-        info!("Updating employee: {:?}", to_be_update_employee);
-        let mut employees = self.employees.lock().unwrap();
+    // revisions of an employee, oldest first
+    pub fn get_employee_history(&self, id: &str) -> Vec<Revision> {
+        info!("Getting history of employee: {}", id);
+        self.history().get(id).cloned().unwrap_or_default()
+    }
 
-        // insert the updated employee
-        let insert_result = employees.insert(id.to_string(), to_be_update_employee);
+    // bring back the fields of an earlier revision as a new revision;
+    // credentials are never rewound
+    pub async fn restore_revision(
+        &self,
+        id: &str,
+        revision: u64,
+        expected_version: Option<u64>,
+        actor: &str,
+    ) -> Result<Employee, DataStoreError> {
+        info!("Restoring employee {} to revision {}", id, revision);
+        self.mutate_employees(|employees, history| {
+            let snapshot = history
+                .get(id)
+                .and_then(|revisions| revisions.iter().find(|r| r.version == revision))
+                .map(|revision| revision.snapshot.clone())
+                .ok_or_else(|| DataStoreError::RevisionNotFound {
+                    id: id.to_string(),
+                    revision,
+                })?;
+            let current = employees
+                .get(id)
+                .ok_or_else(|| DataStoreError::EmployeeNotFound { id: id.to_string() })?;
 
-        // if the employee is not found, return an error
-        if insert_result.is_none() {
-            Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                "Employee not found",
-            ))
-        } else {
-            let content = serde_json::to_string_pretty(&*employees)?;
-            self.save_employee_content_to_file(&content)
-        }
+            // the account of the employee is not rewound with the profile
+            let restored = Employee {
+                password: current.password.clone(),
+                secure_password: current.secure_password,
+                handle: current.handle.clone(),
+                avaya_email: current.avaya_email.clone(),
+                onboarded: current.onboarded,
+                ..snapshot
+            };
+            Self::apply_update(
+                employees,
+                history,
+                id,
+                restored,
+                expected_version,
+                actor,
+                Some(revision),
+            )
+        })
+        .await
     }
 
     // get employee by handle
     pub fn get_employee_by_handle(&self, handle: &str) -> Option<Employee> {
         info!("Getting employee by handle: {}", handle);
-        let employees = self.employees.lock().unwrap();
-        employees
-            .values()
-            .find(|employee| employee.handle == Some(handle.to_string()))
+        self.employees().get_by_handle(handle).cloned()
+    }
+
+    // get employee by corporate email
+    pub fn get_employee_by_avaya_email(&self, email: &str) -> Option<Employee> {
+        info!("Getting employee by avaya email: {}", email);
+        self.employees().get_by_avaya_email(email).cloned()
+    }
+
+    // find employees sharing a personal email
+    pub fn find_employees_by_personal_email(&self, email: &str) -> Vec<Employee> {
+        info!("Finding employees by personal email: {}", email);
+        self.employees()
+            .find_by_personal_email(email)
+            .into_iter()
             .cloned()
+            .collect()
     }
 
     pub fn get_employee(&self, id: &str) -> Option<Employee> {
@@ -170,17 +687,928 @@ impl FileManager {
         self.get_employee_by_id(id)
     }
 
+    pub fn list_admins(&self) -> Vec<Admin> {
+        let mut admins: Vec<Admin> = self.admins().values().cloned().collect();
+        admins.sort();
+        admins
+    }
+
     pub fn get_admin_by_id(&self, id: &str) -> Option<Admin> {
         info!("Getting admin by id: {}", id);
-        let admins = self.admins.lock().unwrap();
-        admins.get(id).cloned()
-    }
-    // delete employee
-    pub fn delete_employee(&self, id: &str) -> io::Result<()> {
-        info!("Deleting employee by id: {}", id);
-        let mut employees = self.employees.lock().unwrap();
-        employees.remove(id);
-        let content = serde_json::to_string_pretty(&*employees)?;
-        self.save_employee_content_to_file(&content)
+        self.admins().get(id).cloned()
+    }
+
+    // move an employee to the trash; it stays there until it is restored or
+    // purged once the retention period has passed
+    pub async fn archive_employee(
+        &self,
+        id: &str,
+        reason: &str,
+        actor: &str,
+    ) -> Result<Employee, DataStoreError> {
+        info!("Archiving employee by id: {}", id);
+        self.mutate_employees(|employees, history| {
+            let current = employees
+                .get(id)
+                .filter(|employee| employee.archived.is_none())
+                .cloned()
+                .ok_or_else(|| DataStoreError::EmployeeNotFound { id: id.to_string() })?;
+
+            let mut employee = current.clone();
+            employee.version = current.version + 1;
+            employee.archived = Some(ArchiveInfo {
+                reason: reason.to_string(),
+                actor: actor.to_string(),
+                archived_at: Utc::now(),
+            });
+            employees.insert(id, employee.clone())?;
+            record_revision(history, Some(&current), &employee, actor, None);
+            Ok(employee)
+        })
+        .await
+    }
+
+    // take an employee back out of the trash
+    pub async fn restore_archived_employee(
+        &self,
+        id: &str,
+        actor: &str,
+    ) -> Result<Employee, DataStoreError> {
+        info!("Restoring archived employee by id: {}", id);
+        self.mutate_employees(|employees, history| {
+            let current = employees
+                .get(id)
+                .filter(|employee| employee.archived.is_some())
+                .cloned()
+                .ok_or_else(|| DataStoreError::EmployeeNotFound { id: id.to_string() })?;
+
+            let mut employee = current.clone();
+            employee.version = current.version + 1;
+            employee.archived = None;
+            employees.insert(id, employee.clone())?;
+            record_revision(history, Some(&current), &employee, actor, None);
+            Ok(employee)
+        })
+        .await
+    }
+
+    // employees in the trash, most recently archived first
+    pub fn list_archived_employees(&self) -> Vec<Employee> {
+        info!("Listing archived employees");
+        let mut vec_employees: Vec<Employee> = self
+            .employees()
+            .values()
+            .filter(|employee| employee.archived.is_some())
+            .cloned()
+            .collect();
+        vec_employees.sort_by_key(|employee| {
+            Reverse(
+                employee
+                    .archived
+                    .as_ref()
+                    .map(|archived| archived.archived_at),
+            )
+        });
+        vec_employees
+    }
+
+    // consistency problems of the data files that loading them tolerates;
+    // an empty list means the data is sound
+    pub fn verify(&self) -> io::Result<Vec<String>> {
+        let mut problems = vec![];
+        let employees = self.employees();
+        let history = self.history();
+
+        let stored: HashMap<String, Value> = Self::load_from_file(&self.paths.employee_file)?;
+        let mut ids: Vec<&String> = stored.keys().collect();
+        ids.sort();
+        for id in ids {
+            let value = &stored[id];
+            if value.get("id").and_then(Value::as_str) != Some(id.as_str()) {
+                problems.push(format!("employee {id}: stored under another id"));
+            }
+            if self.encryption().is_some() {
+                for field in clear_text_fields(value) {
+                    problems.push(format!("employee {id}: {field} is not encrypted"));
+                }
+            }
+            let Some(employee) = employees.get(id) else {
+                continue;
+            };
+            if employee.version == 0 {
+                problems.push(format!("employee {id}: has no version"));
+            }
+            match history.get(id).and_then(|revisions| revisions.last()) {
+                None => problems.push(format!("employee {id}: has no history")),
+                Some(last) if last.version != employee.version => problems.push(format!(
+                    "employee {id}: version {} but last revision {}",
+                    employee.version, last.version
+                )),
+                Some(_) => {}
+            }
+        }
+
+        let mut orphans: Vec<&String> = history
+            .keys()
+            .filter(|id| !employees.contains(id))
+            .collect();
+        orphans.sort();
+        for id in orphans {
+            problems.push(format!("history {id}: no such employee"));
+        }
+
+        for admin in self.list_admins() {
+            match &admin.password {
+                Some(password) if is_hashed_password(password) => {}
+                Some(_) => problems.push(format!("admin {}: password is not hashed", admin.id)),
+                None => problems.push(format!("admin {}: has no password", admin.id)),
+            }
+        }
+        Ok(problems)
+    }
+
+    // bring the employee records written by older releases to the current
+    // format: every record gets a version and a first revision, and every
+    // file is rewritten, which also encrypts clear text fields when a key is
+    // configured; returns the ids of the employees that were changed
+    pub async fn migrate(&self) -> Result<Vec<String>, DataStoreError> {
+        info!("Migrating employees");
+        self.mutate_employees(|employees, history| {
+            let outdated: Vec<String> = employees
+                .values()
+                .filter(|employee| {
+                    employee.version == 0
+                        || !history.contains_key(employee.id.as_deref().unwrap_or_default())
+                })
+                .filter_map(|employee| employee.id.clone())
+                .collect();
+            for id in &outdated {
+                let mut employee = employees.get(id).cloned().unwrap();
+                employee.version = employee.version.max(1);
+                employees.insert(id, employee.clone())?;
+                if !history.contains_key(id) {
+                    record_revision(history, None, &employee, MIGRATION_ACTOR, None);
+                }
+            }
+            Ok(outdated)
+        })
+        .await
+    }
+
+    // permanently remove employees archived before `cutoff`, along with
+    // their history; returns the ids that were removed
+    pub async fn purge_archived_employees(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<Vec<String>, DataStoreError> {
+        let expired = |employee: &Employee| {
+            employee
+                .archived
+                .as_ref()
+                .is_some_and(|archived| archived.archived_at < cutoff)
+        };
+        // nothing to rewrite in the common case
+        if !self.employees().values().any(expired) {
+            return Ok(vec![]);
+        }
+
+        info!("Purging employees archived before {}", cutoff);
+        self.mutate_employees(|employees, history| {
+            let ids: Vec<String> = employees
+                .values()
+                .filter(|employee| expired(employee))
+                .filter_map(|employee| employee.id.clone())
+                .collect();
+            for id in &ids {
+                employees.remove(id);
+                history.remove(id);
+            }
+            Ok(ids)
+        })
+        .await
+    }
+}
+
+// the handle of the employee, with a random suffix while it or its corporate
+// email is already taken in `store`
+fn free_handle(store: &EmployeeStore, first_name: &str, last_name: &str) -> String {
+    let base = base_handle(first_name, last_name);
+    let mut handle = base.clone();
+    while store.get_by_handle(&handle).is_some()
+        || store
+            .get_by_avaya_email(&format!("{handle}@avaya.com"))
+            .is_some()
+    {
+        // up to 5 random digits
+        handle = format!("{base}{}", rand::random::<u32>() % 100000);
+    }
+    handle
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::database::persistence::{create_persistence_store, migrate_admin_passwords};
+    use crate::models::change_models::ChangeKind;
+
+    fn file_manager(dir: &TempDir) -> FileManager {
+        create_persistence_store(&DataPaths::new(dir.path())).unwrap();
+        file_manager_from(dir)
+    }
+
+    fn employee(first_name: &str, last_name: &str) -> Employee {
+        Employee {
+            id: Some(uuid::Uuid::new_v4().to_string()),
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            personal_email: None,
+            avaya_email: None,
+            age: 30,
+            diploma: "MSc".to_string(),
+            onboarded: Some(false),
+            handle: None,
+            password: None,
+            secure_password: Some(false),
+            version: 0,
+            archived: None,
+        }
+    }
+
+    fn file_manager_from(dir: &TempDir) -> FileManager {
+        open_with_key(dir, None).unwrap()
+    }
+
+    fn open_with_key(dir: &TempDir, master_key: Option<MasterKey>) -> io::Result<FileManager> {
+        FileManager::new(&DataPaths::new(dir.path()), master_key)
+    }
+
+    // a second view of the files written by a running FileManager
+    fn read_only_from(dir: &TempDir) -> FileManager {
+        read_only_with_key(dir, None).unwrap()
+    }
+
+    fn read_only_with_key(dir: &TempDir, master_key: Option<MasterKey>) -> io::Result<FileManager> {
+        FileManager::open_read_only(&DataPaths::new(dir.path()), master_key)
+    }
+
+    #[tokio::test]
+    async fn test_data_dir_is_locked() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = file_manager(&dir);
+
+        let error = open_with_key(&dir, None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+        assert!(error
+            .to_string()
+            .contains(&format!("process {}", std::process::id())));
+
+        // a read-only store can still look at the data, but never write it
+        let read_only = read_only_from(&dir);
+        assert!(read_only.is_read_only());
+        let result = read_only
+            .add_employee(employee("John", "Doe"), "admin")
+            .await;
+        assert!(matches!(result, Err(DataStoreError::ReadOnly)));
+        assert!(read_only.list_employees().is_empty());
+
+        // the lock is released with the store
+        drop(file_manager);
+        assert!(!file_manager_from(&dir).is_read_only());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_update_is_not_published() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = file_manager(&dir);
+
+        let mut john = employee("John", "Doe");
+        john.handle = Some("jdoe".to_string());
+        let mut jim = employee("Jim", "Doe");
+        file_manager.add_employee(john, "admin").await.unwrap();
+        file_manager
+            .add_employee(jim.clone(), "admin")
+            .await
+            .unwrap();
+
+        jim.handle = Some("jdoe".to_string());
+        let result = file_manager
+            .update_employee(jim.id.clone().unwrap().as_str(), jim.clone(), None, "admin")
+            .await;
+        assert!(matches!(
+            result,
+            Err(DataStoreError::HandleAlreadyTaken { .. })
+        ));
+        assert_eq!(
+            file_manager
+                .get_employee(jim.id.as_deref().unwrap())
+                .unwrap()
+                .handle,
+            None
+        );
+
+        let missing = file_manager
+            .update_employee("missing", jim, None, "admin")
+            .await;
+        assert!(matches!(
+            missing,
+            Err(DataStoreError::EmployeeNotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_update_checks_expected_version() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = file_manager(&dir);
+
+        let john = file_manager
+            .add_employee(employee("John", "Doe"), "admin")
+            .await
+            .unwrap();
+        assert_eq!(john.version, 1);
+        let id = john.id.clone().unwrap();
+
+        let mut first_edit = john.clone();
+        first_edit.diploma = "PhD".to_string();
+        let updated = file_manager
+            .update_employee(&id, first_edit, Some(1), "admin")
+            .await
+            .unwrap();
+        assert_eq!(updated.version, 2);
+
+        // a second editor still holding version 1 must not overwrite it
+        let mut stale_edit = john;
+        stale_edit.age = 40;
+        let result = file_manager
+            .update_employee(&id, stale_edit, Some(1), "admin")
+            .await;
+        assert!(matches!(
+            result,
+            Err(DataStoreError::VersionConflict {
+                expected: 1,
+                actual: 2,
+                ..
+            })
+        ));
+        let stored = file_manager.get_employee(&id).unwrap();
+        assert_eq!(stored.diploma, "PhD");
+        assert_eq!(stored.age, 30);
+    }
+
+    #[tokio::test]
+    async fn test_history_and_restore() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = file_manager(&dir);
+
+        let john = file_manager
+            .add_employee(employee("John", "Doe"), "hr")
+            .await
+            .unwrap();
+        let id = john.id.clone().unwrap();
+
+        let mut edit = john.clone();
+        edit.diploma = "PhD".to_string();
+        edit.password = Some("Secret!12".to_string());
+        file_manager
+            .update_employee(&id, edit, Some(1), "admin")
+            .await
+            .unwrap();
+
+        let restored = file_manager
+            .restore_revision(&id, 1, Some(2), "admin")
+            .await
+            .unwrap();
+        assert_eq!(restored.version, 3);
+        assert_eq!(restored.diploma, "MSc");
+        // credentials are not rewound by a restore
+        assert_eq!(restored.password, Some("Secret!12".to_string()));
+
+        // nor is the account of an onboarded employee
+        file_manager
+            .onboard_employees(vec![(id.clone(), "Secret!34".to_string())], "admin")
+            .await
+            .unwrap();
+        let restored = file_manager
+            .restore_revision(&id, 2, Some(4), "admin")
+            .await
+            .unwrap();
+        assert_eq!(restored.diploma, "PhD");
+        assert_eq!(restored.handle.as_deref(), Some("jdoe"));
+        assert_eq!(restored.avaya_email.as_deref(), Some("jdoe@avaya.com"));
+        assert_eq!(restored.onboarded, Some(true));
+
+        let history = read_only_from(&dir).get_employee_history(&id);
+        assert_eq!(history.len(), 5);
+        assert_eq!(history[0].actor, "hr");
+        assert_eq!(history[1].changes.len(), 2);
+        assert_eq!(history[2].restored_from, Some(1));
+        assert_eq!(history[4].restored_from, Some(2));
+
+        let missing = file_manager.restore_revision(&id, 9, None, "admin").await;
+        assert!(matches!(
+            missing,
+            Err(DataStoreError::RevisionNotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_at_rest_and_reencrypt() {
+        let dir = TempDir::new().unwrap();
+        drop(file_manager(&dir));
+        let master_key = MasterKey::generate();
+        let encoded = master_key.to_base64();
+        let file_manager = open_with_key(&dir, Some(master_key)).unwrap();
+
+        let mut john = employee("Johnathan", "Doe");
+        john.personal_email = Some("john@example.com".to_string());
+        // long enough not to show up by chance in the base64 ciphertext
+        john.diploma = "Master of Science".to_string();
+        let john = file_manager.add_employee(john, "admin").await.unwrap();
+        let id = john.id.clone().unwrap();
+        let mut edit = john.clone();
+        edit.diploma = "Doctorate in Physics".to_string();
+        file_manager
+            .update_employee(&id, edit, None, "admin")
+            .await
+            .unwrap();
+
+        let on_disk = fs::read_to_string(dir.path().join("employees.json")).unwrap()
+            + &fs::read_to_string(dir.path().join("history.json")).unwrap();
+        for secret in [
+            "Johnathan",
+            "john@example.com",
+            "Doctorate in Physics",
+            "Master of Science",
+        ] {
+            assert!(!on_disk.contains(secret), "{secret} stored in clear text");
+        }
+
+        // searches work on the decrypted records
+        let reloaded =
+            read_only_with_key(&dir, Some(MasterKey::from_base64(&encoded).unwrap())).unwrap();
+        assert!(reloaded.check_employee_exists("Johnathan", "Doe"));
+        assert_eq!(
+            reloaded
+                .find_employees_by_personal_email("john@example.com")
+                .len(),
+            1
+        );
+        assert_eq!(
+            reloaded.get_employee_history(&id)[1].changes[0].new,
+            "Doctorate in Physics"
+        );
+        assert!(read_only_with_key(&dir, None).is_err());
+
+        let new_master_key = MasterKey::generate();
+        let new_encoded = new_master_key.to_base64();
+        file_manager.reencrypt(Some(new_master_key)).await.unwrap();
+
+        let key_ring: Value =
+            serde_json::from_str(&fs::read_to_string(dir.path().join("keys.json")).unwrap())
+                .unwrap();
+        assert_eq!(key_ring["keys"].as_object().unwrap().len(), 1);
+        assert!(read_only_with_key(&dir, Some(MasterKey::from_base64(&encoded).unwrap())).is_err());
+        let rotated =
+            read_only_with_key(&dir, Some(MasterKey::from_base64(&new_encoded).unwrap())).unwrap();
+        assert_eq!(
+            rotated.get_employee(&id).unwrap().diploma,
+            "Doctorate in Physics"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_archive_restore_and_purge() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = file_manager(&dir);
+
+        let john = file_manager
+            .add_employee(employee("John", "Doe"), "admin")
+            .await
+            .unwrap();
+        let jane = file_manager
+            .add_employee(employee("Jane", "Doe"), "admin")
+            .await
+            .unwrap();
+        let john_id = john.id.clone().unwrap();
+        let jane_id = jane.id.clone().unwrap();
+
+        let archived = file_manager
+            .archive_employee(&john_id, "Duplicate record", "admin")
+            .await
+            .unwrap();
+        assert_eq!(archived.version, 2);
+        assert_eq!(archived.archived.unwrap().reason, "Duplicate record");
+        assert!(file_manager.get_employee(&john_id).is_none());
+        assert!(!file_manager.check_employee_exists("John", "Doe"));
+        assert_eq!(file_manager.list_employees(), vec![jane.clone()]);
+        assert_eq!(file_manager.list_archived_employees().len(), 1);
+
+        // archived records cannot be edited until they are restored
+        let result = file_manager
+            .update_employee(&john_id, john.clone(), None, "admin")
+            .await;
+        assert!(matches!(
+            result,
+            Err(DataStoreError::EmployeeNotFound { .. })
+        ));
+
+        let restored = file_manager
+            .restore_archived_employee(&john_id, "admin")
+            .await
+            .unwrap();
+        assert_eq!(restored.version, 3);
+        assert_eq!(restored.archived, None);
+        assert_eq!(file_manager.get_employee_history(&john_id).len(), 3);
+
+        file_manager
+            .archive_employee(&jane_id, "Left the company", "admin")
+            .await
+            .unwrap();
+        // nothing is old enough yet
+        let cutoff = Utc::now() - chrono::Duration::days(30);
+        assert!(file_manager
+            .purge_archived_employees(cutoff)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let purged = file_manager
+            .purge_archived_employees(Utc::now())
+            .await
+            .unwrap();
+        assert_eq!(purged, vec![jane_id.clone()]);
+        let reloaded = read_only_from(&dir);
+        assert!(reloaded.list_archived_employees().is_empty());
+        assert!(reloaded.get_employee_history(&jane_id).is_empty());
+        assert_eq!(reloaded.get_employee(&john_id), Some(restored));
+    }
+
+    #[tokio::test]
+    async fn test_import_is_all_or_nothing() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = file_manager(&dir);
+        file_manager
+            .add_employee(employee("John", "Doe"), "admin")
+            .await
+            .unwrap();
+
+        let result = file_manager
+            .import_employees(
+                vec![employee("Jane", "Doe"), employee("John", "Doe")],
+                "cli",
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(DataStoreError::EmployeeAlreadyExists { .. })
+        ));
+        assert_eq!(file_manager.list_employees().len(), 1);
+
+        let imported = file_manager
+            .import_employees(
+                vec![employee("Jane", "Doe"), employee("Ada", "Lovelace")],
+                "cli",
+            )
+            .await
+            .unwrap();
+        assert!(imported.iter().all(|employee| employee.version == 1));
+        let reloaded = read_only_from(&dir);
+        assert_eq!(reloaded.list_employees().len(), 3);
+        assert!(reloaded.verify().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_adds_of_a_name() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = Arc::new(file_manager(&dir));
+        let adds: Vec<_> = (0..4)
+            .map(|_| {
+                let file_manager = file_manager.clone();
+                tokio::spawn(async move {
+                    file_manager
+                        .add_employee(employee("John", "Doe"), "admin")
+                        .await
+                })
+            })
+            .collect();
+        let mut added = 0;
+        for add in adds {
+            match add.await.unwrap() {
+                Ok(_) => added += 1,
+                Err(error) => assert!(matches!(
+                    error,
+                    DataStoreError::EmployeeAlreadyExists { .. }
+                )),
+            }
+        }
+        assert_eq!(added, 1);
+        assert_eq!(file_manager.list_employees().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_persisted() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = file_manager(&dir);
+
+        let john = file_manager
+            .add_employee(employee("John", "Doe"), "admin")
+            .await
+            .unwrap();
+        let jane = file_manager
+            .add_employee(employee("Jane", "Doe"), "admin")
+            .await
+            .unwrap();
+        file_manager
+            .archive_employee(john.id.as_deref().unwrap(), "Left the company", "admin")
+            .await
+            .unwrap();
+
+        let reloaded = read_only_from(&dir);
+        assert!(reloaded.get_employee(john.id.as_deref().unwrap()).is_none());
+        assert_eq!(
+            reloaded.get_employee(jane.id.as_deref().unwrap()),
+            Some(jane.clone())
+        );
+        let (changes, more) = reloaded.changes_since(0, 10);
+        let changes: Vec<(u64, Option<&str>, ChangeKind)> = changes
+            .iter()
+            .map(|change| (change.sequence, Some(change.id.as_str()), change.change))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (2, jane.id.as_deref(), ChangeKind::Created),
+                (3, john.id.as_deref(), ChangeKind::Deleted)
+            ]
+        );
+        assert!(!more);
+
+        // data of a release without the change feed
+        fs::remove_file(dir.path().join("changes.json")).unwrap();
+        let (changes, _) = read_only_from(&dir).changes_since(0, 10);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].change, ChangeKind::Created);
+    }
+
+    #[tokio::test]
+    async fn test_onboard_employees() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = file_manager(&dir);
+        let mut ids = vec![];
+        for (first_name, last_name) in [("John", "Doe"), ("Jane", "Doe"), ("Jim", "Doe")] {
+            let employee = file_manager
+                .add_employee(employee(first_name, last_name), "admin")
+                .await
+                .unwrap();
+            ids.push(employee.id.unwrap());
+        }
+        let results = file_manager
+            .onboard_employees(vec![(ids[2].clone(), "S3cret!".to_string())], "admin")
+            .await
+            .unwrap();
+        assert_eq!(results[0].as_ref().unwrap().handle.as_deref(), Some("jdoe"));
+
+        // every Doe of the batch starts as `jdoe`
+        let onboardings = [&ids[0], &ids[1], &ids[2], &"unknown".to_string(), &ids[0]]
+            .iter()
+            .map(|id| (id.to_string(), "S3cret!".to_string()))
+            .collect();
+        let results = file_manager
+            .onboard_employees(onboardings, "admin")
+            .await
+            .unwrap();
+        let john = results[0].as_ref().unwrap();
+        let jane = results[1].as_ref().unwrap();
+        assert_ne!(john.handle, jane.handle);
+        assert!(john.handle.as_deref().unwrap().starts_with("jdoe"));
+        assert_ne!(john.handle.as_deref(), Some("jdoe"));
+        assert_eq!(
+            jane.avaya_email,
+            Some(format!("{}@avaya.com", jane.handle.as_deref().unwrap()))
+        );
+        assert_eq!(jane.version, 2);
+        assert!(matches!(
+            results[2],
+            Err(DataStoreError::AlreadyOnboarded { .. })
+        ));
+        assert!(matches!(
+            results[3],
+            Err(DataStoreError::EmployeeNotFound { .. })
+        ));
+        assert!(matches!(
+            results[4],
+            Err(DataStoreError::AlreadyOnboarded { .. })
+        ));
+        let reloaded = read_only_from(&dir);
+        assert_eq!(reloaded.get_employee(&ids[0]).as_ref(), Some(john));
+        assert_eq!(reloaded.get_employee_history(&ids[1]).len(), 2);
+        drop(reloaded);
+
+        // a batch that cannot be written leaves every employee pending
+        let pending = file_manager
+            .add_employee(employee("Joe", "Doe"), "admin")
+            .await
+            .unwrap();
+        let pending_id = pending.id.clone().unwrap();
+        file_manager.close().await.unwrap();
+        let result = file_manager
+            .onboard_employees(vec![(pending_id.clone(), "S3cret!".to_string())], "admin")
+            .await;
+        assert!(result.is_err());
+        assert_eq!(file_manager.get_employee(&pending_id), Some(pending));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_onboardings_get_distinct_handles() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = Arc::new(file_manager(&dir));
+        let mut pending = vec![];
+        for first_name in ["John", "Jane", "Jim", "Joe"] {
+            let employee = file_manager
+                .add_employee(employee(first_name, "Doe"), "admin")
+                .await
+                .unwrap();
+            pending.push(employee);
+        }
+
+        let tasks: Vec<_> = pending
+            .into_iter()
+            .map(|employee| {
+                let file_manager = file_manager.clone();
+                tokio::spawn(async move {
+                    let version = employee.version;
+                    file_manager
+                        .onboard_employee(employee, "S3cret!".to_string(), Some(version), "admin")
+                        .await
+                        .unwrap()
+                })
+            })
+            .collect();
+        let mut handles = HashSet::new();
+        for task in tasks {
+            let employee = task.await.unwrap();
+            assert_eq!(employee.onboarded, Some(true));
+            assert_eq!(employee.password.as_deref(), Some("S3cret!"));
+            assert!(handles.insert(employee.handle.unwrap()));
+        }
+        assert!(handles.contains("jdoe"));
+
+        // the version is still checked
+        let late = file_manager
+            .add_employee(employee("Jack", "Doe"), "admin")
+            .await
+            .unwrap();
+        assert!(matches!(
+            file_manager
+                .onboard_employee(late, "S3cret!".to_string(), Some(0), "admin")
+                .await,
+            Err(DataStoreError::VersionConflict { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_verify_and_migrate_legacy_data() {
+        let dir = TempDir::new().unwrap();
+        let paths = DataPaths::new(dir.path());
+        create_persistence_store(&paths).unwrap();
+        // records written before versions and history existed
+        let legacy = employee("John", "Doe");
+        let id = legacy.id.clone().unwrap();
+        fs::write(
+            &paths.employee_file,
+            serde_json::to_string(&HashMap::from([(id.clone(), legacy)])).unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            &paths.admin_file,
+            r#"{"root": {"id": "root", "password": "secret"}}"#,
+        )
+        .unwrap();
+
+        let problems = read_only_from(&dir).verify().unwrap();
+        assert_eq!(
+            problems,
+            vec![
+                format!("employee {id}: has no version"),
+                format!("employee {id}: has no history"),
+                "admin root: password is not hashed".to_string(),
+            ]
+        );
+
+        let file_manager = file_manager_from(&dir);
+        assert_eq!(file_manager.migrate().await.unwrap(), vec![id.clone()]);
+        assert_eq!(
+            migrate_admin_passwords(&file_manager).await.unwrap(),
+            vec!["root".to_string()]
+        );
+        assert!(file_manager.verify().unwrap().is_empty());
+        assert_eq!(file_manager.get_employee(&id).unwrap().version, 1);
+        assert_eq!(
+            file_manager.get_employee_history(&id)[0].actor,
+            MIGRATION_ACTOR
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_writes_are_all_persisted() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = Arc::new(file_manager(&dir));
+
+        let tasks: Vec<_> = (0..50)
+            .map(|index| {
+                let file_manager = file_manager.clone();
+                tokio::spawn(async move {
+                    let employee = employee("Employee", &index.to_string());
+                    file_manager.add_employee(employee, "admin").await.unwrap();
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(file_manager.list_employees().len(), 50);
+        assert_eq!(read_only_from(&dir).list_employees().len(), 50);
+    }
+
+    // Load test: readers keep running at full speed while writers are
+    // persisting, compared with the same readers alone. Run with
+    // `cargo test -- --ignored load_test`.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    #[ignore]
+    async fn load_test_reads_during_writes() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = Arc::new(file_manager(&dir));
+        for index in 0..1000 {
+            let mut employee = employee("Employee", &index.to_string());
+            employee.handle = Some(format!("employee{index}"));
+            file_manager.add_employee(employee, "admin").await.unwrap();
+        }
+
+        // reads by 16 readers until `duration` has elapsed
+        let read_for = |duration: Duration| {
+            let started = Instant::now();
+            let readers: Vec<_> = (0..16)
+                .map(|reader| {
+                    let file_manager = file_manager.clone();
+                    tokio::spawn(async move {
+                        let mut reads = 0u64;
+                        while started.elapsed() < duration {
+                            let handle = format!("employee{}", (reads + reader) % 1000);
+                            assert!(file_manager.get_employee_by_handle(&handle).is_some());
+                            reads += 1;
+                            if reads.is_multiple_of(100) {
+                                tokio::task::yield_now().await;
+                            }
+                        }
+                        reads
+                    })
+                })
+                .collect();
+            async move {
+                let mut reads = 0;
+                for reader in readers {
+                    reads += reader.await.unwrap();
+                }
+                reads
+            }
+        };
+
+        let duration = Duration::from_secs(3);
+        let baseline = read_for(duration).await;
+
+        let started = Instant::now();
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let file_manager = file_manager.clone();
+                tokio::spawn(async move {
+                    let mut writes = 0u64;
+                    while started.elapsed() < duration {
+                        let employee = employee("Writer", &format!("{writer}-{writes}"));
+                        file_manager.add_employee(employee, "admin").await.unwrap();
+                        writes += 1;
+                    }
+                    writes
+                })
+            })
+            .collect();
+        let reads = read_for(duration).await;
+        let mut writes = 0;
+        for writer in writers {
+            writes += writer.await.unwrap();
+        }
+
+        assert!(writes > 0);
+        // the writers take a share of the worker threads, not a lock the
+        // readers wait for
+        assert!(
+            reads * 2 > baseline,
+            "{reads} reads during {writes} writes, {baseline} alone"
+        );
     }
 }
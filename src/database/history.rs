@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serde_json::Value;
+
+use crate::models::{
+    employee_models::Employee,
+    history_models::{FieldChange, Revision},
+};
+
+// fields whose values never appear in the history
+const REDACTED_FIELDS: [&str; 1] = ["password"];
+const REDACTED_VALUE: &str = "********";
+
+// Revisions of every employee, oldest first, keyed by employee id.
+pub type EmployeeHistory = HashMap<String, Vec<Revision>>;
+
+// field-level diff between two versions of an employee
+pub fn diff_employees(before: Option<&Employee>, after: &Employee) -> Vec<FieldChange> {
+    let before = before
+        .map(|employee| serde_json::to_value(employee).unwrap())
+        .unwrap_or(Value::Null);
+    let after = serde_json::to_value(after).unwrap();
+
+    after
+        .as_object()
+        .unwrap()
+        .iter()
+        .filter(|(field, _)| !matches!(field.as_str(), "id" | "version"))
+        .filter_map(|(field, new)| {
+            let old = before.get(field).cloned().unwrap_or(Value::Null);
+            if &old == new {
+                return None;
+            }
+            let (old, new) = if REDACTED_FIELDS.contains(&field.as_str()) {
+                (redact(&old), redact(new))
+            } else {
+                (old, new.clone())
+            };
+            Some(FieldChange {
+                field: field.clone(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+fn redact(value: &Value) -> Value {
+    match value {
+        Value::Null => Value::Null,
+        _ => Value::String(REDACTED_VALUE.to_string()),
+    }
+}
+
+// append a revision for `after`, the record just written by `actor`
+pub fn record_revision(
+    history: &mut EmployeeHistory,
+    before: Option<&Employee>,
+    after: &Employee,
+    actor: &str,
+    restored_from: Option<u64>,
+) {
+    let mut snapshot = after.clone();
+    snapshot.password = None;
+
+    let revision = Revision {
+        version: after.version,
+        actor: actor.to_string(),
+        timestamp: Utc::now(),
+        changes: diff_employees(before, after),
+        restored_from,
+        snapshot,
+    };
+    history
+        .entry(after.id.clone().unwrap_or_default())
+        .or_default()
+        .push(revision);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn employee() -> Employee {
+        Employee {
+            id: Some("1".to_string()),
+            first_name: "John".to_string(),
+            last_name: "Doe".to_string(),
+            personal_email: Some("john@example.com".to_string()),
+            avaya_email: None,
+            age: 30,
+            diploma: "MSc".to_string(),
+            onboarded: Some(false),
+            handle: None,
+            password: None,
+            secure_password: Some(false),
+            version: 1,
+            archived: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_lists_changed_fields_only() {
+        let before = employee();
+        let mut after = employee();
+        after.age = 31;
+        after.version = 2;
+
+        let changes = diff_employees(Some(&before), &after);
+        assert_eq!(
+            changes,
+            vec![FieldChange {
+                field: "age".to_string(),
+                old: Value::from(30),
+                new: Value::from(31),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_credentials_are_redacted() {
+        let before = employee();
+        let mut after = employee();
+        after.password = Some("Secret!12".to_string());
+
+        let mut history = EmployeeHistory::new();
+        record_revision(&mut history, Some(&before), &after, "admin", None);
+
+        let revision = &history["1"][0];
+        assert_eq!(revision.changes[0].field, "password");
+        assert_eq!(revision.changes[0].old, Value::Null);
+        assert_eq!(revision.changes[0].new, Value::from(REDACTED_VALUE));
+        assert_eq!(revision.snapshot.password, None);
+        assert_eq!(revision.actor, "admin");
+    }
+}
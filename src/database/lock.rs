@@ -0,0 +1,56 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+use std::process;
+
+use log::info;
+
+const LOCK_FILE: &str = ".lock";
+
+// Advisory lock on a data directory, held for as long as the value lives.
+// The lock file records the pid of the owner so a second process can say
+// who is holding it. The OS releases the lock when the process exits, even
+// if it crashes, so a stale lock file never blocks a restart.
+#[derive(Debug)]
+pub struct DataDirLock {
+    _file: File,
+}
+
+impl DataDirLock {
+    pub fn acquire(data_dir: &Path) -> io::Result<Self> {
+        let path = data_dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut owner = String::new();
+                file.read_to_string(&mut owner)?;
+                let owner = match owner.trim() {
+                    "" => "another process".to_string(),
+                    pid => format!("process {pid}"),
+                };
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!(
+                        "data directory {data_dir:?} is locked by {owner}; \
+                         stop it or open the data read-only"
+                    ),
+                ));
+            }
+            Err(TryLockError::Error(error)) => return Err(error),
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", process::id())?;
+        file.sync_all()?;
+        info!("Locked data directory {:?}", data_dir);
+        Ok(DataDirLock { _file: file })
+    }
+}
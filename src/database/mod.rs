@@ -1,2 +1,10 @@
+pub mod backup;
+pub mod changes;
+pub mod employee_store;
+pub mod encryption;
 pub mod file_manager;
+pub mod history;
+pub mod lock;
 pub mod persistence;
+pub mod retention;
+pub mod writer;
@@ -3,94 +3,163 @@ use log::{debug, info};
 use std::{
     collections::HashMap,
     fs::{self, File},
-    io::Result,
-    path::Path,
+    io::{ErrorKind, Result},
+    path::{Path, PathBuf},
 };
 
-use crate::{models::admin_models::Admin, utils::password_utils::hash_password};
+use crate::{
+    database::{encryption::MasterKey, file_manager::FileManager, writer::write_atomically},
+    models::admin_models::Admin,
+    utils::password_utils::{hash_password, is_hashed_password},
+};
 
-const DATA_DIR: &str = "data";
-pub const ADMIN_DATA_FILE: &str = "data/admin.json";
-pub const EMPLOYEE_DATA_FILE: &str = "data/employees.json";
+pub const DEFAULT_DATA_DIR: &str = "data";
+
+// Locations of the data files, all inside one data directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataPaths {
+    pub data_dir: PathBuf,
+    pub admin_file: PathBuf,
+    pub employee_file: PathBuf,
+    pub history_file: PathBuf,
+    pub changes_file: PathBuf,
+    pub keys_file: PathBuf,
+    pub sessions_file: PathBuf,
+}
 
-pub fn create_persistence_store() -> Result<()> {
-    if Path::new(DATA_DIR).exists() {
-        info!("Persistence directory already exists: {DATA_DIR:?}");
-    } else {
-        info!("Creating Persistence directory : {DATA_DIR:?}");
-        fs::create_dir_all(DATA_DIR)?
+impl DataPaths {
+    pub fn new(data_dir: impl Into<PathBuf>) -> Self {
+        let data_dir = data_dir.into();
+        DataPaths {
+            admin_file: data_dir.join("admin.json"),
+            employee_file: data_dir.join("employees.json"),
+            history_file: data_dir.join("history.json"),
+            changes_file: data_dir.join("changes.json"),
+            keys_file: data_dir.join("keys.json"),
+            sessions_file: data_dir.join("sessions.json"),
+            data_dir,
+        }
     }
+}
 
-    if Path::new(ADMIN_DATA_FILE).exists() {
-        info!("Admin Persistence file already exists: {ADMIN_DATA_FILE:?}");
+pub fn create_persistence_store(paths: &DataPaths) -> Result<()> {
+    let data_dir = &paths.data_dir;
+    if data_dir.exists() {
+        info!("Persistence directory already exists: {data_dir:?}");
     } else {
-        info!("Creating Admin Persistence file : {ADMIN_DATA_FILE:?}");
-        File::create(ADMIN_DATA_FILE)?;
+        info!("Creating Persistence directory : {data_dir:?}");
+        fs::create_dir_all(data_dir)?
     }
 
-    if Path::new(EMPLOYEE_DATA_FILE).exists() {
-        info!("Employee Persistence file already exists: {EMPLOYEE_DATA_FILE:?}");
+    create_persistence_file("Admin", &paths.admin_file)?;
+    create_persistence_file("Employee", &paths.employee_file)?;
+    create_persistence_file("History", &paths.history_file)?;
+
+    Ok(())
+}
+
+// open the data store, with the encryption key of the environment; a
+// read-only store is neither created nor locked
+pub fn open_file_manager(paths: &DataPaths, read_only: bool) -> Result<FileManager> {
+    // sensitive employee fields are encrypted on disk when a key is configured
+    let master_key = MasterKey::from_env()?;
+    if read_only {
+        FileManager::open_read_only(paths, master_key)
     } else {
-        info!("Creating Employee Persistence file : {EMPLOYEE_DATA_FILE:?}");
-        File::create(EMPLOYEE_DATA_FILE)?;
+        create_persistence_store(paths)?;
+        FileManager::new(paths, master_key)
     }
+}
 
-    Ok(())
+// sessions saved by the previous run, none when the file does not exist
+pub fn load_sessions(path: &Path) -> Result<HashMap<String, String>> {
+    match fs::read_to_string(path) {
+        Ok(content) if content.trim().is_empty() => Ok(HashMap::new()),
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(error) => Err(error),
+    }
 }
 
-pub async fn create_admin(admin: Admin) -> Result<bool> {
-    let admin_exists = check_admin_exists(admin.id.clone()).await;
+pub fn save_sessions(path: &Path, sessions: &HashMap<String, String>) -> Result<()> {
+    write_atomically(path, &serde_json::to_string_pretty(sessions)?)
+}
 
-    match admin_exists {
-        Ok(result) => {
-            if result {
-                Ok(false)
-            } else {
-                let admin_file_path = Path::new(ADMIN_DATA_FILE);
-                let data = fs::read_to_string(admin_file_path).expect("Unable to read file");
-                let mut map_admins: HashMap<String, Admin> = HashMap::new();
+fn create_persistence_file(kind: &str, path: &Path) -> Result<()> {
+    if path.exists() {
+        info!("{kind} Persistence file already exists: {path:?}");
+    } else {
+        info!("Creating {kind} Persistence file : {path:?}");
+        File::create(path)?;
+    }
+    Ok(())
+}
 
-                //let mut admins: Vec<Admin> = Vec::new();
-                if fs::metadata(admin_file_path).unwrap().len() != 0 {
-                    //  admins = serde_json::from_str(&data)?;
-                    map_admins = serde_json::from_str(&data)?;
-                }
+pub async fn create_admin(file_manager: &FileManager, admin: Admin) -> Result<bool> {
+    if check_admin_exists(file_manager, admin.id.clone()).await? {
+        return Ok(false);
+    }
 
-                let hashed_password = hash_password(admin.password.unwrap()).await;
+    let hashed_password = hash_password(admin.password.unwrap()).await;
 
-                let new_admin = Admin {
-                    id: admin.id.clone(),
-                    password: Some(hashed_password),
-                };
+    let new_admin = Admin {
+        id: admin.id.clone(),
+        password: Some(hashed_password),
+    };
 
-                map_admins.insert(admin.id.clone(), new_admin.clone());
-                // admins.push(admin.clone());
+    // saved through the file manager, which holds the data directory lock
+    file_manager.add_admin(new_admin.clone()).await?;
+    debug!("saving admin: {new_admin:?}");
 
-                let json: String = serde_json::to_string_pretty(&map_admins)?;
-                fs::write(admin_file_path, json).expect("Unable to write file");
-                debug!("saving admin: {new_admin:?}");
+    Ok(true)
+}
 
-                Ok(true)
-            }
-        }
-        Err(_) => Ok(false),
+// replace the password of an existing admin, returns false when there is no
+// admin with this id
+pub async fn reset_admin_password(
+    file_manager: &FileManager,
+    id: &str,
+    password: String,
+) -> Result<bool> {
+    if !check_admin_exists(file_manager, id.to_string()).await? {
+        return Ok(false);
     }
-}
 
-pub async fn check_admin_exists(id: String) -> Result<bool> {
-    let admin_file_path = Path::new(ADMIN_DATA_FILE);
-    let data = fs::read_to_string(admin_file_path).expect("Unable to read file");
-    let mut map_admins: HashMap<String, Admin> = HashMap::new();
+    let admin = Admin {
+        id: id.to_string(),
+        password: Some(hash_password(password).await),
+    };
+    file_manager.add_admin(admin).await?;
+    info!("Password of admin {id} reset");
+
+    Ok(true)
+}
 
-    //let mut admins: Vec<Admin> = Vec::new();
-    if fs::metadata(admin_file_path).unwrap().len() != 0 {
-        //  admins = serde_json::from_str(&data)?;
-        map_admins = serde_json::from_str(&data)?;
+// hash the admin passwords still stored in clear text, returns the ids of
+// the admins that were migrated
+pub async fn migrate_admin_passwords(file_manager: &FileManager) -> Result<Vec<String>> {
+    let mut migrated = vec![];
+    for admin in file_manager.list_admins() {
+        let Some(password) = admin.password else {
+            continue;
+        };
+        if is_hashed_password(&password) {
+            continue;
+        }
+        let hashed_admin = Admin {
+            id: admin.id.clone(),
+            password: Some(hash_password(password).await),
+        };
+        file_manager.add_admin(hashed_admin).await?;
+        migrated.push(admin.id);
     }
+    Ok(migrated)
+}
 
-    let admin = map_admins.get(&id);
+pub async fn check_admin_exists(file_manager: &FileManager, id: String) -> Result<bool> {
+    let admin = file_manager.get_admin_by_id(&id);
     info!("admin: {admin:?}");
-    let admin_exists = map_admins.contains_key(&id);
+    let admin_exists = admin.is_some();
 
     Ok(admin_exists)
 }
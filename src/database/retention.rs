@@ -0,0 +1,26 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use log::{error, info};
+use tokio::task::JoinHandle;
+
+use crate::database::file_manager::FileManager;
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// purge expired archived employees now and then every hour
+pub fn spawn_purge_job(file_manager: Arc<FileManager>, retention_days: i64) -> JoinHandle<()> {
+    info!("Archived employees are purged after {retention_days} days");
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let cutoff = Utc::now() - chrono::Duration::days(retention_days);
+            match file_manager.purge_archived_employees(cutoff).await {
+                Ok(ids) if ids.is_empty() => {}
+                Ok(ids) => info!("Purged {} archived employees: {:?}", ids.len(), ids),
+                Err(error) => error!("Error purging archived employees: {error}"),
+            }
+        }
+    })
+}
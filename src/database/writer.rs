@@ -0,0 +1,98 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use log::{debug, info};
+use tokio::sync::{mpsc, oneshot};
+
+enum Job {
+    Write {
+        path: PathBuf,
+        content: String,
+        reply: oneshot::Sender<io::Result<()>>,
+    },
+    // answered once every job sent before it is done
+    Flush {
+        reply: oneshot::Sender<()>,
+    },
+}
+
+// Handle to the writer actor: a dedicated OS thread that owns all file
+// writes, so blocking disk I/O never runs on the tokio worker threads.
+// Jobs are processed in the order they were sent.
+#[derive(Debug, Clone)]
+pub struct StoreWriter {
+    sender: mpsc::UnboundedSender<Job>,
+}
+
+impl StoreWriter {
+    pub fn spawn() -> io::Result<Self> {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Job>();
+        thread::Builder::new()
+            .name("red-carpet-writer".to_string())
+            .spawn(move || {
+                while let Some(job) = receiver.blocking_recv() {
+                    match job {
+                        Job::Write {
+                            path,
+                            content,
+                            reply,
+                        } => {
+                            debug!("Writing {:?}", path);
+                            let _ = reply.send(write_atomically(&path, &content));
+                        }
+                        Job::Flush { reply } => {
+                            let _ = reply.send(());
+                        }
+                    }
+                }
+                info!("Store writer stopped");
+            })?;
+        Ok(StoreWriter { sender })
+    }
+
+    // write content to path and wait until it is on disk
+    pub async fn write(&self, path: &Path, content: String) -> io::Result<()> {
+        let (reply, response) = oneshot::channel();
+        let job = Job::Write {
+            path: path.to_path_buf(),
+            content,
+            reply,
+        };
+        self.sender.send(job).map_err(|_| writer_stopped())?;
+        response.await.map_err(|_| writer_stopped())?
+    }
+
+    // wait until the writes already sent are on disk, including those whose
+    // caller stopped waiting for them
+    pub async fn flush(&self) -> io::Result<()> {
+        let (reply, response) = oneshot::channel();
+        self.sender
+            .send(Job::Flush { reply })
+            .map_err(|_| writer_stopped())?;
+        response.await.map_err(|_| writer_stopped())
+    }
+}
+
+fn writer_stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "store writer stopped")
+}
+
+// write to a sibling temp file and rename it over the target, so readers of
+// the file never observe a half-written document
+pub fn write_atomically(path: &Path, content: &str) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp_path)?;
+    file.write_all(content.as_bytes())?;
+    file.flush()?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
@@ -0,0 +1,453 @@
+use std::io::{self, BufWriter, Seek, Write};
+
+use axum::body::{Body, Bytes};
+use chrono::SecondsFormat;
+use log::{info, warn};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::{
+    models::{
+        employee_models::{Employee, SortPosition},
+        export_models::ExportFormat,
+    },
+    utils::xlsx::{Cell, XlsxWriter},
+};
+
+// size of the chunks the export is sent in
+const CHUNK_SIZE: usize = 64 * 1024;
+// chunks written ahead of the client
+const CHUNKS_AHEAD: usize = 4;
+
+// Exported column of the employees; passwords are never exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    FirstName,
+    LastName,
+    PersonalEmail,
+    AvayaEmail,
+    Age,
+    Diploma,
+    Onboarded,
+    Handle,
+    SecurePassword,
+    Version,
+    CreatedAt,
+}
+
+pub const COLUMNS: [Column; 12] = [
+    Column::Id,
+    Column::FirstName,
+    Column::LastName,
+    Column::PersonalEmail,
+    Column::AvayaEmail,
+    Column::Age,
+    Column::Diploma,
+    Column::Onboarded,
+    Column::Handle,
+    Column::SecurePassword,
+    Column::Version,
+    Column::CreatedAt,
+];
+
+fn text(value: Option<&String>) -> Cell {
+    value.map_or(Cell::Empty, |value| Cell::Text(value.clone()))
+}
+
+impl Column {
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Id => "id",
+            Column::FirstName => "first_name",
+            Column::LastName => "last_name",
+            Column::PersonalEmail => "personal_email",
+            Column::AvayaEmail => "avaya_email",
+            Column::Age => "age",
+            Column::Diploma => "diploma",
+            Column::Onboarded => "onboarded",
+            Column::Handle => "handle",
+            Column::SecurePassword => "secure_password",
+            Column::Version => "version",
+            Column::CreatedAt => "created_at",
+        }
+    }
+
+    fn value(self, position: &SortPosition, employee: &Employee) -> Cell {
+        match self {
+            Column::Id => text(employee.id.as_ref()),
+            Column::FirstName => Cell::Text(employee.first_name.clone()),
+            Column::LastName => Cell::Text(employee.last_name.clone()),
+            Column::PersonalEmail => text(employee.personal_email.as_ref()),
+            Column::AvayaEmail => text(employee.avaya_email.as_ref()),
+            Column::Age => Cell::Number(employee.age.into()),
+            Column::Diploma => Cell::Text(employee.diploma.clone()),
+            Column::Onboarded => Cell::Bool(employee.onboarded.unwrap_or(false)),
+            Column::Handle => text(employee.handle.as_ref()),
+            Column::SecurePassword => Cell::Bool(employee.secure_password.unwrap_or(false)),
+            Column::Version => Cell::Number(employee.version),
+            Column::CreatedAt => position.created_at.map_or(Cell::Empty, |created_at| {
+                Cell::Text(created_at.to_rfc3339_opts(SecondsFormat::Secs, true))
+            }),
+        }
+    }
+}
+
+// columns named in `fields`, e.g. `last_name,first_name`, all of them when
+// no field is given
+pub fn parse_columns(fields: Option<&str>) -> Result<Vec<Column>, String> {
+    let Some(fields) = fields else {
+        return Ok(COLUMNS.to_vec());
+    };
+    let mut columns = vec![];
+    for name in fields
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let column = match COLUMNS.iter().find(|column| column.name() == name) {
+            Some(column) => *column,
+            None if name == "password" => {
+                return Err("passwords cannot be exported".to_string());
+            }
+            None => {
+                let names: Vec<&str> = COLUMNS.iter().map(|column| column.name()).collect();
+                return Err(format!(
+                    "cannot export {name:?}, expected some of {}",
+                    names.join(", ")
+                ));
+            }
+        };
+        if !columns.contains(&column) {
+            columns.push(column);
+        }
+    }
+    if columns.is_empty() {
+        return Err("fields must name at least one column".to_string());
+    }
+    Ok(columns)
+}
+
+// spreadsheets run a cell starting with one of these as a formula
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+fn csv_field(cell: Cell) -> String {
+    match cell {
+        // the quote makes the spreadsheet show the text as it is
+        Cell::Text(text) if text.starts_with(FORMULA_PREFIXES) => format!("'{text}"),
+        Cell::Text(text) => text,
+        Cell::Number(number) => number.to_string(),
+        Cell::Bool(value) => value.to_string(),
+        Cell::Empty => String::new(),
+    }
+}
+
+fn json_value(cell: Cell) -> Value {
+    match cell {
+        Cell::Text(text) => Value::String(text),
+        Cell::Number(number) => number.into(),
+        Cell::Bool(value) => Value::Bool(value),
+        Cell::Empty => Value::Null,
+    }
+}
+
+// write `columns` of the employees to `writer`, one row per employee after
+// a header row in CSV and XLSX
+pub fn write_export(
+    format: ExportFormat,
+    columns: &[Column],
+    employees: impl IntoIterator<Item = (SortPosition, Employee)>,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    let header = columns.iter().map(|column| column.name());
+    let cells = |(position, employee): &(SortPosition, Employee)| {
+        columns
+            .iter()
+            .map(|column| column.value(position, employee))
+            .collect::<Vec<_>>()
+    };
+    match format {
+        ExportFormat::Csv => {
+            let mut csv = csv::Writer::from_writer(writer);
+            csv.write_record(header)?;
+            for row in employees {
+                csv.write_record(cells(&row).into_iter().map(csv_field))?;
+            }
+            csv.flush()?;
+        }
+        ExportFormat::Jsonl => {
+            // the columns keep their order, unlike in a `serde_json::Map`
+            for row in employees {
+                writer.write_all(b"{")?;
+                for (index, (name, cell)) in header.clone().zip(cells(&row)).enumerate() {
+                    if index > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    serde_json::to_writer(&mut writer, name)?;
+                    writer.write_all(b":")?;
+                    serde_json::to_writer(&mut writer, &json_value(cell))?;
+                }
+                writer.write_all(b"}\n")?;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Xlsx => {
+            // the sizes of a zip entry are written back into its header, so
+            // the workbook is put together in a temporary file first
+            let mut xlsx = XlsxWriter::new(tempfile::tempfile()?, "Employees")?;
+            let header: Vec<Cell> = header.map(|name| Cell::Text(name.to_string())).collect();
+            xlsx.write_row(&header)?;
+            for row in employees {
+                xlsx.write_row(&cells(&row))?;
+            }
+            let mut workbook = xlsx.finish()?;
+            workbook.rewind()?;
+            io::copy(&mut workbook, &mut writer)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+// sends what is written to the response body, waiting for the client to
+// take the previous chunks
+struct ChannelWriter(mpsc::Sender<io::Result<Bytes>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the client went away"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Body streaming the export, written by a blocking task as the client reads
+// it. Only the serialization is streamed: the matching employees are
+// collected and sorted beforehand, then a few chunks of output are held in
+// memory at a time. An error ends the body early, so that the client does
+// not take a truncated export for a complete one.
+pub fn export_body(
+    format: ExportFormat,
+    columns: Vec<Column>,
+    employees: Vec<(SortPosition, Employee)>,
+) -> Body {
+    let (sender, mut receiver) = mpsc::channel(CHUNKS_AHEAD);
+    tokio::task::spawn_blocking(move || {
+        let count = employees.len();
+        let writer = BufWriter::with_capacity(CHUNK_SIZE, ChannelWriter(sender.clone()));
+        match write_export(format, &columns, employees, writer) {
+            Ok(()) => info!("Exported {count} employees as {format:?}"),
+            Err(error) => {
+                warn!("Export of {count} employees stopped: {error}");
+                let _ = sender.blocking_send(Err(error));
+            }
+        }
+    });
+    Body::from_stream(futures_util::stream::poll_fn(move |context| {
+        receiver.poll_recv(context)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, Method, Request, StatusCode};
+    use http_body_util::BodyExt;
+    use serde_json::json;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        api::employees::EMPLOYEES_PATH,
+        testing::{ada, app, basic_auth, login_admin, send},
+    };
+
+    fn employee(first_name: &str, handle: Option<&str>) -> (SortPosition, Employee) {
+        let employee = Employee {
+            id: Some(format!("id-{first_name}")),
+            first_name: first_name.to_string(),
+            last_name: "Doe, Jr.".to_string(),
+            personal_email: None,
+            avaya_email: handle.map(|handle| format!("{handle}@avaya.com")),
+            age: 30,
+            diploma: "MSc".to_string(),
+            onboarded: Some(handle.is_some()),
+            handle: handle.map(str::to_string),
+            password: handle.map(|_| "S3cret!".to_string()),
+            secure_password: Some(false),
+            version: 2,
+            archived: None,
+        };
+        (SortPosition::new(&employee, None), employee)
+    }
+
+    #[test]
+    fn test_parse_columns() {
+        assert_eq!(parse_columns(None).unwrap(), COLUMNS.to_vec());
+        assert_eq!(
+            parse_columns(Some("last_name, first_name,last_name")).unwrap(),
+            vec![Column::LastName, Column::FirstName]
+        );
+        assert_eq!(
+            parse_columns(Some("handle,password")).unwrap_err(),
+            "passwords cannot be exported"
+        );
+        assert!(parse_columns(Some("salary"))
+            .unwrap_err()
+            .contains("\"salary\""));
+        assert!(parse_columns(Some(" , ")).is_err());
+    }
+
+    #[test]
+    fn test_write_csv_and_jsonl() {
+        let employees = vec![employee("Ada", Some("alovelace")), employee("Bob", None)];
+        let columns = parse_columns(Some("first_name,last_name,handle,age,onboarded")).unwrap();
+
+        let mut csv = vec![];
+        write_export(ExportFormat::Csv, &columns, employees.clone(), &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "first_name,last_name,handle,age,onboarded\n\
+             Ada,\"Doe, Jr.\",alovelace,30,true\n\
+             Bob,\"Doe, Jr.\",,30,false\n"
+        );
+
+        let mut jsonl = vec![];
+        write_export(ExportFormat::Jsonl, &COLUMNS, employees, &mut jsonl).unwrap();
+        let jsonl = String::from_utf8(jsonl).unwrap();
+        let lines: Vec<&str> = jsonl.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(r#"{"id":"id-Ada","first_name":"Ada","#));
+        let bob: Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(bob["handle"], Value::Null);
+        assert!(!jsonl.contains("\"password\""));
+        assert!(!jsonl.contains("S3cret!"));
+    }
+
+    #[test]
+    fn test_csv_neutralizes_formulas() {
+        let (position, mut employee) = employee("=HYPERLINK(\"http://x\")", None);
+        employee.last_name = "-2+3".to_string();
+        employee.diploma = "@SUM(A1)".to_string();
+        employee.personal_email = Some("+1 555".to_string());
+        let columns =
+            parse_columns(Some("first_name,last_name,diploma,personal_email,age")).unwrap();
+
+        let mut csv = vec![];
+        write_export(
+            ExportFormat::Csv,
+            &columns,
+            [(position, employee)],
+            &mut csv,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap().lines().nth(1).unwrap(),
+            "\"'=HYPERLINK(\"\"http://x\"\")\",'-2+3,'@SUM(A1),'+1 555,30"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_body() {
+        let employees: Vec<_> = (0..5000)
+            .map(|index| employee(&format!("Ada{index}"), Some("ada")))
+            .collect();
+        let body = export_body(ExportFormat::Jsonl, COLUMNS.to_vec(), employees);
+        let bytes = body.collect().await.unwrap().to_bytes();
+        let content = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(content.len() > CHUNK_SIZE);
+        assert_eq!(content.lines().count(), 5000);
+        assert!(!content.contains("S3cret!"));
+    }
+
+    #[tokio::test]
+    async fn test_export_employees() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let (_, headers, _) = send(&app, Method::POST, EMPLOYEES_PATH, None, Some(ada())).await;
+        let uri = headers[header::LOCATION].to_str().unwrap().to_string();
+        send(
+            &app,
+            Method::POST,
+            &format!("{uri}:onboard"),
+            Some("*"),
+            None,
+        )
+        .await;
+        let grace =
+            json!({"first_name": "Grace", "last_name": "Hopper", "age": 40, "diploma": "PhD"});
+        send(&app, Method::POST, EMPLOYEES_PATH, None, Some(grace)).await;
+
+        let export = |uri: String, authorization: Option<&str>| {
+            let mut request = Request::get(uri);
+            if let Some(credentials) = authorization {
+                request = request.header(header::AUTHORIZATION, basic_auth(credentials));
+            }
+            let request = request.body(Body::empty()).unwrap();
+            async {
+                let response = app.clone().oneshot(request).await.unwrap();
+                let status = response.status();
+                let headers = response.headers().clone();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                (status, headers, body)
+            }
+        };
+        let download = |query: &str| export(format!("/export/employees?{query}"), None);
+
+        // the dashboard download needs the admin to be logged in
+        let (status, _, body) = download("format=csv").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(!String::from_utf8(body.to_vec())
+            .unwrap()
+            .contains("Lovelace"));
+        login_admin(&app).await;
+
+        let (status, headers, body) =
+            download("format=csv&onboarded=false&fields=last_name,age").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+        let disposition = headers[header::CONTENT_DISPOSITION].to_str().unwrap();
+        assert!(disposition.ends_with(".csv\""), "{disposition}");
+        assert_eq!(body, "last_name,age\nHopper,40\n");
+
+        let (_, headers, body) = download("format=jsonl&sort=-first_name").await;
+        assert_eq!(headers[header::CONTENT_TYPE], "application/x-ndjson");
+        let rows: Vec<Value> = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1]["handle"], "alovelace");
+        assert!(rows.iter().all(|row| row.get("password").is_none()));
+
+        let (_, _, body) = download("format=xlsx").await;
+        assert!(body.starts_with(b"PK\x03\x04"));
+
+        for query in ["fields=password", "format=pdf", "page=2", "sort=salary"] {
+            let (_, headers, body) = download(query).await;
+            assert!(headers[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/html"));
+            assert!(String::from_utf8(body.to_vec())
+                .unwrap()
+                .contains("Error exporting employees"));
+        }
+
+        let api = "/api/v1/employees:export?fields=first_name&sort=first_name".to_string();
+        let (status, headers, body) = export(api.clone(), Some("admin:secret")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+        assert_eq!(body, "first_name\nAda\nGrace\n");
+        let (status, _, _) = export(api.clone(), Some("admin:wrong")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _, _) = export(api, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -1,31 +1,54 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
+    body::Bytes,
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        Multipart, OriginalUri, Path, Query, State,
+    },
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
     response::IntoResponse,
     Extension, Json,
 };
-use axum_auth::AuthBasic;
+use chrono::Utc;
 use log::{debug, info};
 
 use uuid::Uuid;
 
 use crate::{
+    api::AdminAuth,
+    database::backup::list_backups,
+    export,
+    import::{self, ImportFormat},
     models::{
         admin_models::Admin,
+        backup_models::{BackupListResponse, BackupResponse},
+        change_models::{ChangeKind, ChangesQuery, ChangesResponse, EmployeeChange},
         employee_models::{
-            Employee, EmployeeErrorResponse, EmployeeForm, EmployeeListResponse,
-            EmployeeRequestBody, EmployeeResponse, QueryOptions,
+            ArchiveEmployeeForm, CreateEmployeeSchema, Employee, EmployeeErrorResponse,
+            EmployeeForm, EmployeePageResponse, EmployeeRequestBody, EmployeeResponse, ListCursor,
+            QueryOptions, RestoreArchivedEmployeeForm, UpdateEmployeeSchema, MAX_PER_PAGE,
+        },
+        export_models::ExportOptions,
+        history_models::{EmployeeHistoryResponse, RestoreRevisionForm},
+        import_models::{ImportOptions, ImportReport},
+        onboarding_models::{
+            BulkOnboardReport, BulkOnboardRequest, OnboardingResult, OnboardingStatus,
+            MAX_BATCH_SIZE,
         },
+        view_models::FieldsQuery,
     },
     utils::{
+        errors::{ApiError, DataStoreError, Problem},
+        etag::{etag, parse_if_match, IfMatch},
+        pagination::{cursor_link_header, decode_cursor, encode_cursor, Page},
         password_utils::{
-            generate_handle, generate_random_password, generate_session_token, hash_password,
-            verify_hashed_password,
+            generate_random_password, generate_session_token, hash_password, is_hashed_password,
+            validate_token_expiration, verify_hashed_password,
         },
         state::AppState,
     },
+    views::{EmployeeFields, FieldSet, Projection},
 };
 use axum::{
     http::{self, Response},
@@ -38,6 +61,10 @@ use tera::{Context, Tera};
 
 type Templates = Arc<Tera>;
 
+// actors recorded in the employee history
+const ADMIN_ACTOR: &str = "admin";
+const SELF_SERVICE_ACTOR: &str = "self-service";
+
 pub async fn styles() -> impl IntoResponse {
     Response::builder()
         .status(http::StatusCode::OK)
@@ -126,7 +153,7 @@ async fn list_employees_renderer(
 ) -> Html<String> {
     let mut vec_employees = employees_vec;
     vec_employees.sort_by(|x, y| x.first_name.cmp(&y.first_name));
-    context.insert("employees", &vec_employees);
+    context.insert("employees", &Projection::Admin.views(&vec_employees));
     Html(templates.render("dashboard.html", &context).unwrap())
 }
 
@@ -146,7 +173,7 @@ pub async fn edit_employee(
             let employee_result = state.file_manager.get_employee(id.clone().as_str());
             match employee_result {
                 Some(employee) => {
-                    context.insert("employee", &employee);
+                    context.insert("employee", &Projection::Admin.view(&employee));
                     Html(templates.render("edit_form.html", &context).unwrap())
                 }
                 None => {
@@ -168,27 +195,35 @@ pub async fn edit_employee(
     }
 }
 
-pub async fn delete_employee(
+pub async fn handle_delete_form_data(
     State(state): State<AppState>,
-    Path(id): Path<String>,
     Extension(templates): Extension<Templates>,
+    Form(delete_data): Form<ArchiveEmployeeForm>,
 ) -> impl IntoResponse {
     //let token_valid = validate_session_token(state.clone(), "admin".to_string()).await;
     let token_valid = true;
     match token_valid {
         true => {
             let mut context = Context::new();
-            context.insert("title", "Delete Employee");
+            context.insert("title", "Admin Dashboard");
 
-            let delete_result = state.file_manager.delete_employee(id.clone().as_str());
-            match delete_result {
+            // deleted employees go to the trash, from where they can be restored
+            let archive_result = state
+                .file_manager
+                .archive_employee(
+                    delete_data.id.as_str(),
+                    delete_data.reason.trim(),
+                    ADMIN_ACTOR,
+                )
+                .await;
+            match archive_result {
                 Ok(_) => {
                     let employees_vec = state.file_manager.list_employees();
                     list_employees_renderer(context, employees_vec, templates).await
                 }
-                Err(_) => {
+                Err(error) => {
                     let error_response = EmployeeErrorResponse {
-                        error: "Error deleting employee".to_string(),
+                        error: format!("Error deleting employee: {error}"),
                     };
                     error!("{error_response:?}");
                     context.insert("error_message", &error_response);
@@ -205,6 +240,153 @@ pub async fn delete_employee(
     }
 }
 
+pub async fn list_trash(
+    State(state): State<AppState>,
+    Extension(templates): Extension<Templates>,
+) -> impl IntoResponse {
+    if !admin_logged_in(&state).await {
+        return admin_login_required(&templates);
+    }
+    let mut context = Context::new();
+    context.insert("title", "Trash");
+    trash_renderer(context, &state, templates).into_response()
+}
+
+fn trash_renderer(mut context: Context, state: &AppState, templates: Arc<Tera>) -> Html<String> {
+    let employees = state.file_manager.list_archived_employees();
+    context.insert("employees", &Projection::Admin.views(&employees));
+    context.insert("retention_days", &state.config.trash.retention_days);
+    Html(templates.render("trash.html", &context).unwrap())
+}
+
+pub async fn handle_restore_archived_form_data(
+    State(state): State<AppState>,
+    Extension(templates): Extension<Templates>,
+    Form(restore_data): Form<RestoreArchivedEmployeeForm>,
+) -> impl IntoResponse {
+    if !admin_logged_in(&state).await {
+        return admin_login_required(&templates);
+    }
+    let mut context = Context::new();
+    context.insert("title", "Trash");
+
+    let restore_result = state
+        .file_manager
+        .restore_archived_employee(restore_data.id.as_str(), ADMIN_ACTOR)
+        .await;
+
+    match restore_result {
+        Ok(_) => trash_renderer(context, &state, templates).into_response(),
+        Err(error) => {
+            let error_response = EmployeeErrorResponse {
+                error: format!("Error restoring employee: {error}"),
+            };
+            error!("{error_response:?}");
+            context.insert("error_message", &error_response);
+            Html(templates.render("errors.html", &context).unwrap()).into_response()
+        }
+    }
+}
+
+pub async fn import_page(
+    State(state): State<AppState>,
+    Extension(templates): Extension<Templates>,
+) -> impl IntoResponse {
+    if !admin_logged_in(&state).await {
+        return admin_login_required(&templates);
+    }
+    let mut context = Context::new();
+    context.insert("title", "Import Employees");
+    Html(templates.render("import.html", &context).unwrap()).into_response()
+}
+
+// check or import the uploaded file, the report is shown on the same page
+pub async fn handle_import_form_data(
+    State(state): State<AppState>,
+    Extension(templates): Extension<Templates>,
+    multipart: Multipart,
+) -> impl IntoResponse {
+    if !admin_logged_in(&state).await {
+        return admin_login_required(&templates);
+    }
+    let mut context = Context::new();
+    context.insert("title", "Import Employees");
+
+    match import_form_data(&state, multipart).await {
+        Ok(report) => context.insert("report", &report),
+        Err(error) => {
+            error!("Error importing employees: {error}");
+            context.insert("error_message", &error);
+        }
+    }
+    Html(templates.render("import.html", &context).unwrap()).into_response()
+}
+
+async fn import_form_data(
+    state: &AppState,
+    mut multipart: Multipart,
+) -> Result<ImportReport, String> {
+    let mut file = None;
+    let mut options = ImportOptions::default();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|error| error.to_string())?
+    {
+        match field.name() {
+            Some("file") => {
+                let format = field
+                    .file_name()
+                    .and_then(ImportFormat::from_file_name)
+                    .or_else(|| {
+                        field
+                            .content_type()
+                            .and_then(ImportFormat::from_content_type)
+                    });
+                let content = field.bytes().await.map_err(|error| error.to_string())?;
+                file = Some((format, content));
+            }
+            Some("dry_run") => options.dry_run = Some(true),
+            Some("allow_duplicates") => options.allow_duplicates = Some(true),
+            _ => {}
+        }
+    }
+    match file {
+        Some((Some(format), content)) => {
+            import::import(&state.file_manager, format, &content, &options, ADMIN_ACTOR)
+                .await
+                .map_err(|error| error.to_string())
+        }
+        Some((None, _)) => Err("Only .csv and .json files can be imported".to_string()),
+        None => Err("Choose a file to import".to_string()),
+    }
+}
+
+// download of the employees from the dashboard, see `export_employees`
+pub async fn download_export(
+    State(state): State<AppState>,
+    Extension(templates): Extension<Templates>,
+    opts: Result<Query<QueryOptions>, QueryRejection>,
+    options: Result<Query<ExportOptions>, QueryRejection>,
+) -> impl IntoResponse {
+    if !admin_logged_in(&state).await {
+        return admin_login_required(&templates);
+    }
+    match employee_export(&state, opts, options) {
+        Ok(export) => export.into_response(),
+        Err(error) => {
+            let mut context = Context::new();
+            context.insert("title", "Export Employees");
+            let error_response = EmployeeErrorResponse {
+                error: format!("Error exporting employees: {error}"),
+            };
+            error!("{error_response:?}");
+            context.insert("error_message", &error_response);
+            Html(templates.render("errors.html", &context).unwrap()).into_response()
+        }
+    }
+}
+
 pub async fn select_employee(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -219,10 +401,7 @@ pub async fn select_employee(
 
             let employee_result = state.file_manager.get_employee(id.clone().as_str());
             match employee_result {
-                Some(employee) => {
-                    context.insert("employee", &employee);
-                    Html(templates.render("employee.html", &context).unwrap())
-                }
+                Some(employee) => employee_renderer(context, employee, &state, templates),
                 None => {
                     let error_response = EmployeeErrorResponse {
                         error: "Employee not found".to_string(),
@@ -242,20 +421,108 @@ pub async fn select_employee(
     }
 }
 
+// render the employee page with its revision history, newest first
+fn employee_renderer(
+    mut context: Context,
+    employee: Employee,
+    state: &AppState,
+    templates: Arc<Tera>,
+) -> Html<String> {
+    let mut revisions = state
+        .file_manager
+        .get_employee_history(employee.id.clone().unwrap_or_default().as_str());
+    revisions.reverse();
+    context.insert("employee", &Projection::Admin.view(&employee));
+    context.insert("revisions", &revisions);
+    Html(templates.render("employee.html", &context).unwrap())
+}
+
+pub async fn handle_restore_revision_form_data(
+    State(state): State<AppState>,
+    Extension(templates): Extension<Templates>,
+    Form(restore_data): Form<RestoreRevisionForm>,
+) -> impl IntoResponse {
+    if !admin_logged_in(&state).await {
+        return admin_login_required(&templates);
+    }
+    let mut context = Context::new();
+    context.insert("title", "Employee");
+
+    let restore_result = state
+        .file_manager
+        .restore_revision(
+            restore_data.id.as_str(),
+            restore_data.revision,
+            Some(restore_data.version),
+            ADMIN_ACTOR,
+        )
+        .await;
+
+    match restore_result {
+        Ok(employee) => employee_renderer(context, employee, &state, templates).into_response(),
+        Err(error) => {
+            let error_response = EmployeeErrorResponse {
+                error: format!("Error restoring employee: {error}"),
+            };
+            error!("{error_response:?}");
+            context.insert("error_message", &error_response);
+            Html(templates.render("errors.html", &context).unwrap()).into_response()
+        }
+    }
+}
+
 pub async fn handle_edit_form_data(
     State(state): State<AppState>,
     Extension(templates): Extension<Templates>,
-    Form(modified_employee_data): Form<Employee>,
+    Form(mut modified_employee_data): Form<Employee>,
 ) -> impl IntoResponse {
     let mut context = Context::new();
     context.insert("title", "Edit Employee");
 
-    let update_result = state.file_manager.update_employee(
-        modified_employee_data.clone().id.unwrap().as_str(),
-        modified_employee_data,
-    );
+    // the form never holds the stored password, left blank it is kept
+    if modified_employee_data
+        .password
+        .as_deref()
+        .is_none_or(str::is_empty)
+    {
+        if let Some(current) = modified_employee_data
+            .id
+            .as_deref()
+            .and_then(|id| state.file_manager.get_employee(id))
+        {
+            modified_employee_data.password = current.password;
+            modified_employee_data.secure_password = current.secure_password;
+        }
+    } else {
+        modified_employee_data.secure_password = Some(false);
+    }
+
+    let update_result = state
+        .file_manager
+        .update_employee(
+            modified_employee_data.clone().id.unwrap().as_str(),
+            modified_employee_data.clone(),
+            Some(modified_employee_data.version),
+            ADMIN_ACTOR,
+        )
+        .await;
 
     match update_result {
+        Err(DataStoreError::VersionConflict { id, .. }) => {
+            match state.file_manager.get_employee(id.as_str()) {
+                Some(current) => {
+                    conflict_renderer(context, modified_employee_data, current, templates)
+                }
+                None => {
+                    let error_response = EmployeeErrorResponse {
+                        error: "Employee not found".to_string(),
+                    };
+                    error!("{error_response:?}");
+                    context.insert("error_message", &error_response);
+                    Html(templates.render("errors.html", &context).unwrap())
+                }
+            }
+        }
         Ok(_) => {
             let employee_vec = state.file_manager.list_employees();
 
@@ -281,6 +548,50 @@ pub async fn handle_edit_form_data(
     }
 }
 
+// render the edit conflict page comparing the submitted and current values
+fn conflict_renderer(
+    mut context: Context,
+    mine: Employee,
+    current: Employee,
+    templates: Arc<Tera>,
+) -> Html<String> {
+    warn!("Edit conflict on employee {:?}", current.id);
+    let mine = Projection::Admin.view(&mine);
+    let current = Projection::Admin.view(&current);
+    let mine_values = serde_json::to_value(&mine).unwrap();
+    let current_values = serde_json::to_value(&current).unwrap();
+
+    let fields: Vec<serde_json::Value> = mine_values
+        .as_object()
+        .unwrap()
+        .iter()
+        .filter(|(name, _)| !matches!(name.as_str(), "id" | "version"))
+        .map(|(name, mine_value)| {
+            let current_value = &current_values[name];
+            serde_json::json!({
+                "name": name,
+                "mine": mine_value,
+                "current": current_value,
+                "changed": mine_value != current_value,
+            })
+        })
+        .collect();
+
+    context.insert("title", "Edit Conflict");
+    context.insert("fields", &fields);
+    context.insert("mine", &mine);
+    context.insert("current", &current);
+    Html(templates.render("conflict.html", &context).unwrap())
+}
+
+// colleagues shown on the pages of an employee, those done with onboarding
+fn team_directory(state: &AppState) -> Vec<EmployeeFields> {
+    let employees = state.file_manager.list_employees();
+    Projection::Public.views(employees.iter().filter(|employee| {
+        employee.onboarded == Some(true) && employee.secure_password == Some(true)
+    }))
+}
+
 pub async fn handle_personal_data_form_data(
     State(state): State<AppState>,
     Extension(templates): Extension<Templates>,
@@ -309,16 +620,21 @@ pub async fn handle_personal_data_form_data(
                     let mut context = Context::new();
                     context.insert("title", "Personal Data");
 
-                    let update_result = state.file_manager.update_employee(
-                        new_employee.clone().id.unwrap().as_str(),
-                        new_employee.clone(),
-                    );
+                    let update_result = state
+                        .file_manager
+                        .update_employee(
+                            new_employee.clone().id.unwrap().as_str(),
+                            new_employee.clone(),
+                            Some(new_employee.version),
+                            new_employee.handle.clone().unwrap_or_default().as_str(),
+                        )
+                        .await;
 
                     match update_result {
-                        Ok(_) => {
-                            let employees_vec = state.file_manager.list_employees();
-                            context.insert("employees", &employees_vec);
-                            context.insert("employee", &new_employee);
+                        Ok(new_employee) => {
+                            context.insert("employees", &team_directory(&state));
+                            context
+                                .insert("employee", &Projection::SelfService.view(&new_employee));
 
                             Html(
                                 templates
@@ -373,11 +689,16 @@ pub async fn handle_save_form_data(
         handle: None,
         password: None,
         secure_password: Some(false),
+        version: 0,
+        archived: None,
     };
-    let save_result = state.file_manager.add_employee(new_employee.clone());
+    let save_result = state
+        .file_manager
+        .add_employee(new_employee.clone(), SELF_SERVICE_ACTOR)
+        .await;
     match save_result {
         Ok(_) => {
-            context.insert("employee", &new_employee);
+            context.insert("employee", &Projection::SelfService.view(&new_employee));
             Html(templates.render("save_result.html", &context).unwrap())
         }
         Err(_) => {
@@ -402,45 +723,39 @@ pub async fn handle_onboard_form_data(
         true => {
             let mut context = Context::new();
             context.insert("title", "Employee");
-            let mut new_handle = generate_handle(
-                onboarding_employee.first_name.clone(),
-                onboarding_employee.last_name.clone(),
-            )
-            .await;
-
-            // get employee by handle
-            let employee_by_handle_result = state
-                .file_manager
-                .get_employee_by_handle(new_handle.as_str());
-
-            if employee_by_handle_result.is_some() {
-                // update new handle with 6 random digits as suffix
-                new_handle = format!("{}{}", new_handle, rand::random::<u32>() % 100000);
-            }
-
             let employee = Employee {
                 id: onboarding_employee.id.clone(),
                 first_name: onboarding_employee.first_name.clone(),
                 last_name: onboarding_employee.last_name.clone(),
                 personal_email: onboarding_employee.personal_email.clone(),
-                avaya_email: Some(format!("{}@avaya.com", new_handle)),
+                avaya_email: None,
                 age: onboarding_employee.age,
                 diploma: onboarding_employee.diploma.clone(),
-                onboarded: Some(true),
-                handle: Some(new_handle),
-                password: Some(generate_random_password().await),
+                onboarded: Some(false),
+                handle: None,
+                password: None,
                 secure_password: Some(false),
+                version: onboarding_employee.version,
+                archived: None,
             };
+            let password = generate_random_password().await;
+            // shown once, for the technician to hand it over
+            context.insert("password", &password);
 
             let update_result = state
                 .file_manager
-                .update_employee(employee.clone().id.unwrap().as_str(), employee.clone());
+                .onboard_employee(
+                    employee.clone(),
+                    password,
+                    Some(employee.version),
+                    ADMIN_ACTOR,
+                )
+                .await;
 
             match update_result {
-                Ok(_) => {
-                    context.insert("employee", &employee);
+                Ok(employee) => {
                     debug!("----------> {employee:?}");
-                    Html(templates.render("employee.html", &context).unwrap())
+                    employee_renderer(context, employee, &state, templates)
                 }
                 Err(_) => {
                     let error_response = EmployeeErrorResponse {
@@ -461,6 +776,38 @@ pub async fn handle_onboard_form_data(
     }
 }
 
+// onboard the employees checked on the dashboard, the handles, emails and
+// passwords are shown once in the report
+pub async fn handle_bulk_onboard_form_data(
+    State(state): State<AppState>,
+    Extension(templates): Extension<Templates>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> impl IntoResponse {
+    if !admin_logged_in(&state).await {
+        return admin_login_required(&templates);
+    }
+    let mut context = Context::new();
+    context.insert("title", "Onboard Employees");
+    let ids = fields
+        .into_iter()
+        .filter(|(name, _)| name == "ids")
+        .map(|(_, id)| id)
+        .collect::<Vec<_>>();
+    if ids.is_empty() {
+        context.insert("error_message", "Select the pending employees to onboard.");
+        return Html(templates.render("onboard_report.html", &context).unwrap()).into_response();
+    }
+
+    match bulk_onboard(&state, ids, ADMIN_ACTOR).await {
+        Ok(report) => context.insert("report", &report),
+        Err(error) => {
+            error!("Error onboarding employees: {error}");
+            context.insert("error_message", &error.to_string());
+        }
+    }
+    Html(templates.render("onboard_report.html", &context).unwrap()).into_response()
+}
+
 pub async fn reset_password_by_id(
     State(state): State<AppState>,
     Extension(templates): Extension<Templates>,
@@ -478,6 +825,7 @@ pub async fn reset_password_by_id(
             match employee_result {
                 Some(employee) => {
                     let new_password = generate_random_password().await;
+                    context.insert("password", &new_password);
 
                     let modified_employee = Employee {
                         id: employee.id,
@@ -491,18 +839,23 @@ pub async fn reset_password_by_id(
                         handle: employee.handle.clone(),
                         password: Some(new_password),
                         secure_password: Some(false),
+                        version: employee.version,
+                        archived: None,
                     };
 
-                    let update_result = state.file_manager.update_employee(
-                        modified_employee.clone().id.unwrap().as_str(),
-                        modified_employee.clone(),
-                    );
+                    let update_result = state
+                        .file_manager
+                        .update_employee(
+                            modified_employee.clone().id.unwrap().as_str(),
+                            modified_employee.clone(),
+                            Some(modified_employee.version),
+                            ADMIN_ACTOR,
+                        )
+                        .await;
 
                     match update_result {
-                        Ok(_) => {
-                            context.insert("employee", &modified_employee);
-
-                            Html(templates.render("employee.html", &context).unwrap())
+                        Ok(modified_employee) => {
+                            employee_renderer(context, modified_employee, &state, templates)
                         }
                         Err(_) => {
                             let error_response = EmployeeErrorResponse {
@@ -546,17 +899,20 @@ pub async fn secure_password(
             context.insert("title", "Employee");
 
             warn!("employee.handle ---> {:?}", employee.handle);
-            warn!("employee.password ---> {:?}", employee.password);
-
-            let hashed_password = hash_password(employee.password.clone().unwrap()).await;
-            warn!("hashed_password ---> {:?}", hashed_password);
 
             let existing_employee_result = state
                 .file_manager
                 .get_employee(employee.id.clone().unwrap().as_str());
 
             match existing_employee_result {
-                Some(existing_employee) => {
+                // the page no longer holds the password, the stored one is
+                // hashed
+                Some(existing_employee) if existing_employee.password.is_some() => {
+                    let password = existing_employee.password.clone().unwrap();
+                    let hashed_password = match is_hashed_password(&password) {
+                        true => password,
+                        false => hash_password(password).await,
+                    };
                     let modified_employee = Employee {
                         id: existing_employee.id,
                         first_name: existing_employee.first_name.clone(),
@@ -569,18 +925,23 @@ pub async fn secure_password(
                         handle: existing_employee.handle.clone(),
                         password: Some(hashed_password),
                         secure_password: Some(true),
+                        version: employee.version,
+                        archived: None,
                     };
 
-                    let update_result = state.file_manager.update_employee(
-                        modified_employee.clone().id.unwrap().as_str(),
-                        modified_employee.clone(),
-                    );
+                    let update_result = state
+                        .file_manager
+                        .update_employee(
+                            modified_employee.clone().id.unwrap().as_str(),
+                            modified_employee.clone(),
+                            Some(modified_employee.version),
+                            ADMIN_ACTOR,
+                        )
+                        .await;
 
                     match update_result {
-                        Ok(_) => {
-                            context.insert("employee", &modified_employee);
-
-                            Html(templates.render("employee.html", &context).unwrap())
+                        Ok(modified_employee) => {
+                            employee_renderer(context, modified_employee, &state, templates)
                         }
                         Err(_) => {
                             let error_response = EmployeeErrorResponse {
@@ -592,7 +953,7 @@ pub async fn secure_password(
                         }
                     }
                 }
-                None => {
+                _ => {
                     let error_response = EmployeeErrorResponse {
                         error: "Error securing employee".to_string(),
                     };
@@ -622,12 +983,42 @@ pub async fn logout_admin(
     Html(templates.render("admin_login.html", &context).unwrap())
 }
 
+// a session older than the configured lifetime no longer counts as logged in
+async fn drop_expired_session(state: &AppState, key: &str) {
+    let mut sessions = state.sessions.lock().await;
+    if let Some(token) = sessions.get(key) {
+        if !validate_token_expiration(token.clone(), state.config.session.lifetime()).await {
+            warn!("Session of {key} expired");
+            sessions.remove(key);
+        }
+    }
+}
+
+// whether the admin is logged in on the login page
+async fn admin_logged_in(state: &AppState) -> bool {
+    drop_expired_session(state, "admin").await;
+    state.sessions.lock().await.contains_key("admin")
+}
+
+// answer to the pages that need the admin to be logged in
+fn admin_login_required(templates: &Tera) -> axum::response::Response {
+    let mut context = Context::new();
+    context.insert("title", "Administrator Login to Avaya Red Carpet");
+    context.insert("error_message", "Log in as administrator first");
+    (
+        StatusCode::UNAUTHORIZED,
+        Html(templates.render("admin_login.html", &context).unwrap()),
+    )
+        .into_response()
+}
+
 pub async fn login_admin(
     State(state): State<AppState>,
     Extension(templates): Extension<Templates>,
     Form(admin_login_data): Form<Admin>,
 ) -> impl IntoResponse {
     let mut context = Context::new();
+    drop_expired_session(&state, "admin").await;
     if state.sessions.lock().await.contains_key("admin") {
         context.insert("title", "Login to Avaya Red Carpet");
         context.insert("error_message", "Already logged in");
@@ -708,6 +1099,7 @@ pub async fn login_employee(
     let handle = employee_login_data.handle.clone().unwrap();
     let password = employee_login_data.password.clone().unwrap();
 
+    drop_expired_session(&state, &handle).await;
     if let std::collections::hash_map::Entry::Vacant(e) =
         state.sessions.lock().await.entry(handle.clone())
     {
@@ -721,7 +1113,8 @@ pub async fn login_employee(
             .get_employee_by_handle(handle.as_str());
         match employee_result {
             Some(employee) => {
-                if employee.onboarded == Some(false) {
+                // archived employees cannot log in until they are restored
+                if employee.onboarded == Some(false) || employee.archived.is_some() {
                     context.insert("title", "Employee to Avaya Red Carpet");
                     context.insert("error_message", "Invalid credentials");
                     Html(templates.render("login.html", &context).unwrap())
@@ -732,10 +1125,9 @@ pub async fn login_employee(
                     if password_ok {
                         // Store the session token in the state
                         e.insert(token.clone());
-                        let employees_vec = state.file_manager.list_employees();
-                        context.insert("employees", &employees_vec);
+                        context.insert("employees", &team_directory(&state));
                         context.insert("title", "Employee Dashboard");
-                        context.insert("employee", &employee);
+                        context.insert("employee", &Projection::SelfService.view(&employee));
 
                         Html(
                             templates
@@ -776,6 +1168,13 @@ pub async fn login_employee(
 // REST /api/v1 related handlers
 //
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/healthchecker",
+    tag = "v1",
+    responses((status = 200, description = "The server is up", body = Object,
+        example = json!({"status": "success", "message": "Avaya Rust Red Carpet"})))
+)]
 pub async fn health_checker() -> impl IntoResponse {
     const MESSAGE: &str = "Avaya Rust Red Carpet";
 
@@ -787,301 +1186,1237 @@ pub async fn health_checker() -> impl IntoResponse {
     Json(json_response)
 }
 
-pub async fn delete_employee_by_id(
-    State(state): State<AppState>,
-    Path(id): Path<String>,
-) -> Result<Json<EmployeeListResponse>, (StatusCode, Json<EmployeeErrorResponse>)> {
-    let delete_result = state.file_manager.delete_employee(id.as_str());
+// version the client expects from its If-Match header; PUT and PATCH require one
+pub(crate) fn expected_version(headers: &HeaderMap) -> Result<Option<u64>, ApiError> {
+    match parse_if_match(headers) {
+        IfMatch::Version(version) => Ok(Some(version)),
+        IfMatch::Any => Ok(None),
+        IfMatch::Missing => Err(ApiError::PreconditionRequired),
+        IfMatch::Invalid => Err(ApiError::BadRequest("Invalid If-Match header".to_string())),
+    }
+}
 
-    match delete_result {
-        Ok(_) => {
-            let employees_vec = state.file_manager.list_employees();
+// fields of the employees asked for with `fields=`, in the admin projection
+pub(crate) fn requested_fields(
+    fields: Result<Query<FieldsQuery>, QueryRejection>,
+) -> Result<FieldSet, ApiError> {
+    let Query(fields) = fields?;
+    FieldSet::parse(Projection::Admin, fields.fields.as_deref()).map_err(ApiError::BadRequest)
+}
 
-            let json_response = EmployeeListResponse {
-                message: format!("Employee {id:?} deleted successfully"),
-                results: employees_vec.len(),
-                employees: employees_vec,
-            };
+// replace the personal data of an employee by id, the handle, emails and
+// password are kept
+#[utoipa::path(
+    put,
+    path = "/api/v1/employee/{emp_id}",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    params(
+        ("emp_id" = String, Path, description = "Employee id"),
+        ("If-Match" = String, Header, description = "ETag of the employee, or `*`"),
+    ),
+    request_body = CreateEmployeeSchema,
+    responses(
+        (status = 200, description = "Employee replaced", body = EmployeeResponse,
+            headers(("ETag" = String, description = "New version of the employee"))),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "No such employee", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "Another employee has the new name", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The employee changed meanwhile", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Unknown field or invalid employee", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn update_employee_by_id(
+    State(state): State<AppState>,
+    AdminAuth(admin): AdminAuth,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Result<Json<CreateEmployeeSchema>, JsonRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let expected_version = expected_version(&headers)?;
+    let Json(body) = body?;
 
-            debug!("{json_response:?}");
-            Ok(Json(json_response))
-        }
-        Err(_) => {
-            let error_response = EmployeeErrorResponse {
-                error: "Error deleting employee".to_string(),
-            };
-            error!("{error_response:?}");
-            Err((StatusCode::NOT_MODIFIED, Json(error_response)))
+    let current = state
+        .file_manager
+        .get_employee(&id)
+        .ok_or_else(|| DataStoreError::EmployeeNotFound { id: id.clone() })?;
+    let employee = UpdateEmployeeSchema::from(body)
+        .apply(current.clone())
+        .map_err(|field| ApiError::Validation(format!("{field} cannot be removed")))?;
+    employee.validate()?;
+    let renamed =
+        (&employee.first_name, &employee.last_name) != (&current.first_name, &current.last_name);
+    if renamed
+        && state
+            .file_manager
+            .check_employee_exists(&employee.first_name, &employee.last_name)
+    {
+        return Err(DataStoreError::EmployeeAlreadyExists {
+            first_name: employee.first_name,
+            last_name: employee.last_name,
         }
+        .into());
     }
+
+    let employee = state
+        .file_manager
+        .update_employee(&id, employee, expected_version, &admin)
+        .await?;
+
+    let json_response = EmployeeResponse {
+        message: format!("Employee {id:?} updated successfully"),
+        data: Projection::Admin.view(&employee),
+    };
+
+    debug!("{json_response:?}");
+    Ok((
+        [(header::ETAG, etag(employee.version))],
+        Json(json_response),
+    ))
 }
 
-// update employee by id
-pub async fn update_employee_by_id(
+#[utoipa::path(
+    post,
+    path = "/api/v1/employees",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Retries sent with the same key get the first response again"),
+    ),
+    request_body = EmployeeRequestBody,
+    responses(
+        (status = 201, description = "Employee created", body = EmployeeResponse),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "An employee has the same name", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid employee", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn create_employee(
     State(state): State<AppState>,
-    Path(id): Path<String>,
-    Json(body): Json<Employee>,
-) -> Result<Json<EmployeeListResponse>, (StatusCode, Json<EmployeeErrorResponse>)> {
-    // let employee = body.clone();
+    AdminAuth(id): AdminAuth,
+    body: Result<Json<EmployeeRequestBody>, JsonRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Json(body) = body?;
+    let employee_exists = state
+        .file_manager
+        .check_employee_exists(&body.first_name, &body.last_name);
 
-    let update_result = state.file_manager.update_employee(id.as_str(), body);
-    match update_result {
-        Ok(_) => {
-            // list employess
-            let vec_employees = state.file_manager.list_employees();
+    if employee_exists {
+        return Err(DataStoreError::EmployeeAlreadyExists {
+            first_name: body.first_name,
+            last_name: body.last_name,
+        }
+        .into());
+    }
 
-            let json_response = EmployeeListResponse {
-                message: format!("Employee {id:?} updated successfully"),
-                results: vec_employees.len(),
-                employees: vec_employees,
-            };
+    let employee = Employee {
+        id: Some(Uuid::new_v4().to_string()),
+        first_name: body.first_name.clone(),
+        last_name: body.last_name.clone(),
+        personal_email: body.personal_email.clone(),
+        avaya_email: None,
+        age: body.age,
+        diploma: body.diploma.clone(),
+        onboarded: Some(false),
+        handle: None,
+        password: None,
+        secure_password: Some(false),
+        version: 0,
+        archived: None,
+    };
+    employee.validate()?;
+    let employee = state
+        .file_manager
+        .add_employee(employee, id.as_str())
+        .await?;
 
-            debug!("{json_response:?}");
-            Ok(Json(json_response))
-        }
-        Err(_) => {
-            let error_response = EmployeeErrorResponse {
-                error: "Error updating employee".to_string(),
-            };
-            error!("{error_response:?}");
-            Err((StatusCode::NOT_MODIFIED, Json(error_response)))
+    let json_response = EmployeeResponse {
+        message: "Employee created successfully".to_string(),
+        data: Projection::Admin.view(&employee),
+    };
+    debug!("{json_response:?}");
+    Ok((StatusCode::CREATED, Json(json_response)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/employees:import",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    params(
+        ImportOptions,
+        ("Idempotency-Key" = Option<String>, Header, description = "Retries sent with the same key get the first response again"),
+    ),
+    request_body(
+        content(
+            (Vec<EmployeeRequestBody> = "application/json"),
+            (String = "text/csv", example = "first_name,last_name,personal_email,age,diploma\nJane,Doe,jane@example.com,31,MSc"),
+        ),
+        description = "Onboarding requests, a JSON array or CSV with a header row",
+    ),
+    responses(
+        (status = 200, description = "Report of every row, and what was imported", body = ImportReport),
+        (status = 400, description = "The file cannot be read", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "An employee was added meanwhile with the name of a row", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn import_employees(
+    state: &AppState,
+    admin: &str,
+    headers: &HeaderMap,
+    options: ImportOptions,
+    body: &Bytes,
+) -> Result<Json<ImportReport>, ApiError> {
+    let format = match headers.get(header::CONTENT_TYPE) {
+        None => ImportFormat::Json,
+        Some(content_type) => content_type
+            .to_str()
+            .ok()
+            .and_then(ImportFormat::from_content_type)
+            .ok_or_else(|| {
+                ApiError::BadRequest(
+                    "Content-Type must be application/json or text/csv".to_string(),
+                )
+            })?,
+    };
+    let json_response = import::import(&state.file_manager, format, body, &options, admin).await?;
+    debug!("{json_response:?}");
+    Ok(Json(json_response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/employees:onboard",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Retries sent with the same key get the first response again"),
+    ),
+    request_body = BulkOnboardRequest,
+    responses(
+        (status = 200, description = "Handle, email and first password of every onboarded employee, and why the others were not", body = BulkOnboardReport),
+        (status = 400, description = "No ids, or too many", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn onboard_employees(
+    state: &AppState,
+    admin: &str,
+    body: &Bytes,
+) -> Result<Json<BulkOnboardReport>, ApiError> {
+    let Json(request) = Json::<BulkOnboardRequest>::from_bytes(body)?;
+    let json_response = bulk_onboard(state, request.ids, admin).await?;
+    debug!("{} employees onboarded", json_response.onboarded);
+    Ok(Json(json_response))
+}
+
+// `POST /api/v1/employees:{action}`, the actions on many employees at once
+pub async fn employees_action(
+    State(state): State<AppState>,
+    AdminAuth(id): AdminAuth,
+    Path(action): Path<String>,
+    headers: HeaderMap,
+    options: Result<Query<ImportOptions>, QueryRejection>,
+    body: Bytes,
+) -> Result<impl IntoResponse, ApiError> {
+    match action.as_str() {
+        ":import" => {
+            let Query(options) = options?;
+            import_employees(&state, id.as_str(), &headers, options, &body)
+                .await
+                .map(IntoResponse::into_response)
         }
+        ":onboard" => onboard_employees(&state, id.as_str(), &body)
+            .await
+            .map(IntoResponse::into_response),
+        _ => Err(unknown_action(&action)),
     }
 }
 
-pub async fn create_employee(
+// Onboard the pending employees `ids` in a single write, each with a handle
+// no other employee has, and report what happened to every one of them.
+pub(crate) async fn bulk_onboard(
+    state: &AppState,
+    ids: Vec<String>,
+    actor: &str,
+) -> Result<BulkOnboardReport, ApiError> {
+    if ids.is_empty() || ids.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::BadRequest(format!(
+            "ids must list 1 to {MAX_BATCH_SIZE} employees"
+        )));
+    }
+    let mut onboardings = Vec::with_capacity(ids.len());
+    for id in ids {
+        onboardings.push((id, generate_random_password().await));
+    }
+    let results = state
+        .file_manager
+        .onboard_employees(onboardings.clone(), actor)
+        .await?;
+
+    let results: Vec<OnboardingResult> = onboardings
+        .into_iter()
+        .zip(results)
+        .map(|((id, password), result)| match result {
+            Ok(employee) => OnboardingResult {
+                id,
+                status: OnboardingStatus::Onboarded,
+                first_name: Some(employee.first_name),
+                last_name: Some(employee.last_name),
+                handle: employee.handle,
+                avaya_email: employee.avaya_email,
+                password: Some(password),
+                version: Some(employee.version),
+                code: None,
+                error: None,
+            },
+            Err(error) => {
+                let employee = state.file_manager.get_employee(&id);
+                let error = ApiError::Store(error);
+                OnboardingResult {
+                    status: OnboardingStatus::Failed,
+                    first_name: employee.as_ref().map(|e| e.first_name.clone()),
+                    last_name: employee.as_ref().map(|e| e.last_name.clone()),
+                    handle: None,
+                    avaya_email: None,
+                    password: None,
+                    version: None,
+                    code: Some(error.code().to_string()),
+                    error: Some(error.to_string()),
+                    id,
+                }
+            }
+        })
+        .collect();
+    let onboarded = results
+        .iter()
+        .filter(|result| result.status == OnboardingStatus::Onboarded)
+        .count();
+    let failed = results.len() - onboarded;
+    info!("Onboarded {onboarded} employees, {failed} could not be");
+    Ok(BulkOnboardReport {
+        message: format!("{onboarded} employees onboarded, {failed} failed"),
+        onboarded,
+        failed,
+        results,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/employees:export",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    params(
+        ("onboarded" = Option<bool>, Query, description = "Only the employees onboarded or not"),
+        ("secure_password" = Option<bool>, Query, description = "Only the employees whose password was secured or not"),
+        ("q" = Option<String>, Query, description = "Search in the first and last names"),
+        ("sort" = Option<String>, Query, description = "Sort keys, as for the list"),
+        ExportOptions,
+    ),
+    responses(
+        (status = 200, description = "Every matching employee, without passwords, streamed",
+            content(
+                (String = "text/csv"),
+                (String = "application/x-ndjson"),
+                (String = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+            ),
+            headers(("Content-Disposition" = String, description = "File name of the export"))),
+        (status = 400, description = "Invalid format, fields or filters", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn export_employees(
     State(state): State<AppState>,
-    AuthBasic((id, password)): AuthBasic,
-    Json(body): Json<EmployeeRequestBody>,
-) -> Result<Json<EmployeeResponse>, (StatusCode, Json<EmployeeErrorResponse>)> {
-    let admin = state.file_manager.get_admin_by_id(id.as_str());
-
-    match admin {
-        Some(admin) => {
-            if admin.password == password {
-                let employee_exists = state
-                    .file_manager
-                    .check_employee_exists(&body.first_name, &body.last_name);
+    _: AdminAuth,
+    Path(action): Path<String>,
+    opts: Result<Query<QueryOptions>, QueryRejection>,
+    options: Result<Query<ExportOptions>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    if action != ":export" {
+        return Err(unknown_action(&action));
+    }
+    employee_export(&state, opts, options)
+}
 
-                if employee_exists {
-                    let error_response = EmployeeErrorResponse {
-                        error: "Employee already exists".to_string(),
-                    };
-                    warn!("{error_response:?}");
-                    Err((StatusCode::ALREADY_REPORTED, Json(error_response)))
-                } else {
-                    let employee = Employee {
-                        id: Some(Uuid::new_v4().to_string()),
-                        first_name: body.first_name.clone(),
-                        last_name: body.last_name.clone(),
-                        personal_email: body.personal_email.clone(),
-                        avaya_email: None,
-                        age: body.age,
-                        diploma: body.diploma.clone(),
-                        onboarded: Some(false),
-                        handle: None,
-                        password: None,
-                        secure_password: Some(false),
-                    };
-                    let save_result = state.file_manager.add_employee(employee.clone());
-                    match save_result {
-                        Ok(_) => {
-                            let json_response = EmployeeResponse {
-                                message: "Employee created successfully".to_string(),
-                                data: employee,
-                            };
-                            debug!("{json_response:?}");
-                            Ok(Json(json_response))
-                        }
-                        Err(error) => {
-                            debug!("{error:?}");
-                            let error_response = EmployeeErrorResponse {
-                                error: error.to_string(),
-                            };
-                            warn!("{error_response:?}");
-                            Err((StatusCode::NOT_MODIFIED, Json(error_response)))
-                        }
-                    }
-                }
-            } else {
-                let error_response = EmployeeErrorResponse {
-                    error: "Invalid credentials".to_string(),
-                };
-                warn!("{error_response:?}");
-                Err((StatusCode::UNAUTHORIZED, Json(error_response)))
+// `POST /api/v1/employees:{action}` and `GET` only know `import` and `export`
+fn unknown_action(action: &str) -> ApiError {
+    ApiError::NotFound(format!(
+        "Unknown action {:?}",
+        action.trim_start_matches(':')
+    ))
+}
+
+// every employee matching the filters of `opts`, streamed in the format
+// and with the columns of `options`
+pub(crate) fn employee_export(
+    state: &AppState,
+    opts: Result<Query<QueryOptions>, QueryRejection>,
+    options: Result<Query<ExportOptions>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Query(opts) = opts?;
+    let Query(options) = options?;
+    if opts.page.is_some() || opts.per_page.is_some() || opts.cursor.is_some() {
+        return Err(ApiError::BadRequest(
+            "an export has every matching employee, page, per_page and cursor do not apply"
+                .to_string(),
+        ));
+    }
+    let sort = opts.sort_keys().map_err(ApiError::BadRequest)?;
+    let columns = export::parse_columns(options.fields.as_deref()).map_err(ApiError::BadRequest)?;
+    let format = options.format.unwrap_or_default();
+
+    let employees = state.file_manager.query_employees(&opts, &sort);
+    let file_name = format!(
+        "employees-{}.{}",
+        Utc::now().format("%Y%m%d"),
+        format.extension()
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        export::export_body(format, columns, employees),
+    ))
+}
+
+// page of the employees matching `opts`, with the `Link` header to the
+// other pages of the same query; a page is either numbered or follows the
+// position held by a cursor
+pub(crate) fn query_employees(
+    state: &AppState,
+    opts: Result<Query<QueryOptions>, QueryRejection>,
+    fields: &FieldSet,
+    uri: &Uri,
+) -> Result<([(header::HeaderName, HeaderValue); 1], EmployeePageResponse), ApiError> {
+    let Query(mut opts) = opts?;
+    let (page, per_page) = opts.page().map_err(ApiError::BadRequest)?;
+    let cursor = match opts.cursor.as_deref() {
+        None => None,
+        Some(_) if opts.page.is_some() => {
+            return Err(ApiError::BadRequest(
+                "cursor and page cannot be combined".to_string(),
+            ))
+        }
+        Some(cursor) => {
+            let cursor: ListCursor = decode_cursor(cursor)
+                .ok_or_else(|| ApiError::BadRequest("invalid cursor".to_string()))?;
+            if opts.sort.is_some() && opts.sort != cursor.sort {
+                return Err(ApiError::BadRequest(
+                    "the cursor was issued for another sort".to_string(),
+                ));
             }
+            opts.sort = cursor.sort.clone();
+            Some(cursor)
+        }
+    };
+    let sort = opts.sort_keys().map_err(ApiError::BadRequest)?;
+
+    let employees = state.file_manager.query_employees(&opts, &sort);
+    let total = employees.len();
+    let (range, page) = match &cursor {
+        // employees changed or added since the previous page cannot shift
+        // the next one
+        Some(cursor) => {
+            let start = employees
+                .partition_point(|(position, _)| position.compare(&cursor.after, &sort).is_le());
+            (start..(start + per_page).min(total), None)
         }
         None => {
-            let error_response = EmployeeErrorResponse {
-                error: "Invalid credentials".to_string(),
+            let page = Page {
+                page,
+                per_page,
+                total,
             };
-            warn!("{error_response:?}");
-            Err((StatusCode::UNAUTHORIZED, Json(error_response)))
+            (page.range(), Some(page))
         }
+    };
+    let next_cursor = (range.end < total).then(|| {
+        encode_cursor(&ListCursor {
+            sort: opts.sort.clone(),
+            after: employees[range.end - 1].0.clone(),
+        })
+    });
+    let link = match &page {
+        Some(page) => page.link_header(uri),
+        None => cursor_link_header(uri, next_cursor.as_deref()),
+    };
+
+    let employees: Vec<EmployeeFields> = employees[range]
+        .iter()
+        .map(|(_, employee)| fields.view(employee))
+        .collect();
+    let json_response = EmployeePageResponse {
+        message: "Employees list".to_string(),
+        results: employees.len(),
+        total,
+        page: page.map(|page| page.page),
+        per_page,
+        employees,
+        next_cursor,
+    };
+    Ok(([(header::LINK, link)], json_response))
+}
+
+// changes committed after the `since` cursor, oldest first, each employee
+// once with its record as of now
+pub(crate) fn employee_changes(
+    state: &AppState,
+    query: Result<Query<ChangesQuery>, QueryRejection>,
+    uri: &Uri,
+) -> Result<(HeaderMap, ChangesResponse), ApiError> {
+    let Query(query) = query?;
+    let since = match query.since.as_deref() {
+        Some(since) => decode_cursor::<u64>(since)
+            .ok_or_else(|| ApiError::BadRequest("invalid cursor".to_string()))?,
+        None => 0,
+    };
+    let limit = query.limit.unwrap_or(MAX_PER_PAGE);
+    if limit == 0 || limit > MAX_PER_PAGE {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be between 1 and {MAX_PER_PAGE}"
+        )));
+    }
+
+    let (changes, more) = state.file_manager.changes_since(since, limit);
+    let next_cursor = encode_cursor(&changes.last().map_or(since, |change| change.sequence));
+    let mut headers = HeaderMap::new();
+    if more {
+        let next = format!(
+            "<{}?since={next_cursor}&limit={limit}>; rel=\"next\"",
+            uri.path()
+        );
+        headers.insert(header::LINK, HeaderValue::from_str(&next).unwrap());
     }
+    let changes: Vec<EmployeeChange> = changes
+        .into_iter()
+        .map(|change| {
+            let employee = match change.change {
+                ChangeKind::Deleted => None,
+                _ => state
+                    .file_manager
+                    .get_employee(&change.id)
+                    .map(|employee| Projection::Admin.view(&employee)),
+            };
+            EmployeeChange {
+                change: change.change,
+                id: change.id,
+                version: change.version,
+                timestamp: change.timestamp,
+                employee,
+            }
+        })
+        .collect();
+    let json_response = ChangesResponse {
+        message: "Employee changes".to_string(),
+        results: changes.len(),
+        changes,
+        next_cursor,
+    };
+    Ok((headers, json_response))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/employees",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    params(QueryOptions, FieldsQuery),
+    responses(
+        (status = 200, description = "A page of employees, without passwords", body = EmployeePageResponse,
+            headers(("Link" = String, description = "Links to the other pages"))),
+        (status = 400, description = "Invalid query", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+    )
+)]
 pub async fn employees_list(
     State(state): State<AppState>,
-    AuthBasic((id, password)): AuthBasic,
-    opts: Option<Query<QueryOptions>>,
-) -> Result<Json<EmployeeListResponse>, (StatusCode, Json<EmployeeErrorResponse>)> {
-    let admin = state.file_manager.get_admin_by_id(id.as_str());
-    match admin {
-        Some(admin) => {
-            if admin.password == password {
-                let Query(opts) = opts.unwrap_or_default();
-
-                let page = opts.page.unwrap_or(0);
-                let per_page = opts.per_page.unwrap_or(10);
-
-                let mut employees_list = state.file_manager.paginate_employees(page, per_page);
-
-                employees_list.sort_by(|x, y| x.first_name.cmp(&y.first_name));
-                let json_response = EmployeeListResponse {
-                    message: "Employees list".to_string(),
-                    results: employees_list.len(),
-                    employees: employees_list,
-                };
-                debug!("{json_response:?}");
-                Ok(Json(json_response))
-            } else {
-                let error_response = EmployeeErrorResponse {
-                    error: "Invalid credentials".to_string(),
-                };
-                Err((StatusCode::UNAUTHORIZED, Json(error_response)))
-            }
-        }
-        None => {
-            let error_response = EmployeeErrorResponse {
-                error: "Invalid credentials".to_string(),
-            };
-            Err((StatusCode::UNAUTHORIZED, Json(error_response)))
-        }
-    }
+    _: AdminAuth,
+    OriginalUri(uri): OriginalUri,
+    opts: Result<Query<QueryOptions>, QueryRejection>,
+    fields: Result<Query<FieldsQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let fields = requested_fields(fields)?;
+    let (link, json_response) = query_employees(&state, opts, &fields, &uri)?;
+    debug!("{json_response:?}");
+    Ok((link, Json(json_response)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/employees/changes",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    params(ChangesQuery),
+    responses(
+        (status = 200, description = "Changes since the cursor, in commit order", body = ChangesResponse,
+            headers(("Link" = String, description = "Link to the next changes, when there are more"))),
+        (status = 400, description = "Invalid cursor or limit", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn employees_changes(
+    State(state): State<AppState>,
+    _: AdminAuth,
+    OriginalUri(uri): OriginalUri,
+    query: Result<Query<ChangesQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (headers, json_response) = employee_changes(&state, query, &uri)?;
+    debug!("{json_response:?}");
+    Ok((headers, Json(json_response)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/employee/{emp_id}",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    params(("emp_id" = String, Path, description = "Employee id"), FieldsQuery),
+    responses(
+        (status = 200, description = "The employee, without password", body = EmployeeResponse,
+            headers(("ETag" = String, description = "Version of the employee"))),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "No such employee", body = Problem, content_type = "application/problem+json"),
+    )
+)]
 pub async fn get_employee(
     State(state): State<AppState>,
-    AuthBasic((id, password)): AuthBasic,
+    _: AdminAuth,
     Path(emp_id): Path<String>,
-) -> Result<Json<EmployeeResponse>, (StatusCode, Json<EmployeeErrorResponse>)> {
-    let admin = state.file_manager.get_admin_by_id(id.as_str());
-
-    match admin {
-        Some(admin) => {
-            if admin.password == password {
-                let employee_result = state.file_manager.get_employee(emp_id.clone().as_str());
-                match employee_result {
-                    Some(employee) => {
-                        let json_response = EmployeeResponse {
-                            message: "Employee found".to_string(),
-                            data: employee,
-                        };
-                        debug!("{json_response:?}");
-                        Ok(Json(json_response))
-                    }
-                    None => {
-                        let error_response = EmployeeErrorResponse {
-                            error: "Employee not found".to_string(),
-                        };
-                        error!("{error_response:?}");
-                        Err((StatusCode::NOT_FOUND, Json(error_response)))
-                    }
-                }
-            } else {
-                let error_response = EmployeeErrorResponse {
-                    error: "Invalid credentials".to_string(),
-                };
-                Err((StatusCode::UNAUTHORIZED, Json(error_response)))
-            }
-        }
-        None => {
-            let error_response = EmployeeErrorResponse {
-                error: "Invalid credentials".to_string(),
-            };
-            Err((StatusCode::UNAUTHORIZED, Json(error_response)))
-        }
-    }
+    fields: Result<Query<FieldsQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let fields = requested_fields(fields)?;
+    let employee = state
+        .file_manager
+        .get_employee(emp_id.as_str())
+        .ok_or(DataStoreError::EmployeeNotFound { id: emp_id })?;
+    let version = employee.version;
+    let json_response = EmployeeResponse {
+        message: "Employee found".to_string(),
+        data: fields.view(&employee),
+    };
+    debug!("{json_response:?}");
+    Ok(([(header::ETAG, etag(version))], Json(json_response)))
 }
 
+#[utoipa::path(
+    patch,
+    path = "/api/v1/employee/{emp_id}",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    params(
+        ("emp_id" = String, Path, description = "Employee id"),
+        ("If-Match" = String, Header, description = "ETag of the employee, or `*`"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Retries sent with the same key get the first response again"),
+    ),
+    responses(
+        (status = 200, description = "Handle, email and password generated", body = EmployeeResponse,
+            headers(("ETag" = String, description = "New version of the employee"))),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "No such employee", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The employee changed meanwhile", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = Problem, content_type = "application/problem+json"),
+    )
+)]
 pub async fn generate_handle_and_password(
     State(state): State<AppState>,
-    AuthBasic((id, password)): AuthBasic,
+    AdminAuth(id): AdminAuth,
     Path(emp_id): Path<String>,
-) -> impl IntoResponse {
-    let admin = state.file_manager.get_admin_by_id(id.as_str());
-
-    match admin {
-        Some(admin) => {
-            if admin.password == password {
-                let filtered_employee = state.file_manager.get_employee(emp_id.as_str());
-
-                match filtered_employee {
-                    Some(employee) => {
-                        let new_handle = generate_handle(
-                            employee.first_name.clone(),
-                            employee.last_name.clone(),
-                        )
-                        .await;
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let expected_version = expected_version(&headers)?;
+    let employee = state
+        .file_manager
+        .get_employee(emp_id.as_str())
+        .ok_or(DataStoreError::EmployeeNotFound { id: emp_id })?;
 
-                        let updated_employee = Employee {
-                            id: employee.id.clone(),
-                            first_name: employee.first_name.clone(),
-                            last_name: employee.last_name.clone(),
-                            personal_email: employee.personal_email.clone(),
-                            avaya_email: Some(format!("{}@avaya.com", new_handle)),
-                            age: employee.age,
-                            diploma: employee.diploma.clone(),
-                            onboarded: Some(true),
-                            handle: Some(new_handle),
-                            password: Some(generate_random_password().await),
-                            secure_password: Some(false),
-                        };
-
-                        let save_result = state.file_manager.update_employee(
-                            updated_employee.clone().id.unwrap().as_str(),
-                            updated_employee,
-                        );
-
-                        match save_result {
-                            Ok(_) => {
-                                let json_response = EmployeeResponse {
-                                    message: "Employee onboarded successfully".to_string(),
-                                    data: employee,
-                                };
-                                debug!("{json_response:?}");
-                                Ok(Json(json_response))
-                            }
-                            Err(error) => {
-                                debug!("{error:?}");
-                                let error_response = EmployeeErrorResponse {
-                                    error: error.to_string(),
-                                };
-                                Err((StatusCode::NOT_MODIFIED, Json(error_response)))
-                            }
-                        }
-                    }
-                    None => {
-                        let error_response = EmployeeErrorResponse {
-                            error: "Employee not found".to_string(),
-                        };
-                        error!("{error_response:?}");
-                        Err((StatusCode::NOT_FOUND, Json(error_response)))
-                    }
-                }
-            } else {
-                let error_response = EmployeeErrorResponse {
-                    error: "Invalid credentials".to_string(),
-                };
-                Err((StatusCode::UNAUTHORIZED, Json(error_response)))
+    let updated_employee = state
+        .file_manager
+        .onboard_employee(
+            employee,
+            generate_random_password().await,
+            expected_version,
+            id.as_str(),
+        )
+        .await?;
+
+    let json_response = EmployeeResponse {
+        message: "Employee onboarded successfully".to_string(),
+        data: Projection::Admin.view(&updated_employee),
+    };
+    debug!("{json_response:?}");
+    Ok((
+        [(header::ETAG, etag(updated_employee.version))],
+        Json(json_response),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/employee/{emp_id}/history",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    params(("emp_id" = String, Path, description = "Employee id")),
+    responses(
+        (status = 200, description = "Revisions of the employee, oldest first", body = EmployeeHistoryResponse),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "No such employee", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_employee_history(
+    State(state): State<AppState>,
+    _: AdminAuth,
+    Path(emp_id): Path<String>,
+) -> Result<Json<EmployeeHistoryResponse>, ApiError> {
+    if state.file_manager.get_employee(emp_id.as_str()).is_none() {
+        return Err(DataStoreError::EmployeeNotFound { id: emp_id }.into());
+    }
+
+    let revisions = state.file_manager.get_employee_history(emp_id.as_str());
+    let json_response = EmployeeHistoryResponse {
+        message: "Employee history".to_string(),
+        results: revisions.len(),
+        revisions,
+    };
+    debug!("{json_response:?}");
+    Ok(Json(json_response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/employee/{emp_id}/history/{revision}/restore",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    params(
+        ("emp_id" = String, Path, description = "Employee id"),
+        ("revision" = u64, Path, description = "Version to restore"),
+        ("If-Match" = String, Header, description = "ETag of the employee, or `*`"),
+    ),
+    responses(
+        (status = 200, description = "Revision restored", body = EmployeeResponse,
+            headers(("ETag" = String, description = "New version of the employee"))),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "No such employee or revision", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The employee changed meanwhile", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn restore_employee_revision(
+    State(state): State<AppState>,
+    AdminAuth(id): AdminAuth,
+    Path((emp_id, revision)): Path<(String, u64)>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let expected_version = expected_version(&headers)?;
+    let employee = state
+        .file_manager
+        .restore_revision(emp_id.as_str(), revision, expected_version, id.as_str())
+        .await?;
+
+    let version = employee.version;
+    let json_response = EmployeeResponse {
+        message: format!("Employee restored to revision {revision}"),
+        data: Projection::Admin.view(&employee),
+    };
+    debug!("{json_response:?}");
+    Ok(([(header::ETAG, etag(version))], Json(json_response)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/backups",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    responses(
+        (status = 200, description = "Backup written", body = BackupResponse),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn create_backup(
+    State(state): State<AppState>,
+    _: AdminAuth,
+) -> Result<Json<BackupResponse>, ApiError> {
+    let backup = state
+        .file_manager
+        .backup(&state.config.backup_settings())
+        .await
+        .map_err(|error| ApiError::Internal(format!("Error creating backup: {error}")))?;
+    let json_response = BackupResponse {
+        message: format!("Backup {} created successfully", backup.name),
+        backup,
+    };
+    info!("{json_response:?}");
+    Ok(Json(json_response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/backups",
+    tag = "v1",
+    security(("basic_auth" = [])),
+    responses(
+        (status = 200, description = "Backups, newest first", body = BackupListResponse),
+        (status = 401, description = "Invalid credentials", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn backups_list(
+    State(state): State<AppState>,
+    _: AdminAuth,
+) -> Result<Json<BackupListResponse>, ApiError> {
+    let backups = list_backups(&state.config.backup_settings().dir)
+        .map_err(|error| ApiError::Internal(format!("Error listing backups: {error}")))?;
+    let json_response = BackupListResponse {
+        message: "Backups found".to_string(),
+        results: backups.len(),
+        backups,
+    };
+    debug!("{json_response:?}");
+    Ok(Json(json_response))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use axum::{
+        body::Body,
+        http::{Method, Request},
+    };
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        api::employees::EMPLOYEES_PATH,
+        database::persistence::DataPaths,
+        testing::{ada, app, basic_auth, login_admin, send},
+    };
+
+    #[tokio::test]
+    async fn test_v1_checks_admin_password_against_hash() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+
+        let (status, _, body) =
+            send(&app, Method::POST, "/api/v1/employees", None, Some(ada())).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let uri = format!("/api/v1/employee/{}", body["data"]["id"].as_str().unwrap());
+        let (status, _, body) = send(&app, Method::GET, "/api/v1/employees", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"], 1);
+        let (status, _, _) = send(&app, Method::GET, &uri, None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _, _) = send(&app, Method::PATCH, &uri, Some("*"), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // the stored hash is no password
+        let admins: Value = serde_json::from_str(
+            &fs::read_to_string(DataPaths::new(dir.path()).admin_file).unwrap(),
+        )
+        .unwrap();
+        let hash = admins["admin"]["password"].as_str().unwrap();
+        for credentials in ["admin:wrong".to_string(), format!("admin:{hash}")] {
+            for (method, uri) in [
+                (Method::POST, "/api/v1/employees"),
+                (Method::GET, "/api/v1/employees"),
+                (Method::GET, uri.as_str()),
+                (Method::PATCH, uri.as_str()),
+                (Method::PUT, uri.as_str()),
+            ] {
+                let request = Request::builder()
+                    .method(method.clone())
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, basic_auth(&credentials))
+                    .header(header::IF_MATCH, "*")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(json!({"first_name": "Grace"}).to_string()))
+                    .unwrap();
+                let response = app.clone().oneshot(request).await.unwrap();
+                assert_eq!(
+                    response.status(),
+                    StatusCode::UNAUTHORIZED,
+                    "{method} {uri}"
+                );
             }
         }
-        None => {
-            let error_response = EmployeeErrorResponse {
-                error: "Invalid credentials".to_string(),
-            };
-            Err((StatusCode::UNAUTHORIZED, Json(error_response)))
+    }
+
+    #[tokio::test]
+    async fn test_v1_create_validates_the_employee() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let mut minor = ada();
+        minor["age"] = json!(12);
+        let mut no_diploma = ada();
+        no_diploma["diploma"] = json!(" ");
+        for body in [minor, no_diploma] {
+            let (status, headers, _) =
+                send(&app, Method::POST, "/api/v1/employees", None, Some(body)).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(headers[header::CONTENT_TYPE], "application/problem+json");
+        }
+        let (_, _, body) = send(&app, Method::GET, "/api/v1/employees", None, None).await;
+        assert_eq!(body["results"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_v1_onboarding_returns_the_onboarded_employee() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let (_, _, body) = send(&app, Method::POST, "/api/v1/employees", None, Some(ada())).await;
+        let uri = format!("/api/v1/employee/{}", body["data"]["id"].as_str().unwrap());
+
+        let (status, headers, body) = send(&app, Method::PATCH, &uri, Some("*"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::ETAG], "\"2\"");
+        assert_eq!(body["data"]["handle"], "alovelace");
+        assert_eq!(body["data"]["avaya_email"], "alovelace@avaya.com");
+        assert_eq!(body["data"]["onboarded"], true);
+        assert_eq!(body["data"]["version"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_v1_replace_keeps_the_account() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let (_, _, body) = send(&app, Method::POST, "/api/v1/employees", None, Some(ada())).await;
+        let uri = format!("/api/v1/employee/{}", body["data"]["id"].as_str().unwrap());
+        send(&app, Method::PATCH, &uri, Some("*"), None).await;
+
+        let replacement = json!({
+            "first_name": "Ada",
+            "last_name": "Lovelace",
+            "personal_email": "ada@example.com",
+            "age": 37,
+            "diploma": "Maths",
+        });
+        let mut credentials = replacement.clone();
+        credentials["handle"] = json!("admin");
+        credentials["password"] = json!("S3cret!");
+        let mut minor = replacement.clone();
+        minor["age"] = json!(12);
+        for body in [credentials, minor, json!({"first_name": "Ada"})] {
+            let (status, _, _) = send(&app, Method::PUT, &uri, Some("*"), Some(body)).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
         }
+
+        let (status, headers, body) =
+            send(&app, Method::PUT, &uri, Some("\"2\""), Some(replacement)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::ETAG], "\"3\"");
+        assert_eq!(body["data"]["age"], 37);
+        assert_eq!(body["data"]["personal_email"], "ada@example.com");
+        assert_eq!(body["data"]["handle"], "alovelace");
+        assert_eq!(body["data"]["onboarded"], true);
+        assert!(body.get("employees").is_none());
+
+        let (status, _, _) = send(&app, Method::PUT, &uri, Some("\"2\""), Some(ada())).await;
+        assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn test_v1_history_and_restore() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let (_, _, body) = send(&app, Method::POST, "/api/v1/employees", None, Some(ada())).await;
+        let uri = format!("/api/v1/employee/{}", body["data"]["id"].as_str().unwrap());
+        send(&app, Method::PATCH, &uri, Some("*"), None).await;
+
+        let (status, _, body) =
+            send(&app, Method::GET, &format!("{uri}/history"), None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"], 2);
+        let restore = format!("{uri}/history/1/restore");
+        let (status, headers, body) = send(&app, Method::POST, &restore, Some("*"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::ETAG], "\"3\"");
+        // the onboarding of revision 2 is kept
+        assert_eq!(body["data"]["handle"], "alovelace");
+        assert_eq!(body["data"]["onboarded"], true);
+
+        for (method, uri) in [
+            (Method::GET, format!("{uri}/history")),
+            (Method::POST, restore),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(&uri)
+                .header(header::AUTHORIZATION, basic_auth("admin:wrong"))
+                .header(header::IF_MATCH, "*")
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore_form_requires_admin_login() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let (_, _, body) = send(&app, Method::POST, "/api/v1/employees", None, Some(ada())).await;
+        let id = body["data"]["id"].as_str().unwrap().to_string();
+        let uri = format!("/api/v1/employee/{id}");
+        send(&app, Method::PATCH, &uri, Some("*"), None).await;
+
+        let restore = || {
+            Request::post("/restore/employee")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(format!("id={id}&revision=1&version=2")))
+                .unwrap()
+        };
+        let response = app.clone().oneshot(restore()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let (_, _, body) = send(&app, Method::GET, &uri, None, None).await;
+        assert_eq!(body["data"]["version"], 2);
+
+        login_admin(&app).await;
+        let response = app.clone().oneshot(restore()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let (_, _, body) = send(&app, Method::GET, &uri, None, None).await;
+        assert_eq!(body["data"]["version"], 3);
+        assert_eq!(body["data"]["handle"], "alovelace");
+    }
+
+    #[tokio::test]
+    async fn test_trash_requires_admin_login() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let (_, _, body) = send(&app, Method::POST, EMPLOYEES_PATH, None, Some(ada())).await;
+        let id = body["data"]["id"].as_str().unwrap().to_string();
+        let uri = format!("{EMPLOYEES_PATH}/{id}");
+        send(&app, Method::DELETE, &uri, Some("*"), None).await;
+
+        let trash = || {
+            Request::get("/trash/employees")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let restore = || {
+            Request::post("/trash/restore")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(format!("id={id}")))
+                .unwrap()
+        };
+        for request in [trash(), restore()] {
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let (status, _, _) = send(&app, Method::GET, &uri, None, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        login_admin(&app).await;
+        let response = app.clone().oneshot(trash()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8(body.to_vec())
+            .unwrap()
+            .contains("Lovelace"));
+        let response = app.clone().oneshot(restore()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let (status, _, _) = send(&app, Method::GET, &uri, None, None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_v1_backups() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let (status, _, body) = send(&app, Method::POST, "/api/v1/admin/backups", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let name = body["backup"]["name"].clone();
+        let (status, _, body) = send(&app, Method::GET, "/api/v1/admin/backups", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"], 1);
+        assert_eq!(body["backups"][0]["name"], name);
+
+        for method in [Method::POST, Method::GET] {
+            let request = Request::builder()
+                .method(method.clone())
+                .uri("/api/v1/admin/backups")
+                .header(header::AUTHORIZATION, basic_auth("admin:wrong"))
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{method}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_v1_change_feed() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        send(&app, Method::POST, "/api/v1/employees", None, Some(ada())).await;
+        let (status, _, body) =
+            send(&app, Method::GET, "/api/v1/employees/changes", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"], 1);
+        assert_eq!(body["changes"][0]["change"], "created");
+
+        let request = Request::get("/api/v1/employees/changes")
+            .header(header::AUTHORIZATION, basic_auth("admin:wrong"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_import_pages_require_admin_login() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let page = |request: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+        let upload = || {
+            let body = "--boundary\r\n\
+                Content-Disposition: form-data; name=\"file\"; filename=\"employees.csv\"\r\n\
+                Content-Type: text/csv\r\n\r\n\
+                first_name,last_name,personal_email,age,diploma\n\
+                Ada,Lovelace,ada@example.com,36,Maths\r\n\
+                --boundary--\r\n";
+            Request::post("/import/employees")
+                .header(
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=boundary",
+                )
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let import_page = || {
+            Request::get("/import/employees")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        for request in [import_page(), upload()] {
+            let (status, body) = page(request).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert!(body.contains("Log in as administrator first"), "{body}");
+        }
+        let (_, _, body) = send(&app, Method::GET, "/api/v1/employees", None, None).await;
+        assert_eq!(body["results"], 0);
+
+        login_admin(&app).await;
+        let (status, _) = page(import_page()).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = page(upload()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("1 accepted"), "{body}");
+        let (_, _, body) = send(&app, Method::GET, "/api/v1/employees", None, None).await;
+        assert_eq!(body["results"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_onboarding() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let mut ids = vec![];
+        // both start as `alovelace`
+        let alan =
+            json!({"first_name": "Alan", "last_name": "Lovelace", "age": 30, "diploma": "BSc"});
+        let grace =
+            json!({"first_name": "Grace", "last_name": "Hopper", "age": 40, "diploma": "PhD"});
+        for body in [ada(), alan, grace] {
+            let (_, _, employee) = send(&app, Method::POST, EMPLOYEES_PATH, None, Some(body)).await;
+            ids.push(employee["data"]["id"].as_str().unwrap().to_string());
+        }
+        send(
+            &app,
+            Method::POST,
+            &format!("{EMPLOYEES_PATH}/{}:onboard", ids[0]),
+            Some("*"),
+            None,
+        )
+        .await;
+
+        let onboard = |form: String| {
+            let request = Request::post("/onboard/employees")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(form))
+                .unwrap();
+            async {
+                let response = app.clone().oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+        let alan = format!("{EMPLOYEES_PATH}/{}", ids[1]);
+        let form = format!("ids={}&ids={}&ids=unknown", ids[0], ids[1]);
+
+        // the form needs the admin to be logged in
+        let (status, page) = onboard(form.clone()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(page.contains("Log in as administrator first"), "{page}");
+        let (_, _, employee) = send(&app, Method::GET, &alan, None, None).await;
+        assert_eq!(employee["data"]["onboarded"], false);
+
+        login_admin(&app).await;
+        let (status, page) = onboard(form).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(page.contains("1 employees onboarded, 2 failed"), "{page}");
+        let (_, _, employee) = send(&app, Method::GET, &alan, None, None).await;
+        let handle = employee["data"]["handle"].as_str().unwrap();
+        assert!(handle.starts_with("alovelace") && handle != "alovelace");
+        assert!(page.contains(handle));
+        assert!(page.contains("already onboarded"));
+
+        let (_, page) = onboard(String::new()).await;
+        assert!(page.contains("Select the pending employees to onboard"));
+
+        let onboard_api = |credentials: &str| {
+            let request = Request::post("/api/v1/employees:onboard")
+                .header(header::AUTHORIZATION, basic_auth(credentials))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json!({ "ids": [ids[2]] }).to_string()))
+                .unwrap();
+            async {
+                let response = app.clone().oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                (status, serde_json::from_slice::<Value>(&body).unwrap())
+            }
+        };
+        let (status, _) = onboard_api("admin:wrong").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, report) = onboard_api("admin:secret").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report["onboarded"], 1);
+        assert_eq!(report["results"][0]["handle"], "ghopper");
+    }
+
+    #[tokio::test]
+    async fn test_v1_import_action() {
+        let dir = TempDir::new().unwrap();
+        let app = app(&dir).await;
+        let import = |credentials: &str| {
+            let request = Request::post("/api/v1/employees:import")
+                .header(header::AUTHORIZATION, basic_auth(credentials))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json!([ada()]).to_string()))
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+        let response = import("admin:wrong").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = import("admin:secret").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let (_, _, body) = send(&app, Method::GET, "/api/v1/employees", None, None).await;
+        assert_eq!(body["results"], 1);
     }
 }
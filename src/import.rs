@@ -0,0 +1,456 @@
+use std::collections::HashMap;
+
+use log::info;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    database::{
+        employee_store::{normalize_email, normalize_name},
+        file_manager::FileManager,
+    },
+    models::{
+        employee_models::{Employee, EmployeeRequestBody},
+        import_models::{ImportOptions, ImportReport, ImportRow, ImportStatus},
+    },
+    utils::errors::DataStoreError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    // header row with the fields of `EmployeeRequestBody`
+    Csv,
+    // array of `EmployeeRequestBody`
+    Json,
+}
+
+impl ImportFormat {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        match mime.to_lowercase().as_str() {
+            "text/csv" | "application/csv" => Some(ImportFormat::Csv),
+            "application/json" => Some(ImportFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        match extension.to_lowercase().as_str() {
+            "csv" => Some(ImportFormat::Csv),
+            "json" => Some(ImportFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(reason: impl ToString) -> DataStoreError {
+    DataStoreError::InvalidImport {
+        reason: reason.to_string(),
+    }
+}
+
+// rows of the file, each one a new employee or the reason it cannot be read;
+// only a file that is not CSV or a JSON array at all is an error
+pub fn parse_rows(
+    format: ImportFormat,
+    content: &[u8],
+) -> Result<Vec<Result<EmployeeRequestBody, String>>, DataStoreError> {
+    match format {
+        ImportFormat::Json => {
+            let rows: Vec<Value> = serde_json::from_slice(content).map_err(invalid)?;
+            Ok(rows
+                .into_iter()
+                .map(|row| serde_json::from_value(row).map_err(|error| error.to_string()))
+                .collect())
+        }
+        ImportFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(content);
+            let headers = reader.headers().map_err(invalid)?.clone();
+            for field in ["first_name", "last_name", "age", "diploma"] {
+                if !headers.iter().any(|header| header == field) {
+                    return Err(invalid(format!("the CSV header has no {field:?} column")));
+                }
+            }
+            Ok(reader
+                .deserialize::<EmployeeRequestBody>()
+                .map(|row| row.map_err(|error| csv_error(&error)))
+                .collect())
+        }
+    }
+}
+
+// message of a CSV row error, without the position already in the report
+fn csv_error(error: &csv::Error) -> String {
+    match error.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => match err.field() {
+            Some(field) => format!("column {}: {}", field + 1, err.kind()),
+            None => err.kind().to_string(),
+        },
+        _ => error.to_string(),
+    }
+}
+
+fn new_employee(body: EmployeeRequestBody) -> Employee {
+    Employee {
+        id: Some(Uuid::new_v4().to_string()),
+        first_name: body.first_name,
+        last_name: body.last_name,
+        personal_email: body.personal_email.filter(|email| !email.trim().is_empty()),
+        avaya_email: None,
+        age: body.age,
+        diploma: body.diploma,
+        onboarded: Some(false),
+        handle: None,
+        password: None,
+        secure_password: Some(false),
+        version: 0,
+        archived: None,
+    }
+}
+
+// Check every row with the rules of `create_employee` against the current
+// employees and the rows before it; returns the report and the employees to
+// import, by row.
+pub fn check_rows(
+    file_manager: &FileManager,
+    rows: Vec<Result<EmployeeRequestBody, String>>,
+) -> (Vec<ImportRow>, Vec<(usize, Employee)>) {
+    let archived_names: HashMap<String, Employee> = file_manager
+        .list_archived_employees()
+        .into_iter()
+        .map(|employee| {
+            let name = normalize_name(&employee.first_name, &employee.last_name);
+            (name, employee)
+        })
+        .collect();
+    // first row of every name and personal email of the file
+    let mut names_in_file: HashMap<String, usize> = HashMap::new();
+    let mut emails_in_file: HashMap<String, usize> = HashMap::new();
+
+    let mut report = Vec::with_capacity(rows.len());
+    let mut employees = vec![];
+    for (index, row) in rows.into_iter().enumerate() {
+        let row_number = index + 1;
+        let body = match row {
+            Ok(body) => body,
+            Err(reason) => {
+                report.push(ImportRow {
+                    row: row_number,
+                    status: ImportStatus::Rejected,
+                    first_name: None,
+                    last_name: None,
+                    reasons: vec![reason],
+                    id: None,
+                });
+                continue;
+            }
+        };
+        let employee = new_employee(body);
+        let name = normalize_name(&employee.first_name, &employee.last_name);
+        let email = employee.personal_email.as_deref().map(normalize_email);
+
+        let mut rejected = vec![];
+        if let Err(error) = employee.validate() {
+            rejected.push(error.to_string());
+        }
+        if file_manager.check_employee_exists(&employee.first_name, &employee.last_name) {
+            let error = DataStoreError::EmployeeAlreadyExists {
+                first_name: employee.first_name.clone(),
+                last_name: employee.last_name.clone(),
+            };
+            rejected.push(error.to_string());
+        }
+        if let Some(first) = names_in_file.get(&name) {
+            rejected.push(format!("same name as row {first}"));
+        }
+
+        let mut doubts = vec![];
+        if let Some(archived) = archived_names.get(&name) {
+            doubts.push(format!(
+                "same name as employee {} in the trash",
+                archived.id.as_deref().unwrap_or_default()
+            ));
+        }
+        if let Some(email) = &email {
+            for existing in file_manager.find_employees_by_personal_email(email) {
+                doubts.push(format!(
+                    "same personal email as employee {} ({} {})",
+                    existing.id.as_deref().unwrap_or_default(),
+                    existing.first_name,
+                    existing.last_name
+                ));
+            }
+            if let Some(first) = emails_in_file.get(email) {
+                doubts.push(format!("same personal email as row {first}"));
+            }
+        }
+
+        names_in_file.entry(name).or_insert(row_number);
+        if let Some(email) = email {
+            emails_in_file.entry(email).or_insert(row_number);
+        }
+        let (status, reasons) = match (rejected.is_empty(), doubts.is_empty()) {
+            (false, _) => (ImportStatus::Rejected, rejected),
+            (true, false) => (ImportStatus::PossibleDuplicate, doubts),
+            (true, true) => (ImportStatus::Accepted, vec![]),
+        };
+        report.push(ImportRow {
+            row: row_number,
+            status,
+            first_name: Some(employee.first_name.clone()),
+            last_name: Some(employee.last_name.clone()),
+            reasons,
+            id: None,
+        });
+        if status != ImportStatus::Rejected {
+            employees.push((row_number, employee));
+        }
+    }
+    (report, employees)
+}
+
+// check the rows of `content` and, unless it is a dry run, import the
+// accepted ones at once, with the possible duplicates when allowed
+pub async fn import(
+    file_manager: &FileManager,
+    format: ImportFormat,
+    content: &[u8],
+    options: &ImportOptions,
+    actor: &str,
+) -> Result<ImportReport, DataStoreError> {
+    let dry_run = options.dry_run.unwrap_or(false);
+    let allow_duplicates = options.allow_duplicates.unwrap_or(false);
+
+    let (mut rows, employees) = check_rows(file_manager, parse_rows(format, content)?);
+    let count = |status| rows.iter().filter(|row| row.status == status).count();
+    let accepted = count(ImportStatus::Accepted);
+    let rejected = count(ImportStatus::Rejected);
+    let possible_duplicates = count(ImportStatus::PossibleDuplicate);
+
+    let mut imported = 0;
+    if !dry_run {
+        let (row_numbers, employees): (Vec<usize>, Vec<Employee>) = employees
+            .into_iter()
+            .filter(|(row, _)| allow_duplicates || rows[row - 1].status == ImportStatus::Accepted)
+            .unzip();
+        if !employees.is_empty() {
+            let employees = file_manager.import_employees(employees, actor).await?;
+            imported = employees.len();
+            for (row, employee) in row_numbers.into_iter().zip(employees) {
+                rows[row - 1].id = employee.id;
+            }
+        }
+        info!("Imported {imported} employees, {rejected} rows rejected");
+    }
+
+    let message = match dry_run {
+        true => "Import checked, nothing was imported".to_string(),
+        false => format!("{imported} employees imported"),
+    };
+    Ok(ImportReport {
+        message,
+        dry_run,
+        accepted,
+        rejected,
+        possible_duplicates,
+        imported,
+        rows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::database::persistence::{create_persistence_store, DataPaths};
+
+    fn file_manager(dir: &TempDir) -> FileManager {
+        let paths = DataPaths::new(dir.path());
+        create_persistence_store(&paths).unwrap();
+        FileManager::new(&paths, None).unwrap()
+    }
+
+    fn body(
+        first_name: &str,
+        last_name: &str,
+        personal_email: Option<&str>,
+    ) -> EmployeeRequestBody {
+        EmployeeRequestBody {
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            personal_email: personal_email.map(str::to_string),
+            age: 30,
+            diploma: "MSc".to_string(),
+        }
+    }
+
+    fn statuses(rows: &[ImportRow]) -> Vec<ImportStatus> {
+        rows.iter().map(|row| row.status).collect()
+    }
+
+    #[test]
+    fn test_parse_rows() {
+        let csv = "first_name, last_name, personal_email, age, diploma\n\
+                   Jane, Doe, , 31, MSc\n\
+                   John, Roe, john@example.com, young, BSc\n";
+        let rows = parse_rows(ImportFormat::Csv, csv.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 2);
+        let jane = rows[0].as_ref().unwrap();
+        assert_eq!((jane.first_name.as_str(), jane.age), ("Jane", 31));
+        assert!(rows[1].as_ref().unwrap_err().contains("column 4"));
+
+        let error = parse_rows(ImportFormat::Csv, b"first_name,last_name\nJane,Doe\n").unwrap_err();
+        assert!(error.to_string().contains("\"age\""), "{error}");
+
+        let json = r#"[{"first_name": "Jane", "last_name": "Doe", "age": 31, "diploma": "MSc"},
+                       {"first_name": "John"}]"#;
+        let rows = parse_rows(ImportFormat::Json, json.as_bytes()).unwrap();
+        assert!(rows[0].is_ok());
+        assert!(rows[1].as_ref().unwrap_err().contains("last_name"));
+        assert!(parse_rows(ImportFormat::Json, b"{}").is_err());
+
+        assert_eq!(
+            ImportFormat::from_content_type("text/csv; charset=utf-8"),
+            Some(ImportFormat::Csv)
+        );
+        assert_eq!(
+            ImportFormat::from_file_name("new.JSON"),
+            Some(ImportFormat::Json)
+        );
+        assert_eq!(ImportFormat::from_file_name("new.xlsx"), None);
+    }
+
+    #[tokio::test]
+    async fn test_check_rows() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = file_manager(&dir);
+        let existing = file_manager
+            .add_employee(
+                new_employee(body("John", "Doe", Some("john@example.com"))),
+                "admin",
+            )
+            .await
+            .unwrap();
+        let archived = file_manager
+            .add_employee(new_employee(body("Ann", "Lee", None)), "admin")
+            .await
+            .unwrap();
+        file_manager
+            .archive_employee(archived.id.as_deref().unwrap(), "left", "admin")
+            .await
+            .unwrap();
+
+        let mut too_young = body("Tim", "Young", None);
+        too_young.age = 10;
+        let rows = vec![
+            Ok(body("Jane", "Roe", Some("jane@example.com"))),
+            Err("column 4: invalid digit found in string".to_string()),
+            Ok(too_young),
+            Ok(body("john", "DOE", None)),
+            Ok(body("Ann", "Lee", None)),
+            Ok(body("Johnny", "Doe", Some("JOHN@example.com"))),
+            Ok(body("Jane", "Roe", None)),
+            Ok(body("Janet", "Roe", Some("jane@example.com"))),
+        ];
+        let (report, employees) = check_rows(&file_manager, rows);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                ImportStatus::Accepted,
+                ImportStatus::Rejected,
+                ImportStatus::Rejected,
+                ImportStatus::Rejected,
+                ImportStatus::PossibleDuplicate,
+                ImportStatus::PossibleDuplicate,
+                ImportStatus::Rejected,
+                ImportStatus::PossibleDuplicate,
+            ]
+        );
+        assert_eq!(report[1].first_name, None);
+        assert!(report[4].reasons[0].contains(archived.id.as_deref().unwrap()));
+        assert!(report[5].reasons[0].contains(existing.id.as_deref().unwrap()));
+        assert_eq!(report[6].reasons, vec!["same name as row 1"]);
+        assert_eq!(report[7].reasons, vec!["same personal email as row 1"]);
+        let rows: Vec<usize> = employees.iter().map(|(row, _)| *row).collect();
+        assert_eq!(rows, vec![1, 5, 6, 8]);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_and_import() {
+        let dir = TempDir::new().unwrap();
+        let file_manager = file_manager(&dir);
+        file_manager
+            .add_employee(
+                new_employee(body("John", "Doe", Some("john@example.com"))),
+                "admin",
+            )
+            .await
+            .unwrap();
+        let csv = "first_name,last_name,personal_email,age,diploma\n\
+                   Jane,Roe,,31,MSc\n\
+                   Johnny,Doe,john@example.com,40,PhD\n\
+                   John,Doe,,40,PhD\n";
+
+        let options = ImportOptions {
+            dry_run: Some(true),
+            allow_duplicates: None,
+        };
+        let report = import(
+            &file_manager,
+            ImportFormat::Csv,
+            csv.as_bytes(),
+            &options,
+            "admin",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (
+                report.accepted,
+                report.possible_duplicates,
+                report.rejected,
+                report.imported
+            ),
+            (1, 1, 1, 0)
+        );
+        assert_eq!(file_manager.list_employees().len(), 1);
+
+        let report = import(
+            &file_manager,
+            ImportFormat::Csv,
+            csv.as_bytes(),
+            &ImportOptions::default(),
+            "admin",
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.imported, 1);
+        let id = report.rows[0].id.as_deref().unwrap();
+        assert_eq!(file_manager.get_employee(id).unwrap().first_name, "Jane");
+        assert_eq!(report.rows[1].id, None);
+
+        // Jane is known now, the duplicate goes in when allowed
+        let options = ImportOptions {
+            dry_run: None,
+            allow_duplicates: Some(true),
+        };
+        let report = import(
+            &file_manager,
+            ImportFormat::Csv,
+            csv.as_bytes(),
+            &options,
+            "admin",
+        )
+        .await
+        .unwrap();
+        assert_eq!(statuses(&report.rows)[0], ImportStatus::Rejected);
+        assert_eq!(report.imported, 1);
+        assert!(report.rows[1].id.is_some());
+        assert_eq!(file_manager.list_employees().len(), 3);
+        assert_eq!(file_manager.get_employee_history(id).len(), 1);
+    }
+}
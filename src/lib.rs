@@ -0,0 +1,20 @@
+pub mod api;
+pub mod app;
+pub mod cli;
+pub mod commands;
+pub mod config;
+pub mod database;
+pub mod export;
+pub mod handlers;
+pub mod import;
+pub mod listeners;
+pub mod models;
+pub mod routes;
+#[cfg(test)]
+mod testing;
+pub mod tls;
+pub mod utils;
+pub mod views;
+
+pub use app::{RedCarpet, RedCarpetBuilder};
+pub use config::Config;
@@ -0,0 +1,359 @@
+use std::{
+    env,
+    future::Future,
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use axum::Router;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto::Builder, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use log::{debug, error, info, warn};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::mpsc,
+    task::JoinSet,
+};
+
+use crate::config::{Listen, ServerConfig};
+
+// first file descriptor passed by systemd socket activation
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+// A bound socket the server accepts connections on
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix {
+        listener: tokio::net::UnixListener,
+        // socket file created by the server, removed once it stops; None for
+        // a socket inherited from systemd
+        path: Option<PathBuf>,
+    },
+}
+
+impl Listener {
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Listener::Unix { .. } => None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(address) => format!("http://{address}"),
+                Err(_) => "tcp socket".to_string(),
+            },
+            #[cfg(unix)]
+            Listener::Unix { listener, .. } => match listener.local_addr() {
+                Ok(address) => match address.as_pathname() {
+                    Some(path) => format!("unix:{}", path.display()),
+                    None => "unnamed unix socket".to_string(),
+                },
+                Err(_) => "unix socket".to_string(),
+            },
+        }
+    }
+}
+
+// bind every listener of the config; nothing is served unless all of them
+// could be bound
+pub async fn bind_listeners(server: &ServerConfig) -> io::Result<Vec<Listener>> {
+    let mut listeners = vec![];
+    for listen in server.listeners() {
+        match listen {
+            Listen::Tcp(address) => {
+                listeners.push(Listener::Tcp(TcpListener::bind(address).await?))
+            }
+            Listen::Unix(path) => {
+                let mode = server
+                    .socket_mode()
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+                listeners.push(bind_unix(&path, mode)?);
+            }
+            Listen::Systemd => listeners.extend(systemd_listeners()?),
+        }
+    }
+    Ok(listeners)
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: u32) -> io::Result<Listener> {
+    use std::{
+        fs,
+        os::unix::fs::{FileTypeExt, PermissionsExt},
+    };
+
+    // the socket of a previous run that was not stopped cleanly
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{path:?} exists and is not a socket"),
+            ))
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error),
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(Listener::Unix {
+        listener,
+        path: Some(path.to_path_buf()),
+    })
+}
+
+#[cfg(not(unix))]
+fn bind_unix(path: &Path, _mode: u32) -> io::Result<Listener> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("unix sockets are not supported on this platform: {path:?}"),
+    ))
+}
+
+// sockets passed by systemd, see sd_listen_fds(3)
+#[cfg(unix)]
+fn systemd_listeners() -> io::Result<Vec<Listener>> {
+    use std::os::fd::{FromRawFd, IntoRawFd};
+
+    let not_activated = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "no socket passed by systemd, LISTEN_FDS and LISTEN_PID are not set for this process",
+        )
+    };
+    let pid = env::var("LISTEN_PID").map_err(|_| not_activated())?;
+    if pid.parse() != Ok(std::process::id()) {
+        return Err(not_activated());
+    }
+    let count: i32 = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .ok_or_else(not_activated)?;
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // systemd hands over the descriptors, nothing else owns them
+            let tcp = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+            // only a TCP socket has a local address std can represent
+            if tcp.local_addr().is_ok() {
+                tcp.set_nonblocking(true)?;
+                return Ok(Listener::Tcp(TcpListener::from_std(tcp)?));
+            }
+            let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+            unix.set_nonblocking(true)?;
+            Ok(Listener::Unix {
+                listener: tokio::net::UnixListener::from_std(unix)?,
+                path: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn systemd_listeners() -> io::Result<Vec<Listener>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "systemd socket activation is not supported on this platform",
+    ))
+}
+
+// an accepted connection, whichever listener it came from
+enum Connection {
+    Tcp(tokio::net::TcpStream),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+}
+
+async fn accept(listener: &Listener) -> io::Result<Connection> {
+    match listener {
+        Listener::Tcp(listener) => Ok(Connection::Tcp(listener.accept().await?.0)),
+        #[cfg(unix)]
+        Listener::Unix { listener, .. } => Ok(Connection::Unix(listener.accept().await?.0)),
+    }
+}
+
+// Serve `router` on every listener until `shutdown` resolves, then stop
+// accepting connections and give the open ones `drain_timeout` to complete.
+pub async fn serve(
+    listeners: Vec<Listener>,
+    router: Router,
+    shutdown: impl Future<Output = ()>,
+    drain_timeout: Duration,
+) {
+    let (accepted, mut connections) = mpsc::channel(64);
+    let mut accept_jobs = JoinSet::new();
+    let mut socket_files = vec![];
+    for listener in listeners {
+        info!("listening on: {}", listener.describe());
+        #[cfg(unix)]
+        if let Listener::Unix {
+            path: Some(path), ..
+        } = &listener
+        {
+            socket_files.push(path.clone());
+        }
+        let accepted = accepted.clone();
+        accept_jobs.spawn(async move {
+            loop {
+                match accept(&listener).await {
+                    Ok(connection) => {
+                        if accepted.send(connection).await.is_err() {
+                            break;
+                        }
+                    }
+                    // e.g. too many open files, the listener itself is fine
+                    Err(error) => {
+                        error!("Unable to accept a connection: {error}");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                }
+            }
+        });
+    }
+    drop(accepted);
+
+    let graceful = GracefulShutdown::new();
+    let builder = Builder::new(TokioExecutor::new());
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            connection = connections.recv() => match connection {
+                Some(Connection::Tcp(stream)) => serve_connection(&builder, &graceful, stream, &router),
+                #[cfg(unix)]
+                Some(Connection::Unix(stream)) => serve_connection(&builder, &graceful, stream, &router),
+                None => break,
+            },
+            _ = &mut shutdown => break,
+        }
+    }
+
+    // the listeners are closed with their accept job
+    accept_jobs.shutdown().await;
+    remove_socket_files(&socket_files);
+    tokio::select! {
+        _ = graceful.shutdown() => info!("In-flight requests drained"),
+        _ = tokio::time::sleep(drain_timeout) => {
+            warn!("Drain timeout elapsed, dropping the remaining requests")
+        }
+    }
+}
+
+fn serve_connection<I>(
+    builder: &Builder<TokioExecutor>,
+    graceful: &GracefulShutdown,
+    stream: I,
+    router: &Router,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = TowerToHyperService::new(router.clone());
+    let connection = builder
+        .serve_connection_with_upgrades(TokioIo::new(stream), service)
+        .into_owned();
+    let connection = graceful.watch(connection);
+    tokio::spawn(async move {
+        if let Err(error) = connection.await {
+            // mostly clients going away mid-request
+            debug!("Connection closed with an error: {error}");
+        }
+    });
+}
+
+fn remove_socket_files(paths: &[PathBuf]) {
+    for path in paths {
+        if let Err(error) = std::fs::remove_file(path) {
+            warn!("Unable to remove the socket {path:?}: {error}");
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use axum::routing::get;
+    use tempfile::TempDir;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpStream, UnixStream},
+        sync::oneshot,
+    };
+
+    use super::*;
+
+    async fn request<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S) -> String {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_serve_tcp_and_unix_socket() {
+        let dir = TempDir::new().unwrap();
+        let socket = dir.path().join("red_carpet.sock");
+        // left behind by a previous run
+        std::os::unix::net::UnixListener::bind(&socket).unwrap();
+
+        let server = ServerConfig {
+            listen: vec![
+                Listen::Tcp("127.0.0.1:0".parse().unwrap()),
+                Listen::Unix(socket.clone()),
+            ],
+            unix_socket_mode: "600".to_string(),
+            ..ServerConfig::default()
+        };
+        let listeners = bind_listeners(&server).await.unwrap();
+        let address = listeners[0].local_addr().unwrap();
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let router = Router::new().route("/", get(|| async { "red carpet" }));
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            listeners,
+            router,
+            async {
+                let _ = stopped.await;
+            },
+            Duration::from_secs(1),
+        ));
+
+        let response = request(TcpStream::connect(address).await.unwrap()).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("red carpet"));
+        let response = request(UnixStream::connect(&socket).await.unwrap()).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        stop.send(()).unwrap();
+        server.await.unwrap();
+        assert!(!socket.exists());
+        assert!(TcpStream::connect(address).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_systemd_requires_activation() {
+        let server = ServerConfig {
+            listen: vec![Listen::Systemd],
+            ..ServerConfig::default()
+        };
+        let error = bind_listeners(&server).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
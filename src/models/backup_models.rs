@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, ToSchema)]
+pub struct BackupInfo {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    // size of the compressed backup in bytes
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct BackupResponse {
+    pub message: String,
+    pub backup: BackupInfo,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct BackupListResponse {
+    pub message: String,
+    pub results: usize,
+    pub backups: Vec<BackupInfo>,
+}
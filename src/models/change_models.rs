@@ -0,0 +1,55 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::models::view_models::EmployeeView;
+use crate::views::EmployeeFields;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    // moved to the trash or purged
+    Deleted,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Change {
+    // position in the commit order, shared by every employee
+    pub sequence: u64,
+    pub id: String,
+    pub change: ChangeKind,
+    // employee version the change produced
+    pub version: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChangesQuery {
+    // cursor of a previous response, the feed starts from the beginning
+    // without it
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct EmployeeChange {
+    pub change: ChangeKind,
+    pub id: String,
+    pub version: u64,
+    pub timestamp: DateTime<Utc>,
+    // record as of now, without credentials; None once deleted
+    #[schema(value_type = Option<EmployeeView>)]
+    pub employee: Option<EmployeeFields>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ChangesResponse {
+    pub message: String,
+    pub results: usize,
+    pub changes: Vec<EmployeeChange>,
+    // `since` of the next request
+    pub next_cursor: String,
+}
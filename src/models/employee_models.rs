@@ -1,6 +1,19 @@
-use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::models::view_models::EmployeeView;
+use crate::utils::errors::{
+    employee_no_diploma_error, employee_not_old_enough_error, DataStoreError,
+};
+use crate::views::EmployeeFields;
+
+// employees must be adults to be onboarded
+pub const MINIMUM_AGE: u32 = 18;
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
 pub struct Employee {
     pub id: Option<String>,
     pub first_name: String,
@@ -13,6 +26,43 @@ pub struct Employee {
     pub handle: Option<String>,
     pub password: Option<String>,
     pub secure_password: Option<bool>,
+    // incremented by the store on every change, exposed as the ETag
+    #[serde(default)]
+    pub version: u64,
+    // set while the employee sits in the trash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived: Option<ArchiveInfo>,
+}
+
+impl Employee {
+    // rules every new or edited employee must follow, whatever the channel
+    pub fn validate(&self) -> Result<(), DataStoreError> {
+        if self.age < MINIMUM_AGE {
+            employee_not_old_enough_error(self.first_name.clone(), self.last_name.clone())?;
+        }
+        if self.diploma.trim().is_empty() {
+            employee_no_diploma_error(self.first_name.clone(), self.last_name.clone())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+pub struct ArchiveInfo {
+    pub reason: String,
+    pub actor: String,
+    pub archived_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ArchiveEmployeeForm {
+    pub id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RestoreArchivedEmployeeForm {
+    pub id: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -21,7 +71,7 @@ pub struct EmployeeForm {
     pub password: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct EmployeeRequestBody {
     pub first_name: String,
     pub last_name: String,
@@ -35,31 +85,273 @@ pub struct EmployeeData {
     pub employee: Employee,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Clone, Debug, ToSchema)]
 pub struct EmployeeResponse {
     pub message: String,
-    pub data: Employee,
+    #[schema(value_type = EmployeeView)]
+    pub data: EmployeeFields,
 }
 
-#[derive(Serialize, Debug)]
-pub struct EmployeeListResponse {
+// response of the actions generating a password, the only time it is shown
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct EmployeeCredentialsResponse {
+    pub message: String,
+    #[schema(value_type = EmployeeView)]
+    pub data: EmployeeFields,
+    pub password: String,
+}
+
+// one page of an employee list
+#[derive(Serialize, Debug, ToSchema)]
+pub struct EmployeePageResponse {
     pub message: String,
     pub results: usize,
-    pub employees: Vec<Employee>,
+    // employees matching the filters, on every page
+    pub total: usize,
+    // None when the page was read with a cursor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    pub per_page: usize,
+    #[schema(value_type = Vec<EmployeeView>)]
+    pub employees: Vec<EmployeeFields>,
+    // `cursor` of the next page, None on the last one
+    pub next_cursor: Option<String>,
 }
 
-#[allow(non_snake_case)]
-#[derive(Debug, Deserialize, Serialize, Clone)]
+// body of `POST /api/v2/employees` and `PUT /api/v1/employee/{id}`,
+// credentials are never accepted
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateEmployeeSchema {
+    pub first_name: String,
+    pub last_name: String,
+    #[serde(default)]
+    pub personal_email: Option<String>,
+    pub age: u32,
+    pub diploma: String,
+}
+
+// JSON merge patch (RFC 7396) of the personal data of an employee: a missing
+// field is left as is and `null` clears `personal_email`; handles, emails and
+// passwords only change through the dedicated actions
+#[derive(Debug, Deserialize, Serialize, Clone, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
 pub struct UpdateEmployeeSchema {
-    pub title: Option<String>,
-    pub content: Option<String>,
-    pub completed: Option<bool>,
+    #[serde(default, deserialize_with = "present")]
+    pub first_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub last_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub personal_email: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub age: Option<Option<u32>>,
+    #[serde(default, deserialize_with = "present")]
+    pub diploma: Option<Option<String>>,
 }
 
-#[derive(Debug, Deserialize, Default)]
+// tell a field set to `null` (Some(None)) from a missing one (None)
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+// the personal data replaced whole, a missing `personal_email` is cleared
+impl From<CreateEmployeeSchema> for UpdateEmployeeSchema {
+    fn from(employee: CreateEmployeeSchema) -> Self {
+        UpdateEmployeeSchema {
+            first_name: Some(Some(employee.first_name)),
+            last_name: Some(Some(employee.last_name)),
+            personal_email: Some(employee.personal_email),
+            age: Some(Some(employee.age)),
+            diploma: Some(Some(employee.diploma)),
+        }
+    }
+}
+
+impl UpdateEmployeeSchema {
+    // the employee with the patch applied, or the name of a required field
+    // the patch tries to clear
+    pub fn apply(self, mut employee: Employee) -> Result<Employee, &'static str> {
+        fn required<T>(
+            value: Option<Option<T>>,
+            field: &'static str,
+        ) -> Result<Option<T>, &'static str> {
+            match value {
+                Some(None) => Err(field),
+                Some(value) => Ok(value),
+                None => Ok(None),
+            }
+        }
+        if let Some(first_name) = required(self.first_name, "first_name")? {
+            employee.first_name = first_name;
+        }
+        if let Some(last_name) = required(self.last_name, "last_name")? {
+            employee.last_name = last_name;
+        }
+        if let Some(age) = required(self.age, "age")? {
+            employee.age = age;
+        }
+        if let Some(diploma) = required(self.diploma, "diploma")? {
+            employee.diploma = diploma;
+        }
+        if let Some(personal_email) = self.personal_email {
+            employee.personal_email = personal_email;
+        }
+        Ok(employee)
+    }
+}
+
+pub const DEFAULT_PER_PAGE: usize = 10;
+pub const MAX_PER_PAGE: usize = 100;
+
+// Query string of the employee lists: filters, a `q` search on the names,
+// `sort=last_name,-created_at` (`-` for descending) and 1-based pages.
+#[derive(Debug, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct QueryOptions {
     pub page: Option<usize>,
     pub per_page: Option<usize>,
+    pub onboarded: Option<bool>,
+    pub secure_password: Option<bool>,
+    pub q: Option<String>,
+    pub sort: Option<String>,
+    // `next_cursor` of the previous page, instead of `page`
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    FirstName,
+    LastName,
+    Age,
+    CreatedAt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+// Values an employee is sorted on; a list cursor holds the position of the
+// last employee of its page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SortPosition {
+    pub first_name: String,
+    pub last_name: String,
+    pub age: u32,
+    pub created_at: Option<DateTime<Utc>>,
+    pub id: Option<String>,
+}
+
+impl SortPosition {
+    pub fn new(employee: &Employee, created_at: Option<DateTime<Utc>>) -> Self {
+        SortPosition {
+            first_name: employee.first_name.clone(),
+            last_name: employee.last_name.clone(),
+            age: employee.age,
+            created_at,
+            id: employee.id.clone(),
+        }
+    }
+
+    // order of `sort`, then of the ids
+    pub fn compare(&self, other: &SortPosition, sort: &[SortKey]) -> Ordering {
+        sort.iter()
+            .map(|key| {
+                let ordering = match key.field {
+                    SortField::FirstName => self.first_name.cmp(&other.first_name),
+                    SortField::LastName => self.last_name.cmp(&other.last_name),
+                    SortField::Age => self.age.cmp(&other.age),
+                    SortField::CreatedAt => self.created_at.cmp(&other.created_at),
+                };
+                if key.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| self.id.cmp(&other.id))
+    }
+}
+
+// content of an opaque list cursor
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListCursor {
+    // sort of the list the cursor was issued for
+    pub sort: Option<String>,
+    pub after: SortPosition,
+}
+
+impl QueryOptions {
+    // sort keys in order of precedence, by first name when none is given
+    pub fn sort_keys(&self) -> Result<Vec<SortKey>, String> {
+        let Some(sort) = self.sort.as_deref() else {
+            return Ok(vec![SortKey {
+                field: SortField::FirstName,
+                descending: false,
+            }]);
+        };
+        sort.split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(|key| {
+                let (name, descending) = match key.strip_prefix('-') {
+                    Some(name) => (name, true),
+                    None => (key, false),
+                };
+                let field = match name {
+                    "first_name" => SortField::FirstName,
+                    "last_name" => SortField::LastName,
+                    "age" => SortField::Age,
+                    "created_at" => SortField::CreatedAt,
+                    _ => {
+                        return Err(format!(
+                            "cannot sort by {name:?}, expected first_name, last_name, age or created_at"
+                        ))
+                    }
+                };
+                Ok(SortKey { field, descending })
+            })
+            .collect()
+    }
+
+    // requested page and page size
+    pub fn page(&self) -> Result<(usize, usize), String> {
+        let page = self.page.unwrap_or(1);
+        let per_page = self.per_page.unwrap_or(DEFAULT_PER_PAGE);
+        if page == 0 {
+            return Err("page starts at 1".to_string());
+        }
+        if per_page == 0 || per_page > MAX_PER_PAGE {
+            return Err(format!("per_page must be between 1 and {MAX_PER_PAGE}"));
+        }
+        Ok((page, per_page))
+    }
+
+    pub fn matches(&self, employee: &Employee) -> bool {
+        if let Some(onboarded) = self.onboarded {
+            if employee.onboarded.unwrap_or(false) != onboarded {
+                return false;
+            }
+        }
+        if let Some(secure_password) = self.secure_password {
+            if employee.secure_password.unwrap_or(false) != secure_password {
+                return false;
+            }
+        }
+        match self.q.as_deref().map(str::trim) {
+            Some(q) if !q.is_empty() => {
+                let name = format!("{} {}", employee.first_name, employee.last_name);
+                name.to_lowercase().contains(&q.to_lowercase())
+            }
+            _ => true,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
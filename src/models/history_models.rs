@@ -0,0 +1,39 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::employee_models::Employee;
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, ToSchema)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, ToSchema)]
+pub struct Revision {
+    // employee version this revision produced
+    pub version: u64,
+    pub actor: String,
+    pub timestamp: DateTime<Utc>,
+    pub changes: Vec<FieldChange>,
+    // set when the revision restored an earlier one
+    pub restored_from: Option<u64>,
+    // record as of this revision, without credentials
+    pub snapshot: Employee,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct EmployeeHistoryResponse {
+    pub message: String,
+    pub results: usize,
+    pub revisions: Vec<Revision>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RestoreRevisionForm {
+    pub id: String,
+    pub revision: u64,
+    pub version: u64,
+}
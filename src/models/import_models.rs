@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportOptions {
+    // only report what would be imported
+    pub dry_run: Option<bool>,
+    // also import the rows reported as possible duplicates
+    pub allow_duplicates: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Accepted,
+    Rejected,
+    // valid, but it may be someone already known
+    PossibleDuplicate,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, ToSchema)]
+pub struct ImportRow {
+    // position of the row in the file, from 1 and without the CSV header
+    pub row: usize,
+    pub status: ImportStatus,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub reasons: Vec<String>,
+    // id of the employee created from the row, when it was imported
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportReport {
+    pub message: String,
+    pub dry_run: bool,
+    pub accepted: usize,
+    pub rejected: usize,
+    pub possible_duplicates: usize,
+    // employees created, 0 for a dry run
+    pub imported: usize,
+    pub rows: Vec<ImportRow>,
+}
@@ -1,2 +1,9 @@
 pub mod admin_models;
+pub mod backup_models;
+pub mod change_models;
 pub mod employee_models;
+pub mod export_models;
+pub mod history_models;
+pub mod import_models;
+pub mod onboarding_models;
+pub mod view_models;
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// most employees onboarded by one call
+pub const MAX_BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BulkOnboardRequest {
+    // ids of the employees to onboard
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OnboardingStatus {
+    Onboarded,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, ToSchema)]
+pub struct OnboardingResult {
+    pub id: String,
+    pub status: OnboardingStatus,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub handle: Option<String>,
+    pub avaya_email: Option<String>,
+    // first password of the employee, only returned here
+    pub password: Option<String>,
+    pub version: Option<u64>,
+    // why the employee was not onboarded, e.g. `already_onboarded`
+    pub code: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkOnboardReport {
+    pub message: String,
+    pub onboarded: usize,
+    pub failed: usize,
+    pub results: Vec<OnboardingResult>,
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::models::employee_models::{ArchiveInfo, Employee};
+
+// An employee as shown outside of the server: the record without its
+// password, in clear or hashed, which no view can hold.
+#[derive(Debug, Serialize, Clone, PartialEq, ToSchema)]
+pub struct EmployeeView {
+    pub id: Option<String>,
+    pub first_name: String,
+    pub last_name: String,
+    pub personal_email: Option<String>,
+    pub avaya_email: Option<String>,
+    pub age: u32,
+    pub diploma: String,
+    pub onboarded: Option<bool>,
+    pub handle: Option<String>,
+    pub secure_password: Option<bool>,
+    pub version: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived: Option<ArchiveInfo>,
+}
+
+impl From<&Employee> for EmployeeView {
+    fn from(employee: &Employee) -> Self {
+        EmployeeView {
+            id: employee.id.clone(),
+            first_name: employee.first_name.clone(),
+            last_name: employee.last_name.clone(),
+            personal_email: employee.personal_email.clone(),
+            avaya_email: employee.avaya_email.clone(),
+            age: employee.age,
+            diploma: employee.diploma.clone(),
+            onboarded: employee.onboarded,
+            handle: employee.handle.clone(),
+            secure_password: employee.secure_password,
+            version: employee.version,
+            archived: employee.archived.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FieldsQuery {
+    // comma separated fields of the employees to return, e.g.
+    // `first_name,handle`; `id` is always returned
+    pub fields: Option<String>,
+}
@@ -1,78 +1,133 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use axum::{
+    middleware,
     routing::{any, get, post},
     Extension, Router,
 };
 
-use tera::Tera;
+use tera::{Tera, Value};
 
 use crate::{
+    api::{
+        employees::{
+            create_employee as create_employee_v2, delete_employee, employee_action,
+            get_employee as get_employee_v2, list_changes, list_employees as list_employees_v2,
+            update_employee,
+        },
+        openapi::{openapi_json, swagger_ui, OPENAPI_PATH},
+    },
     handlers::{
-        create_employee, delete_employee, edit_employee, employees_list,
-        generate_handle_and_password, get_employee, handle_edit_form_data,
-        handle_onboard_form_data, handle_personal_data_form_data, handle_save_form_data,
-        health_checker, index, list_employees, login, login_admin, login_admin_page,
-        login_employee, logout_admin, logout_employee, new_employee_page, reset_password_by_id,
-        save_result_page, secure_password, select_employee, styles, update_employee_by_id,
+        backups_list, create_backup, create_employee, download_export, edit_employee,
+        employees_action, employees_changes, employees_list, export_employees,
+        generate_handle_and_password, get_employee, get_employee_history,
+        handle_bulk_onboard_form_data, handle_delete_form_data, handle_edit_form_data,
+        handle_import_form_data, handle_onboard_form_data, handle_personal_data_form_data,
+        handle_restore_archived_form_data, handle_restore_revision_form_data,
+        handle_save_form_data, health_checker, import_page, index, list_employees, list_trash,
+        login, login_admin, login_admin_page, login_employee, logout_admin, logout_employee,
+        new_employee_page, reset_password_by_id, restore_employee_revision, save_result_page,
+        secure_password, select_employee, styles, update_employee_by_id,
+    },
+    utils::{
+        idempotency::{idempotency, Idempotency},
+        request_id::request_id,
+        state::AppState,
     },
-    utils::state::AppState,
 };
 
-pub async fn define_routes(state: AppState, mut tera: Tera) -> Router {
-    tera.add_raw_templates(vec![
-        ("base.html", include_str!("./frontend/templates/base.html")),
-        (
-            "index.html",
-            include_str!("./frontend/templates/index.html"),
-        ),
-        (
-            "employee.html",
-            include_str!("./frontend/templates/employee.html"),
-        ),
-        (
-            "dashboard.html",
-            include_str!("./frontend/templates/dashboard.html"),
-        ),
-        (
-            "new_employee.html",
-            include_str!("./frontend/templates/new_employee.html"),
-        ),
-        (
-            "save_result.html",
-            include_str!("./frontend/templates/save_result.html"),
-        ),
-        (
-            "edit_form.html",
-            include_str!("./frontend/templates/edit_form.html"),
-        ),
-        (
-            "delete_confirmation.html",
-            include_str!("./frontend/templates/delete_confirmation.html"),
-        ),
-        (
-            "errors.html",
-            include_str!("./frontend/templates/errors.html"),
-        ),
-        (
-            "login.html",
-            include_str!("./frontend/templates/login.html"),
-        ),
-        (
-            "admin_login.html",
-            include_str!("./frontend/templates/admin_login.html"),
-        ),
-        (
-            "already_logged_in.html",
-            include_str!("./frontend/templates/already_logged_in.html"),
-        ),
-        (
-            "onboarded_employee.html",
-            include_str!("./frontend/templates/onboarded_employee.html"),
-        ),
-    ])
-    .unwrap();
+// built-in templates, by name
+const TEMPLATES: [(&str, &str); 17] = [
+    ("base.html", include_str!("./frontend/templates/base.html")),
+    (
+        "index.html",
+        include_str!("./frontend/templates/index.html"),
+    ),
+    (
+        "employee.html",
+        include_str!("./frontend/templates/employee.html"),
+    ),
+    (
+        "dashboard.html",
+        include_str!("./frontend/templates/dashboard.html"),
+    ),
+    (
+        "new_employee.html",
+        include_str!("./frontend/templates/new_employee.html"),
+    ),
+    (
+        "save_result.html",
+        include_str!("./frontend/templates/save_result.html"),
+    ),
+    (
+        "edit_form.html",
+        include_str!("./frontend/templates/edit_form.html"),
+    ),
+    (
+        "delete_confirmation.html",
+        include_str!("./frontend/templates/delete_confirmation.html"),
+    ),
+    (
+        "trash.html",
+        include_str!("./frontend/templates/trash.html"),
+    ),
+    (
+        "import.html",
+        include_str!("./frontend/templates/import.html"),
+    ),
+    (
+        "conflict.html",
+        include_str!("./frontend/templates/conflict.html"),
+    ),
+    (
+        "errors.html",
+        include_str!("./frontend/templates/errors.html"),
+    ),
+    (
+        "login.html",
+        include_str!("./frontend/templates/login.html"),
+    ),
+    (
+        "admin_login.html",
+        include_str!("./frontend/templates/admin_login.html"),
+    ),
+    (
+        "already_logged_in.html",
+        include_str!("./frontend/templates/already_logged_in.html"),
+    ),
+    (
+        "onboarded_employee.html",
+        include_str!("./frontend/templates/onboarded_employee.html"),
+    ),
+    (
+        "onboard_report.html",
+        include_str!("./frontend/templates/onboard_report.html"),
+    ),
+];
 
+// built-in templates replaced by `overrides` with the same name; links in
+// the templates are prefixed with `base_path`, where the routes are mounted
+pub fn load_templates(overrides: Vec<(String, String)>, base_path: &str) -> tera::Result<Tera> {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(TEMPLATES)?;
+    tera.add_raw_templates(overrides)?;
+    let base_path = base_path.trim_end_matches('/').to_string();
+    // a nested router serves its home page without a trailing slash
+    let home_path = match base_path.is_empty() {
+        true => Value::String("/".to_string()),
+        false => Value::String(base_path.clone()),
+    };
+    let base_path = Value::String(base_path);
+    tera.register_function("base_path", move |_: &HashMap<String, Value>| {
+        Ok(base_path.clone())
+    });
+    tera.register_function("home_path", move |_: &HashMap<String, Value>| {
+        Ok(home_path.clone())
+    });
+    Ok(tera)
+}
+
+pub fn define_routes(state: AppState, tera: Tera) -> Router {
     // build our application with a route
     Router::new()
         .route("/api/v1/healthchecker", get(health_checker))
@@ -80,12 +135,47 @@ pub async fn define_routes(state: AppState, mut tera: Tera) -> Router {
             "/api/v1/employees",
             post(create_employee).get(employees_list),
         )
+        .route("/api/v1/employees/changes", get(employees_changes))
+        // `GET /api/v1/employees:export`, `POST /api/v1/employees:import` and
+        // `POST /api/v1/employees:onboard`, the router reads the action as a
+        // parameter
+        .route(
+            "/api/v1/employees:action",
+            get(export_employees).post(employees_action),
+        )
         .route(
             "/api/v1/employee/:emp_id",
             get(get_employee)
                 .patch(generate_handle_and_password)
                 .put(update_employee_by_id),
         )
+        .route(
+            "/api/v1/admin/backups",
+            post(create_backup).get(backups_list),
+        )
+        .route(
+            "/api/v1/employee/:emp_id/history",
+            get(get_employee_history),
+        )
+        .route(
+            "/api/v1/employee/:emp_id/history/:revision/restore",
+            post(restore_employee_revision),
+        )
+        .route(
+            "/api/v2/employees",
+            post(create_employee_v2).get(list_employees_v2),
+        )
+        .route("/api/v2/employees/changes", get(list_changes))
+        // `POST /api/v2/employees/{id}:{action}` runs an action on the employee
+        .route(
+            "/api/v2/employees/:id",
+            get(get_employee_v2)
+                .patch(update_employee)
+                .delete(delete_employee)
+                .post(employee_action),
+        )
+        .route(OPENAPI_PATH, get(openapi_json))
+        .merge(swagger_ui())
         .route("/styles.css", any(styles))
         .route("/", get(index))
         .route("/login", get(login))
@@ -99,13 +189,27 @@ pub async fn define_routes(state: AppState, mut tera: Tera) -> Router {
         .route("/update/employee", post(handle_edit_form_data))
         .route("/update/onboarded", post(handle_personal_data_form_data))
         .route("/onboard/employee", post(handle_onboard_form_data))
+        .route("/restore/employee", post(handle_restore_revision_form_data))
         .route("/securepassword/employee", post(secure_password))
         .route("/resetpassword/employee/:id", get(reset_password_by_id))
         .route("/new/employee", get(new_employee_page))
         .route("/save/employee", post(handle_save_form_data))
         .route("/save/success", get(save_result_page))
-        .route("/delete/employee/:id", get(delete_employee))
+        .route("/delete/employee", post(handle_delete_form_data))
+        .route("/trash/employees", get(list_trash))
+        .route("/trash/restore", post(handle_restore_archived_form_data))
+        .route(
+            "/import/employees",
+            get(import_page).post(handle_import_form_data),
+        )
+        .route("/onboard/employees", post(handle_bulk_onboard_form_data))
+        .route("/export/employees", get(download_export))
         .route("/select/employee/:id", get(select_employee))
         .layer(Extension(Arc::new(tera)))
+        .layer(middleware::from_fn_with_state(
+            Idempotency::new(state.config.idempotency.window(), state.clone()),
+            idempotency,
+        ))
+        .layer(middleware::from_fn(request_id))
         .with_state(state)
 }
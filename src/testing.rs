@@ -0,0 +1,128 @@
+// fixtures shared by the tests sending requests through the router
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http_body_util::BodyExt;
+use pbkdf2::{
+    password_hash::{PasswordHasher, SaltString},
+    Params, Pbkdf2,
+};
+use rand_core::OsRng;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tokio::sync::Mutex;
+use tower::ServiceExt;
+
+use crate::{
+    config::Config,
+    database::{
+        file_manager::FileManager,
+        persistence::{create_persistence_store, DataPaths},
+    },
+    models::admin_models::Admin,
+    routes::{define_routes, load_templates},
+    utils::state::AppState,
+};
+
+// routes over a new data directory, with the admin `admin` whose password
+// is `secret`
+pub async fn app(dir: &TempDir) -> Router {
+    routes(state(dir).await)
+}
+
+// routes over the state of a test, to seed its data through the file manager
+pub fn routes(state: AppState) -> Router {
+    define_routes(state, load_templates(vec![], "").unwrap())
+}
+
+// state over a new data directory, with the admin of `app`
+pub async fn state(dir: &TempDir) -> AppState {
+    let paths = DataPaths::new(dir.path());
+    create_persistence_store(&paths).unwrap();
+    let file_manager = FileManager::new(&paths, None).unwrap();
+    add_admin(&file_manager, "admin", "secret").await;
+
+    let mut config = Config::default();
+    // backups go to the data directory too
+    config.data.dir = dir.path().to_path_buf();
+    AppState {
+        sessions: Arc::new(Mutex::new(HashMap::new())),
+        file_manager: Arc::new(file_manager),
+        config: Arc::new(config),
+    }
+}
+
+// admin with a PBKDF2 hash of `password`
+pub async fn add_admin(file_manager: &FileManager, id: &str, password: &str) {
+    // few rounds, the default ones take seconds in debug builds
+    let params = Params {
+        rounds: 1_000,
+        output_length: 32,
+    };
+    let salt = SaltString::generate(&mut OsRng);
+    let password = Pbkdf2
+        .hash_password_customized(password.as_bytes(), None, None, params, &salt)
+        .unwrap()
+        .to_string();
+    file_manager
+        .add_admin(Admin {
+            id: id.to_string(),
+            password: Some(password),
+        })
+        .await
+        .unwrap();
+}
+
+// value of the authorization header for `id:password`
+pub fn basic_auth(credentials: &str) -> String {
+    format!("Basic {}", STANDARD.encode(credentials))
+}
+
+// JSON request authenticated as the admin, the body is `Value::Null` when
+// it is not JSON
+pub async fn send(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    if_match: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, HeaderMap, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::AUTHORIZATION, basic_auth("admin:secret"));
+    if let Some(if_match) = if_match {
+        request = request.header(header::IF_MATCH, if_match);
+    }
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/merge-patch+json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    };
+    let response = app.clone().oneshot(request.unwrap()).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    (status, headers, body)
+}
+
+pub fn ada() -> Value {
+    json!({"first_name": "Ada", "last_name": "Lovelace", "age": 36, "diploma": "Maths"})
+}
+
+// log the admin in on the login page, the HTML admin pages need it
+pub async fn login_admin(app: &Router) {
+    let request = Request::post("/admin/login")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("id=admin&password=secret"))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,183 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    extract::Request,
+    http::{
+        header::{HOST, STRICT_TRANSPORT_SECURITY},
+        HeaderValue, StatusCode,
+    },
+    middleware,
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use log::{error, info};
+use tokio::task::JoinHandle;
+
+// how often the certificate files are checked for a change
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+const HTTPS_PORT: u16 = 443;
+
+// load the PEM certificate chain and private key
+pub async fn load_tls(cert: &Path, key: &Path) -> io::Result<RustlsConfig> {
+    // rustls is built without a default provider, ring is used
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    RustlsConfig::from_pem_file(cert, key)
+        .await
+        .map_err(|error| {
+            io::Error::new(
+                error.kind(),
+                format!("unable to load certificate {cert:?} and key {key:?}: {error}"),
+            )
+        })
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// reload the certificate once its files change, e.g. after a renewal; new
+// connections use the new certificate, a file that cannot be loaded keeps
+// the previous one in use
+pub fn spawn_reload_job(tls: RustlsConfig, cert: PathBuf, key: PathBuf) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut loaded = (modified(&cert), modified(&key));
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            let current = (modified(&cert), modified(&key));
+            if current == loaded {
+                continue;
+            }
+            match tls.reload_from_pem_file(&cert, &key).await {
+                Ok(()) => info!("Reloaded certificate {cert:?}"),
+                Err(error) => error!("Unable to reload certificate {cert:?}: {error}"),
+            }
+            // a broken file is not retried until it changes again
+            loaded = current;
+        }
+    })
+}
+
+// address of the same resource over HTTPS
+pub fn https_uri(host: &str, https_port: u16, path_and_query: &str) -> String {
+    // the host header carries the port of the plain listener, if any
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    match https_port {
+        HTTPS_PORT => format!("https://{host}{path_and_query}"),
+        port => format!("https://{host}:{port}{path_and_query}"),
+    }
+}
+
+// routes of the plain HTTP listener, every request is redirected to HTTPS
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |request: Request| async move {
+        let Some(host) = request
+            .headers()
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+        else {
+            return (StatusCode::BAD_REQUEST, "Host header required").into_response();
+        };
+        let path_and_query = request
+            .uri()
+            .path_and_query()
+            .map(|path_and_query| path_and_query.as_str())
+            .unwrap_or("/");
+        Redirect::permanent(&https_uri(host, https_port, path_and_query)).into_response()
+    })
+}
+
+// tell browsers to use HTTPS only for `max_age_secs`
+pub fn with_hsts(router: Router, max_age_secs: u64) -> Router {
+    let value =
+        HeaderValue::from_str(&format!("max-age={max_age_secs}; includeSubDomains")).unwrap();
+    router.layer(middleware::map_response(move |mut response: Response| {
+        let value = value.clone();
+        async move {
+            response
+                .headers_mut()
+                .insert(STRICT_TRANSPORT_SECURITY, value);
+            response
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::header::LOCATION, routing::get};
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[test]
+    fn test_https_uri() {
+        assert_eq!(
+            https_uri("example.com:8080", 443, "/login?next=%2F"),
+            "https://example.com/login?next=%2F"
+        );
+        assert_eq!(
+            https_uri("example.com", 8443, "/"),
+            "https://example.com:8443/"
+        );
+        assert_eq!(https_uri("[::1]:80", 8443, "/"), "https://[::1]:8443/");
+        assert_eq!(https_uri("[::1]", 443, "/"), "https://[::1]/");
+    }
+
+    #[tokio::test]
+    async fn test_redirect_and_hsts() {
+        let request = Request::get("/list/employees")
+            .header(HOST, "intranet:8080")
+            .body(Body::empty())
+            .unwrap();
+        let response = redirect_router(8443).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[LOCATION],
+            "https://intranet:8443/list/employees"
+        );
+
+        let app = with_hsts(Router::new().route("/", get(|| async { "ok" })), 600);
+        let response = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[STRICT_TRANSPORT_SECURITY],
+            "max-age=600; includeSubDomains"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_and_reload_certificate() {
+        let dir = TempDir::new().unwrap();
+        let cert_file = dir.path().join("cert.pem");
+        let key_file = dir.path().join("key.pem");
+        let write_certificate = |name: &str| {
+            let certified = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+            fs::write(&cert_file, certified.cert.pem()).unwrap();
+            fs::write(&key_file, certified.key_pair.serialize_pem()).unwrap();
+        };
+
+        assert!(load_tls(&cert_file, &key_file).await.is_err());
+        write_certificate("localhost");
+        let tls = load_tls(&cert_file, &key_file).await.unwrap();
+        let before = tls.get_inner();
+
+        write_certificate("red-carpet.local");
+        tls.reload_from_pem_file(&cert_file, &key_file)
+            .await
+            .unwrap();
+        assert!(!std::sync::Arc::ptr_eq(&before, &tls.get_inner()));
+    }
+}
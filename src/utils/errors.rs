@@ -1,5 +1,17 @@
+use std::path::PathBuf;
+
+use axum::{
+    extract::rejection::{JsonRejection, QueryRejection},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use log::{error, warn};
 use serde::Serialize;
 use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::utils::request_id::current_request_id;
 
 #[derive(Error, Debug, Serialize)]
 pub enum DataStoreError {
@@ -22,6 +34,278 @@ pub enum DataStoreError {
         first_name: String,
         last_name: String,
     },
+
+    #[error("Employee: '{id:?}' not found!")]
+    EmployeeNotFound { id: String },
+
+    #[error("Handle: '{handle:?}' is already taken!")]
+    HandleAlreadyTaken { handle: String },
+
+    #[error("Email: '{email:?}' is already taken!")]
+    EmailAlreadyTaken { email: String },
+
+    #[error("Employee: '{id:?}' was modified concurrently (expected version {expected}, found {actual})!")]
+    VersionConflict {
+        id: String,
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("Employee: '{id:?}' is already onboarded!")]
+    AlreadyOnboarded { id: String },
+
+    #[error("Employee: '{id:?}' is not onboarded yet!")]
+    NotOnboarded { id: String },
+
+    #[error("Employee: '{id:?}' has no revision {revision}!")]
+    RevisionNotFound { id: String, revision: u64 },
+
+    #[error("Admin: '{id:?}' already exists!")]
+    AdminAlreadyExists { id: String },
+
+    #[error("Admin: '{id:?}' not found!")]
+    AdminNotFound { id: String },
+
+    #[error("{problems} consistency problems found, `data migrate` fixes missing versions, history and encryption")]
+    Inconsistent { problems: usize },
+
+    #[error("the data store is open read-only")]
+    ReadOnly,
+
+    #[error("the data store is shutting down")]
+    Closed,
+
+    #[error("no encryption key is configured")]
+    EncryptionDisabled,
+
+    #[error("the import file is invalid: {reason}")]
+    InvalidImport { reason: String },
+
+    #[error("storage error: {0}")]
+    Io(String),
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("unable to read config file {path:?}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("invalid config file {path:?}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    #[error("invalid {name} {value:?}: {reason}")]
+    Env {
+        name: &'static str,
+        value: String,
+        reason: String,
+    },
+
+    #[error("invalid configuration: {0}")]
+    Invalid(String),
+}
+
+#[derive(Error, Debug)]
+pub enum BuildError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error("unable to open the data store: {0}")]
+    Storage(#[from] std::io::Error),
+
+    #[error("invalid template: {0}")]
+    Templates(#[from] tera::Error),
+}
+
+// Error of a REST handler, answered with an RFC 7807 problem document
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("Invalid credentials")]
+    Unauthorized,
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("If-Match header is required")]
+    PreconditionRequired,
+
+    #[error("{0}")]
+    Validation(String),
+
+    #[error("{0}")]
+    PayloadTooLarge(String),
+
+    #[error("Idempotency-Key was already used for a different request")]
+    IdempotencyKeyReused,
+
+    #[error("a request with the same Idempotency-Key is still being handled")]
+    IdempotencyKeyInUse,
+
+    #[error(transparent)]
+    Store(#[from] DataStoreError),
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::IdempotencyKeyInUse => StatusCode::CONFLICT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Store(error) => match error {
+                DataStoreError::EmployeeNotFound { .. }
+                | DataStoreError::RevisionNotFound { .. }
+                | DataStoreError::AdminNotFound { .. } => StatusCode::NOT_FOUND,
+                DataStoreError::EmployeeAlreadyExists { .. }
+                | DataStoreError::HandleAlreadyTaken { .. }
+                | DataStoreError::EmailAlreadyTaken { .. }
+                | DataStoreError::AdminAlreadyExists { .. }
+                | DataStoreError::AlreadyOnboarded { .. }
+                | DataStoreError::NotOnboarded { .. } => StatusCode::CONFLICT,
+                DataStoreError::NoOldEnough { .. } | DataStoreError::NoDiploma { .. } => {
+                    StatusCode::UNPROCESSABLE_ENTITY
+                }
+                DataStoreError::VersionConflict { .. } => StatusCode::PRECONDITION_FAILED,
+                DataStoreError::InvalidImport { .. } => StatusCode::BAD_REQUEST,
+                DataStoreError::ReadOnly => StatusCode::FORBIDDEN,
+                DataStoreError::Closed => StatusCode::SERVICE_UNAVAILABLE,
+                DataStoreError::Unknown
+                | DataStoreError::Inconsistent { .. }
+                | DataStoreError::EncryptionDisabled
+                | DataStoreError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+        }
+    }
+
+    // stable identifier of the error for clients, unlike the detail message
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized => "invalid_credentials",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::PreconditionRequired => "if_match_required",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::IdempotencyKeyReused => "idempotency_key_reused",
+            ApiError::IdempotencyKeyInUse => "idempotency_key_in_use",
+            ApiError::Internal(_) => "internal_error",
+            ApiError::Store(error) => match error {
+                DataStoreError::EmployeeNotFound { .. } => "employee_not_found",
+                DataStoreError::RevisionNotFound { .. } => "revision_not_found",
+                DataStoreError::AdminNotFound { .. } => "admin_not_found",
+                DataStoreError::EmployeeAlreadyExists { .. } => "employee_already_exists",
+                DataStoreError::HandleAlreadyTaken { .. } => "handle_taken",
+                DataStoreError::EmailAlreadyTaken { .. } => "email_taken",
+                DataStoreError::AdminAlreadyExists { .. } => "admin_already_exists",
+                DataStoreError::AlreadyOnboarded { .. } => "already_onboarded",
+                DataStoreError::NotOnboarded { .. } => "not_onboarded",
+                DataStoreError::NoOldEnough { .. } => "employee_too_young",
+                DataStoreError::NoDiploma { .. } => "employee_without_diploma",
+                DataStoreError::VersionConflict { .. } => "version_conflict",
+                DataStoreError::InvalidImport { .. } => "invalid_import",
+                DataStoreError::ReadOnly => "read_only",
+                DataStoreError::Closed => "shutting_down",
+                DataStoreError::Unknown
+                | DataStoreError::Inconsistent { .. }
+                | DataStoreError::EncryptionDisabled
+                | DataStoreError::Io(_) => "storage_error",
+            },
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            // well-formed JSON that does not match the schema
+            JsonRejection::JsonDataError(_) => ApiError::Validation(rejection.body_text()),
+            _ => ApiError::BadRequest(rejection.body_text()),
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
+// RFC 7807 body of an error response
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    problem_type: &'static str,
+    #[schema(example = "Conflict")]
+    title: &'static str,
+    #[schema(example = 409)]
+    status: u16,
+    detail: String,
+    // stable identifier of the error, e.g. `employee_already_exists`
+    #[schema(example = "employee_already_exists")]
+    code: &'static str,
+    // `X-Request-Id` of the request
+    request_id: Option<String>,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let problem = Problem {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or_default(),
+            status: status.as_u16(),
+            detail: self.to_string(),
+            code: self.code(),
+            request_id: current_request_id(),
+        };
+        if status.is_server_error() {
+            error!("{problem:?}");
+        } else {
+            warn!("{problem:?}");
+        }
+        let mut response = (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            Json(problem),
+        )
+            .into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Basic realm=\"red_carpet\""),
+            );
+        }
+        response
+    }
+}
+
+impl From<std::io::Error> for DataStoreError {
+    fn from(error: std::io::Error) -> Self {
+        DataStoreError::Io(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for DataStoreError {
+    fn from(error: serde_json::Error) -> Self {
+        DataStoreError::Io(error.to_string())
+    }
 }
 
 pub fn employee_already_exists_error(
@@ -0,0 +1,65 @@
+use axum::http::{header, HeaderMap};
+
+// strong entity tag for a record version
+pub fn etag(version: u64) -> String {
+    format!("\"{version}\"")
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IfMatch {
+    Missing,
+    Any,
+    Version(u64),
+    Invalid,
+}
+
+// read the version a client expects from its `If-Match` header
+pub fn parse_if_match(headers: &HeaderMap) -> IfMatch {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return IfMatch::Missing;
+    };
+    let Ok(value) = value.to_str() else {
+        return IfMatch::Invalid;
+    };
+
+    let value = value.trim();
+    if value == "*" {
+        return IfMatch::Any;
+    }
+
+    value
+        .trim_start_matches("W/")
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .and_then(|version| version.parse::<u64>().ok())
+        .map(IfMatch::Version)
+        .unwrap_or(IfMatch::Invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn if_match(value: &str) -> IfMatch {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MATCH, HeaderValue::from_str(value).unwrap());
+        parse_if_match(&headers)
+    }
+
+    #[test]
+    fn test_etag_round_trip() {
+        assert_eq!(etag(7), "\"7\"");
+        assert_eq!(if_match(&etag(7)), IfMatch::Version(7));
+    }
+
+    #[test]
+    fn test_parse_if_match() {
+        assert_eq!(parse_if_match(&HeaderMap::new()), IfMatch::Missing);
+        assert_eq!(if_match("*"), IfMatch::Any);
+        assert_eq!(if_match("W/\"3\""), IfMatch::Version(3));
+        assert_eq!(if_match("3"), IfMatch::Invalid);
+        assert_eq!(if_match("\"abc\""), IfMatch::Invalid);
+    }
+}
@@ -0,0 +1,484 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{FromRequestParts, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    api::AdminAuth,
+    utils::{errors::ApiError, state::AppState},
+};
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+// set on the responses replayed to a retry
+pub const REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+const MAX_KEY_LENGTH: usize = 255;
+// the default limit of the body extractors
+const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+type Fingerprint = [u8; 32];
+
+struct StoredResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+enum Entry {
+    // the first request with the key is still being handled
+    InFlight(Fingerprint),
+    Done {
+        fingerprint: Fingerprint,
+        response: StoredResponse,
+        expires_at: Instant,
+    },
+}
+
+impl Entry {
+    fn fingerprint(&self) -> &Fingerprint {
+        match self {
+            Entry::InFlight(fingerprint) | Entry::Done { fingerprint, .. } => fingerprint,
+        }
+    }
+}
+
+// Responses to the requests sent with an `Idempotency-Key`, kept in memory
+// for `window`; a zero window turns the keys off.
+#[derive(Clone)]
+pub struct Idempotency {
+    window: Duration,
+    // by scope: admin and key of the request
+    entries: Arc<Mutex<HashMap<Fingerprint, Entry>>>,
+    // to authenticate the admins, whose keys are kept apart
+    state: AppState,
+}
+
+impl Idempotency {
+    pub fn new(window: Duration, state: AppState) -> Self {
+        Idempotency {
+            window,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            state,
+        }
+    }
+}
+
+// releases the key of a request that ended without a response to keep,
+// e.g. a client gone before the handler completed
+struct InFlightGuard<'a> {
+    idempotency: &'a Idempotency,
+    scope: Fingerprint,
+    completed: bool,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.idempotency.entries.lock().unwrap().remove(&self.scope);
+        }
+    }
+}
+
+fn digest(parts: &[&[u8]]) -> Fingerprint {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        // the length keeps `ab` + `c` apart from `a` + `bc`
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn replay(response: &StoredResponse) -> Response {
+    let mut replayed = Response::new(Body::from(response.body.clone()));
+    *replayed.status_mut() = response.status;
+    *replayed.headers_mut() = response.headers.clone();
+    replayed
+        .headers_mut()
+        .insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    replayed
+}
+
+// whether the response answers the request itself, and is kept for the retries
+fn kept(status: StatusCode) -> bool {
+    status.is_success()
+        || status.is_client_error()
+            && !matches!(
+                status,
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+            )
+}
+
+// Run a POST or PATCH sent with an `Idempotency-Key` by an admin once: the
+// retries of the admin with the same key get the first response again, with
+// 422 if they are not the same request and 409 while the first one is
+// running. Only the responses to the request itself are kept, success and
+// client errors but 401, 403 and 429, so that the others can be retried.
+// Requests without the credentials of an admin go to the handler as is.
+pub async fn idempotency(
+    State(idempotency): State<Idempotency>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let key = request.headers().get(&IDEMPOTENCY_KEY_HEADER);
+    if idempotency.window.is_zero()
+        || key.is_none()
+        || !matches!(*request.method(), Method::POST | Method::PATCH)
+    {
+        return next.run(request).await;
+    }
+    let Some(key) = key
+        .and_then(|key| key.to_str().ok())
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+        .map(str::to_string)
+    else {
+        return ApiError::BadRequest(format!(
+            "Idempotency-Key must be 1 to {MAX_KEY_LENGTH} visible ASCII characters"
+        ))
+        .into_response();
+    };
+
+    let (mut parts, body) = request.into_parts();
+    let Ok(AdminAuth(admin)) = AdminAuth::from_request_parts(&mut parts, &idempotency.state).await
+    else {
+        return next.run(Request::from_parts(parts, body)).await;
+    };
+    let Ok(body) = to_bytes(body, MAX_BODY_SIZE).await else {
+        return ApiError::PayloadTooLarge(format!(
+            "the body of a request with an Idempotency-Key is limited to {MAX_BODY_SIZE} bytes"
+        ))
+        .into_response();
+    };
+    let header = |name| {
+        parts
+            .headers
+            .get(name)
+            .map_or(&b""[..], HeaderValue::as_bytes)
+    };
+    // the key of one admin is not visible to another
+    let scope = digest(&[admin.as_bytes(), key.as_bytes()]);
+    let fingerprint = digest(&[
+        parts.method.as_str().as_bytes(),
+        parts.uri.to_string().as_bytes(),
+        header(header::CONTENT_TYPE),
+        &body,
+    ]);
+
+    {
+        let mut entries = idempotency.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| match entry {
+            Entry::Done { expires_at, .. } => *expires_at > now,
+            Entry::InFlight(_) => true,
+        });
+        match entries.get(&scope) {
+            Some(entry) if *entry.fingerprint() != fingerprint => {
+                return ApiError::IdempotencyKeyReused.into_response();
+            }
+            Some(Entry::InFlight(_)) => return ApiError::IdempotencyKeyInUse.into_response(),
+            Some(Entry::Done { response, .. }) => {
+                info!("Replaying the response to Idempotency-Key {key:?}");
+                return replay(response);
+            }
+            None => {
+                entries.insert(scope, Entry::InFlight(fingerprint));
+            }
+        }
+    }
+    let mut guard = InFlightGuard {
+        idempotency: &idempotency,
+        scope,
+        completed: false,
+    };
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if !kept(response.status()) {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(error) => {
+            warn!("Unable to read the response to Idempotency-Key {key:?}: {error}");
+            return ApiError::Internal(error.to_string()).into_response();
+        }
+    };
+    let stored = StoredResponse {
+        status: parts.status,
+        headers: parts.headers.clone(),
+        body: body.clone(),
+    };
+    idempotency.entries.lock().unwrap().insert(
+        guard.scope,
+        Entry::Done {
+            fingerprint,
+            response: stored,
+            expires_at: Instant::now() + idempotency.window,
+        },
+    );
+    guard.completed = true;
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{http::Method, middleware, routing::post, Router};
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use tempfile::TempDir;
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{api::employees::EMPLOYEES_PATH, testing};
+
+    // the handler answers with the status named by the body, 201 by default
+    async fn app(
+        dir: &TempDir,
+        window: Duration,
+        calls: Arc<AtomicUsize>,
+        release: Arc<Notify>,
+    ) -> Router {
+        let state = testing::state(dir).await;
+        testing::add_admin(&state.file_manager, "grace", "hopper").await;
+        Router::new()
+            .route(
+                "/api/v1/employees",
+                post(move |body: String| async move {
+                    let (calls, release) = (calls.clone(), release.clone());
+                    let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                    if body == "slow" {
+                        release.notified().await;
+                    }
+                    let status = match body.as_str() {
+                        "fail" => StatusCode::SERVICE_UNAVAILABLE,
+                        "invalid" => StatusCode::UNPROCESSABLE_ENTITY,
+                        "unauthorized" => StatusCode::UNAUTHORIZED,
+                        "forbidden" => StatusCode::FORBIDDEN,
+                        "limited" => StatusCode::TOO_MANY_REQUESTS,
+                        _ => StatusCode::CREATED,
+                    };
+                    (status, format!("call {call}"))
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                Idempotency::new(window, state),
+                idempotency,
+            ))
+    }
+
+    async fn send(app: &Router, key: Option<&str>, credentials: &str, body: &str) -> Response {
+        let mut request = Request::post("/api/v1/employees")
+            .header(header::AUTHORIZATION, testing::basic_auth(credentials));
+        if let Some(key) = key {
+            request = request.header(IDEMPOTENCY_KEY_HEADER, key);
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn text(response: Response) -> String {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_replay_and_reuse() {
+        let dir = TempDir::new().unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(
+            &dir,
+            Duration::from_secs(60),
+            calls.clone(),
+            Arc::new(Notify::new()),
+        )
+        .await;
+        let admin = "admin:secret";
+
+        let response = send(&app, Some("a"), admin, "Ada").await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(response.headers().get(REPLAYED_HEADER).is_none());
+        assert_eq!(text(response).await, "call 1");
+        let response = send(&app, Some("a"), admin, "Ada").await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[REPLAYED_HEADER], "true");
+        assert_eq!(text(response).await, "call 1");
+
+        let response = send(&app, Some("a"), admin, "Grace").await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        // keys are per admin, and optional
+        assert_eq!(
+            text(send(&app, Some("a"), "grace:hopper", "Grace").await).await,
+            "call 2"
+        );
+        assert_eq!(text(send(&app, None, admin, "Ada").await).await, "call 3");
+        let response = send(&app, Some(""), admin, "Ada").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        // the wrong password gets no replay, the handler rejects it
+        let response = send(&app, Some("a"), "admin:wrong", "Ada").await;
+        assert!(response.headers().get(REPLAYED_HEADER).is_none());
+        assert_eq!(text(response).await, "call 4");
+
+        // a client error caused by the body is kept
+        send(&app, Some("b"), admin, "invalid").await;
+        let response = send(&app, Some("b"), admin, "invalid").await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(text(response).await, "call 5");
+
+        // server errors, rejections and limits are not
+        let mut call = 5;
+        for body in ["fail", "unauthorized", "forbidden", "limited"] {
+            for _ in 0..2 {
+                call += 1;
+                let response = send(&app, Some(body), admin, body).await;
+                assert!(response.headers().get(REPLAYED_HEADER).is_none());
+                assert_eq!(text(response).await, format!("call {call}"));
+            }
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 13);
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_and_expired_keys() {
+        let dir = TempDir::new().unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let release = Arc::new(Notify::new());
+        let app = app(
+            &dir,
+            Duration::from_millis(50),
+            calls.clone(),
+            release.clone(),
+        )
+        .await;
+        let admin = "admin:secret";
+
+        let first = tokio::spawn({
+            let app = app.clone();
+            async move { send(&app, Some("a"), admin, "slow").await }
+        });
+        while calls.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        let response = send(&app, Some("a"), admin, "slow").await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        release.notify_one();
+        assert_eq!(text(first.await.unwrap()).await, "call 1");
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let response = send(&app, Some("a"), admin, "Ada").await;
+        assert!(response.headers().get(REPLAYED_HEADER).is_none());
+        assert_eq!(text(response).await, "call 2");
+
+        let dir = TempDir::new().unwrap();
+        let app = self::app(&dir, Duration::ZERO, calls.clone(), release).await;
+        send(&app, Some("c"), admin, "Ada").await;
+        let response = send(&app, Some("c"), admin, "Ada").await;
+        assert!(response.headers().get(REPLAYED_HEADER).is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_keys() {
+        let dir = TempDir::new().unwrap();
+        let app = testing::app(&dir).await;
+        let send_with_key = |method: Method, uri: String, key: &str, body: Option<Value>| {
+            let mut request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::AUTHORIZATION, testing::basic_auth("admin:secret"))
+                .header(header::IF_MATCH, "*")
+                .header(IDEMPOTENCY_KEY_HEADER, key);
+            let body = match body {
+                Some(body) => {
+                    request = request.header(header::CONTENT_TYPE, "application/json");
+                    Body::from(body.to_string())
+                }
+                None => Body::empty(),
+            };
+            let request = request.body(body).unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let replayed = response.headers().contains_key(REPLAYED_HEADER);
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                (
+                    status,
+                    replayed,
+                    serde_json::from_slice::<Value>(&body).unwrap(),
+                )
+            }
+        };
+
+        let create = || {
+            send_with_key(
+                Method::POST,
+                EMPLOYEES_PATH.to_string(),
+                "hr-1",
+                Some(testing::ada()),
+            )
+        };
+        let (status, replayed, first) = create().await;
+        assert_eq!((status, replayed), (StatusCode::CREATED, false));
+        let (status, replayed, retry) = create().await;
+        assert_eq!((status, replayed), (StatusCode::CREATED, true));
+        assert_eq!(retry["data"]["id"], first["data"]["id"]);
+
+        let mut other = testing::ada();
+        other["age"] = json!(37);
+        let (status, _, problem) = send_with_key(
+            Method::POST,
+            EMPLOYEES_PATH.to_string(),
+            "hr-1",
+            Some(other),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem["code"], "idempotency_key_reused");
+        // without the key, the retry is a second request
+        let (status, _, _) = testing::send(
+            &app,
+            Method::POST,
+            EMPLOYEES_PATH,
+            None,
+            Some(testing::ada()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let onboard = format!(
+            "{EMPLOYEES_PATH}/{}:onboard",
+            first["data"]["id"].as_str().unwrap()
+        );
+        let (status, _, onboarded) =
+            send_with_key(Method::POST, onboard.clone(), "hr-2", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, replayed, retry) =
+            send_with_key(Method::POST, onboard.clone(), "hr-2", None).await;
+        assert_eq!((status, replayed), (StatusCode::OK, true));
+        assert_eq!(retry["password"], onboarded["password"]);
+
+        // the password is not replayed to another password of the admin
+        let request = Request::post(onboard)
+            .header(header::AUTHORIZATION, testing::basic_auth("admin:wrong"))
+            .header(header::IF_MATCH, "*")
+            .header(IDEMPOTENCY_KEY_HEADER, "hr-2")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().get(REPLAYED_HEADER).is_none());
+    }
+}
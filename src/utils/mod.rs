@@ -1,3 +1,8 @@
 pub mod errors;
+pub mod etag;
+pub mod idempotency;
+pub mod pagination;
 pub mod password_utils;
+pub mod request_id;
 pub mod state;
+pub mod xlsx;
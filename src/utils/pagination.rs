@@ -0,0 +1,153 @@
+use std::ops::Range;
+
+use axum::http::{HeaderValue, Uri};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{de::DeserializeOwned, Serialize};
+
+// A page of a list, numbered from 1
+#[derive(Debug, PartialEq)]
+pub struct Page {
+    pub page: usize,
+    pub per_page: usize,
+    // number of items on every page
+    pub total: usize,
+}
+
+impl Page {
+    // indexes of the items of the page in the whole list
+    pub fn range(&self) -> Range<usize> {
+        let start = (self.page - 1)
+            .saturating_mul(self.per_page)
+            .min(self.total);
+        start..(start + self.per_page).min(self.total)
+    }
+
+    pub fn last_page(&self) -> usize {
+        self.total.div_ceil(self.per_page).max(1)
+    }
+
+    // RFC 8288 `Link` header to the first, previous, next and last pages of
+    // `uri`, keeping its other query parameters
+    pub fn link_header(&self, uri: &Uri) -> HeaderValue {
+        let last = self.last_page();
+        let mut links = vec![(1, "first")];
+        if self.page > 1 {
+            links.push(((self.page - 1).min(last), "prev"));
+        }
+        if self.page < last {
+            links.push((self.page + 1, "next"));
+        }
+        links.push((last, "last"));
+
+        let links = links
+            .into_iter()
+            .map(|(page, rel)| (with_position(uri, "page", &page.to_string()), rel))
+            .collect();
+        link_header(links)
+    }
+}
+
+// `Link` header of a list read with cursors: its first page and, unless the
+// list is over, the next one
+pub fn cursor_link_header(uri: &Uri, next_cursor: Option<&str>) -> HeaderValue {
+    let mut links = vec![(with_position(uri, "page", "1"), "first")];
+    if let Some(cursor) = next_cursor {
+        links.push((with_position(uri, "cursor", cursor), "next"));
+    }
+    link_header(links)
+}
+
+fn link_header(links: Vec<(String, &str)>) -> HeaderValue {
+    let link = links
+        .into_iter()
+        .map(|(uri, rel)| format!("<{uri}>; rel=\"{rel}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    // built from a valid uri, it only holds visible ASCII
+    HeaderValue::from_str(&link).unwrap()
+}
+
+// `uri` pointing at another position of the list: its `page` and `cursor`
+// parameters are replaced by `name=value`
+fn with_position(uri: &Uri, name: &str, value: &str) -> String {
+    let mut parameters: Vec<&str> = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|parameter| {
+            !parameter.is_empty()
+                && !parameter.starts_with("page=")
+                && !parameter.starts_with("cursor=")
+        })
+        .collect();
+    let position = format!("{name}={value}");
+    parameters.push(&position);
+    format!("{}?{}", uri.path(), parameters.join("&"))
+}
+
+// opaque, URL safe token for `position`; clients hand it back as is
+pub fn encode_cursor<T: Serialize>(position: &T) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(position).unwrap())
+}
+
+// position of a cursor made by `encode_cursor`, None for anything else
+pub fn decode_cursor<T: DeserializeOwned>(cursor: &str) -> Option<T> {
+    let json = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pages_and_links() {
+        let page = Page {
+            page: 2,
+            per_page: 10,
+            total: 25,
+        };
+        assert_eq!(page.range(), 10..20);
+        assert_eq!(page.last_page(), 3);
+
+        let uri: Uri = "/api/v2/employees?onboarded=false&page=2&per_page=10"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            page.link_header(&uri),
+            "</api/v2/employees?onboarded=false&per_page=10&page=1>; rel=\"first\", \
+             </api/v2/employees?onboarded=false&per_page=10&page=1>; rel=\"prev\", \
+             </api/v2/employees?onboarded=false&per_page=10&page=3>; rel=\"next\", \
+             </api/v2/employees?onboarded=false&per_page=10&page=3>; rel=\"last\""
+        );
+
+        let empty = Page {
+            page: 2,
+            per_page: 10,
+            total: 0,
+        };
+        assert_eq!(empty.range(), 0..0);
+        assert_eq!(
+            empty.link_header(&"/list".parse().unwrap()),
+            "</list?page=1>; rel=\"first\", </list?page=1>; rel=\"prev\", </list?page=1>; rel=\"last\""
+        );
+    }
+
+    #[test]
+    fn test_cursors() {
+        let cursor = encode_cursor(&42u64);
+        assert_eq!(decode_cursor::<u64>(&cursor), Some(42));
+        assert_eq!(decode_cursor::<u64>("not a cursor"), None);
+        assert_eq!(decode_cursor::<String>(&cursor), None);
+
+        let uri: Uri = "/list?cursor=abc&per_page=2".parse().unwrap();
+        assert_eq!(
+            cursor_link_header(&uri, Some("def")),
+            "</list?per_page=2&page=1>; rel=\"first\", </list?per_page=2&cursor=def>; rel=\"next\""
+        );
+        assert_eq!(
+            cursor_link_header(&uri, None),
+            "</list?per_page=2&page=1>; rel=\"first\""
+        );
+    }
+}
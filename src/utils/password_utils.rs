@@ -1,4 +1,4 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use log::warn;
 use pbkdf2::{
@@ -20,9 +20,15 @@ async fn password(library: String, size: u32) -> String {
 }
 
 pub async fn generate_handle(first_name: String, last_name: String) -> String {
+    base_handle(&first_name, &last_name)
+}
+
+// initial of the first name followed by the last name, in lowercase
+pub fn base_handle(first_name: &str, last_name: &str) -> String {
+    let initial = first_name.to_lowercase().chars().next();
     format!(
         "{}{}",
-        first_name.to_lowercase().chars().next().unwrap(),
+        initial.unwrap_or_default(),
         last_name.to_lowercase()
     )
 }
@@ -53,6 +59,11 @@ pub async fn hash_password(password: String) -> String {
     password_hash
 }
 
+// whether the password is a PHC hash rather than clear text
+pub fn is_hashed_password(password: &str) -> bool {
+    PasswordHash::new(password).is_ok()
+}
+
 pub async fn verify_hashed_password(password: String, hashed_password: String) -> bool {
     let parsed_hash = PasswordHash::new(&hashed_password).unwrap();
     Pbkdf2
@@ -74,8 +85,8 @@ pub async fn generate_session_token(id: String) -> String {
     format!("{}:{}:{}", id, timestamp, random_string)
 }
 
-// 1 hour expiration time
-pub async fn validate_token_expiration(token: String) -> bool {
+// the token is valid for `lifetime` after it was generated
+pub async fn validate_token_expiration(token: String, lifetime: Duration) -> bool {
     let parts: Vec<&str> = token.split(':').collect();
     let timestamp = parts[1].parse::<u64>().unwrap();
     let current_timestamp = SystemTime::now()
@@ -83,7 +94,7 @@ pub async fn validate_token_expiration(token: String) -> bool {
         .unwrap()
         .as_secs();
     let elapsed_time = current_timestamp - timestamp;
-    elapsed_time < 3600
+    elapsed_time < lifetime.as_secs()
 }
 
 #[cfg(test)]
@@ -136,7 +147,7 @@ mod tests {
     fn test_validate_token_expiration() {
         let rt = Runtime::new().unwrap();
         let token = rt.block_on(generate_session_token("user123".to_string()));
-        let is_valid = rt.block_on(validate_token_expiration(token));
+        let is_valid = rt.block_on(validate_token_expiration(token, Duration::from_secs(3600)));
         assert!(is_valid);
     }
 
@@ -149,7 +160,7 @@ mod tests {
             .as_secs()
             - 3601;
         let token = format!("user123:{}:randomstring", timestamp);
-        let is_valid = rt.block_on(validate_token_expiration(token));
+        let is_valid = rt.block_on(validate_token_expiration(token, Duration::from_secs(3600)));
         assert!(!is_valid);
     }
 }
@@ -0,0 +1,159 @@
+use std::io::{self, Seek, Write};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/></Types>"#;
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+const WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/></Relationships>"#;
+const SHEET_START: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#;
+const SHEET_END: &str = "</sheetData></worksheet>";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Text(String),
+    Number(u64),
+    Bool(bool),
+    Empty,
+}
+
+// text of an XML element or attribute; characters XML cannot hold are dropped
+fn escape_xml(text: &str, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\t' | '\n' | '\r' => output.push(c),
+            c if c < ' ' => {}
+            c => output.push(c),
+        }
+    }
+}
+
+// Workbook with a single sheet, written row by row: only the current row is
+// held in memory, the rest is compressed and passed on to `writer`. The
+// sheet is a ZIP64 entry, so it can grow past 4 GiB.
+pub struct XlsxWriter<W: Write + Seek> {
+    zip: ZipWriter<W>,
+}
+
+impl<W: Write + Seek> XlsxWriter<W> {
+    pub fn new(writer: W, sheet_name: &str) -> io::Result<Self> {
+        let mut zip = ZipWriter::new(writer);
+        let mut workbook = String::from(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name=""#,
+        );
+        escape_xml(sheet_name, &mut workbook);
+        workbook.push_str(r#"" sheetId="1" r:id="rId1"/></sheets></workbook>"#);
+
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, content) in [
+            ("[Content_Types].xml", CONTENT_TYPES),
+            ("_rels/.rels", ROOT_RELS),
+            ("xl/workbook.xml", &workbook),
+            ("xl/_rels/workbook.xml.rels", WORKBOOK_RELS),
+        ] {
+            zip.start_file(name, options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+        zip.start_file("xl/worksheets/sheet1.xml", options.large_file(true))?;
+        zip.write_all(SHEET_START.as_bytes())?;
+        Ok(XlsxWriter { zip })
+    }
+
+    pub fn write_row(&mut self, cells: &[Cell]) -> io::Result<()> {
+        let mut row = String::from("<row>");
+        for cell in cells {
+            match cell {
+                Cell::Text(text) => {
+                    row.push_str(r#"<c t="inlineStr"><is><t xml:space="preserve">"#);
+                    escape_xml(text, &mut row);
+                    row.push_str("</t></is></c>");
+                }
+                Cell::Number(number) => row.push_str(&format!("<c><v>{number}</v></c>")),
+                Cell::Bool(value) => {
+                    row.push_str(&format!(r#"<c t="b"><v>{}</v></c>"#, u8::from(*value)))
+                }
+                // keeps the next cells in their column
+                Cell::Empty => row.push_str("<c/>"),
+            }
+        }
+        row.push_str("</row>");
+        self.zip.write_all(row.as_bytes())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.zip.write_all(SHEET_END.as_bytes())?;
+        Ok(self.zip.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use zip::ZipArchive;
+
+    use super::*;
+
+    // content of every entry
+    fn entries(workbook: Cursor<Vec<u8>>) -> Vec<(String, String)> {
+        let mut archive = ZipArchive::new(workbook).unwrap();
+        (0..archive.len())
+            .map(|index| {
+                let mut file = archive.by_index(index).unwrap();
+                let mut content = String::new();
+                file.read_to_string(&mut content).unwrap();
+                (file.name().to_string(), content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_workbook() {
+        let mut writer = XlsxWriter::new(Cursor::new(vec![]), "Employees & co").unwrap();
+        writer
+            .write_row(&[
+                Cell::Text("first_name".to_string()),
+                Cell::Text("age".to_string()),
+            ])
+            .unwrap();
+        for _ in 0..1000 {
+            writer
+                .write_row(&[
+                    Cell::Text("<Ada>\u{1} ".to_string()),
+                    Cell::Number(36),
+                    Cell::Empty,
+                    Cell::Bool(true),
+                ])
+                .unwrap();
+        }
+        let workbook = writer.finish().unwrap();
+
+        let entries = entries(workbook);
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "[Content_Types].xml",
+                "_rels/.rels",
+                "xl/workbook.xml",
+                "xl/_rels/workbook.xml.rels",
+                "xl/worksheets/sheet1.xml"
+            ]
+        );
+        assert!(entries[2].1.contains(r#"name="Employees &amp; co""#));
+        let sheet = &entries[4].1;
+        assert!(sheet.ends_with("</sheetData></worksheet>"));
+        assert_eq!(sheet.matches("<row>").count(), 1001);
+        assert!(sheet.contains(
+            r#"<row><c t="inlineStr"><is><t xml:space="preserve">&lt;Ada&gt; </t></is></c><c><v>36</v></c><c/><c t="b"><v>1</v></c></row>"#
+        ));
+    }
+}
@@ -0,0 +1,367 @@
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::models::{employee_models::Employee, view_models::EmployeeView};
+
+// fields of `EmployeeView`, in the order of the record
+pub const FIELDS: [&str; 12] = [
+    "id",
+    "first_name",
+    "last_name",
+    "personal_email",
+    "avaya_email",
+    "age",
+    "diploma",
+    "onboarded",
+    "handle",
+    "secure_password",
+    "version",
+    "archived",
+];
+
+// Who an employee is shown to. Each projection is a subset of the fields of
+// `EmployeeView`, so that none of them can show a password.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    // colleagues, e.g. in the team directory of the employee pages
+    Public,
+    // administrators, on the dashboard and through the API
+    Admin,
+    // the employee, on their own pages
+    SelfService,
+}
+
+impl Projection {
+    pub fn fields(self) -> &'static [&'static str] {
+        match self {
+            Projection::Public => &["id", "first_name", "last_name", "avaya_email", "handle"],
+            Projection::Admin => &FIELDS,
+            // an archived employee cannot log in
+            Projection::SelfService => &FIELDS[..FIELDS.len() - 1],
+        }
+    }
+
+    pub fn view(self, employee: &Employee) -> EmployeeFields {
+        FieldSet::all(self).view(employee)
+    }
+
+    pub fn views<'a>(
+        self,
+        employees: impl IntoIterator<Item = &'a Employee>,
+    ) -> Vec<EmployeeFields> {
+        let fields = FieldSet::all(self);
+        employees
+            .into_iter()
+            .map(|employee| fields.view(employee))
+            .collect()
+    }
+}
+
+// fields of the employees shown by a response or a page
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSet(Vec<&'static str>);
+
+impl FieldSet {
+    pub fn all(projection: Projection) -> Self {
+        FieldSet(projection.fields().to_vec())
+    }
+
+    // fields of `projection` named in `fields`, e.g. `first_name,handle`, all
+    // of them when no field is given; `id` is always kept
+    pub fn parse(projection: Projection, fields: Option<&str>) -> Result<Self, String> {
+        let Some(fields) = fields else {
+            return Ok(FieldSet::all(projection));
+        };
+        let names: Vec<&str> = fields
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect();
+        if names.is_empty() {
+            return Err("fields must name at least one field".to_string());
+        }
+        for name in &names {
+            if *name == "password" {
+                return Err("passwords are never returned".to_string());
+            }
+            if !projection.fields().contains(name) {
+                return Err(format!(
+                    "unknown field {name:?}, expected some of {}",
+                    projection.fields().join(", ")
+                ));
+            }
+        }
+        Ok(FieldSet(
+            projection
+                .fields()
+                .iter()
+                .filter(|field| **field == "id" || names.contains(field))
+                .copied()
+                .collect(),
+        ))
+    }
+
+    pub fn view(&self, employee: &Employee) -> EmployeeFields {
+        let Ok(Value::Object(mut values)) = serde_json::to_value(EmployeeView::from(employee))
+        else {
+            unreachable!("an employee view is a JSON object");
+        };
+        EmployeeFields(
+            self.0
+                .iter()
+                .filter_map(|field| values.remove(*field).map(|value| (*field, value)))
+                .collect(),
+        )
+    }
+}
+
+// The fields of an employee a reader may see, serialized as an object in the
+// order of the record; documented as `EmployeeView`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmployeeFields(Vec<(&'static str, Value)>);
+
+impl EmployeeFields {
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.0
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, value)| value)
+    }
+}
+
+impl Serialize for EmployeeFields {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in &self.0 {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Method, Request, StatusCode},
+    };
+    use chrono::Utc;
+    use http_body_util::BodyExt;
+    use serde_json::json;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        api::employees::EMPLOYEES_PATH,
+        models::employee_models::ArchiveInfo,
+        testing::{ada, routes, send, state},
+    };
+
+    const PASSWORD: &str = "S3cret!";
+    const HASHED_PASSWORD: &str = "$pbkdf2-sha256$i=1000,l=32$c2FsdA$aGFzaA";
+
+    fn employee(password: &str) -> Employee {
+        Employee {
+            id: Some("id-ada".to_string()),
+            first_name: "Ada".to_string(),
+            last_name: "Lovelace".to_string(),
+            personal_email: Some("ada@example.com".to_string()),
+            avaya_email: Some("alovelace@avaya.com".to_string()),
+            age: 36,
+            diploma: "Maths".to_string(),
+            onboarded: Some(true),
+            handle: Some("alovelace".to_string()),
+            password: Some(password.to_string()),
+            secure_password: Some(password == HASHED_PASSWORD),
+            version: 3,
+            archived: Some(ArchiveInfo {
+                reason: "Left".to_string(),
+                actor: "admin".to_string(),
+                archived_at: Utc::now(),
+            }),
+        }
+    }
+
+    fn keys(fields: &EmployeeFields) -> Vec<&str> {
+        fields.0.iter().map(|(name, _)| *name).collect()
+    }
+
+    #[test]
+    fn test_projections_never_show_passwords() {
+        for password in [PASSWORD, HASHED_PASSWORD] {
+            let employee = employee(password);
+            for projection in [
+                Projection::Public,
+                Projection::Admin,
+                Projection::SelfService,
+            ] {
+                let json = serde_json::to_string(&projection.view(&employee)).unwrap();
+                assert!(!json.contains("\"password\""), "{json}");
+                assert!(!json.contains(password), "{json}");
+            }
+        }
+
+        let employee = employee(PASSWORD);
+        assert_eq!(
+            keys(&Projection::Public.view(&employee)),
+            ["id", "first_name", "last_name", "avaya_email", "handle"]
+        );
+        assert_eq!(keys(&Projection::Admin.view(&employee)), FIELDS);
+        let own = Projection::SelfService.view(&employee);
+        assert!(own.get("archived").is_none());
+        assert_eq!(own.get("personal_email").unwrap(), "ada@example.com");
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let employee = employee(PASSWORD);
+        let fields = FieldSet::parse(Projection::Admin, Some("handle, first_name,handle")).unwrap();
+        let view = fields.view(&employee);
+        assert_eq!(keys(&view), ["id", "first_name", "handle"]);
+        assert_eq!(
+            serde_json::to_string(&view).unwrap(),
+            r#"{"id":"id-ada","first_name":"Ada","handle":"alovelace"}"#
+        );
+        assert_eq!(
+            FieldSet::parse(Projection::Admin, None).unwrap(),
+            FieldSet::all(Projection::Admin)
+        );
+
+        assert_eq!(
+            FieldSet::parse(Projection::Admin, Some("handle,password")).unwrap_err(),
+            "passwords are never returned"
+        );
+        assert!(FieldSet::parse(Projection::Public, Some("age"))
+            .unwrap_err()
+            .contains("\"age\""));
+        assert!(FieldSet::parse(Projection::Admin, Some(" , ")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_credentials_never_leave_the_server() {
+        let dir = TempDir::new().unwrap();
+        let state = state(&dir).await;
+        let app = routes(state.clone());
+        let (_, headers, _) = send(&app, Method::POST, EMPLOYEES_PATH, None, Some(ada())).await;
+        let uri = headers[header::LOCATION].to_str().unwrap().to_string();
+        let (_, _, onboarded) = send(
+            &app,
+            Method::POST,
+            &format!("{uri}:onboard"),
+            Some("*"),
+            None,
+        )
+        .await;
+        // the action generating the password is the only one showing it
+        let first_password = onboarded["password"].as_str().unwrap().to_string();
+        let id = onboarded["data"]["id"].as_str().unwrap().to_string();
+
+        let get_html = |uri: String| {
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+        let outputs = |password: String| {
+            let (app, uri, id) = (app.clone(), uri.clone(), id.clone());
+            async move {
+                let mut outputs = vec![];
+                for path in [
+                    uri.clone(),
+                    format!("{uri}?fields=handle,secure_password"),
+                    EMPLOYEES_PATH.to_string(),
+                    format!("{EMPLOYEES_PATH}/changes"),
+                ] {
+                    let (status, _, body) = send(&app, Method::GET, &path, None, None).await;
+                    assert_eq!(status, StatusCode::OK, "{path}");
+                    let body = body.to_string();
+                    assert!(!body.contains("\"password\""), "{path}: {body}");
+                    outputs.push((path, body));
+                }
+                for path in [
+                    "/list/employees".to_string(),
+                    format!("/select/employee/{id}"),
+                    format!("/edit/employee/{id}"),
+                ] {
+                    outputs.push((path.clone(), get_html(path).await));
+                }
+                for (path, output) in &outputs {
+                    assert!(!output.contains(&password), "{path}: {output}");
+                }
+            }
+        };
+        outputs(first_password).await;
+
+        // a secured password is hashed, the hash is no more shown
+        let mut secured = state.file_manager.get_employee(&id).unwrap();
+        secured.password = Some(HASHED_PASSWORD.to_string());
+        secured.secure_password = Some(true);
+        let secured = state
+            .file_manager
+            .update_employee(&id, secured, None, "admin")
+            .await
+            .unwrap();
+        outputs(HASHED_PASSWORD.to_string()).await;
+
+        // the pages of the employee, with the team directory
+        let form = format!(
+            "id={id}&version={}&first_name=Ada&last_name=Lovelace&age=37&diploma=Maths",
+            secured.version
+        );
+        let request = Request::post("/update/onboarded")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let page = String::from_utf8(body.to_vec()).unwrap();
+        assert!(page.contains("alovelace@avaya.com"), "{page}");
+        assert!(!page.contains(HASHED_PASSWORD));
+
+        let (status, _, body) = send(
+            &app,
+            Method::GET,
+            &format!("{uri}?fields=handle"),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"], json!({"id": id, "handle": "alovelace"}));
+        let (status, _, problem) = send(
+            &app,
+            Method::GET,
+            &format!("{EMPLOYEES_PATH}?fields=password"),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(problem["detail"], "passwords are never returned");
+
+        // the technician onboarding an employee on the dashboard sees the
+        // password once
+        let grace =
+            json!({"first_name": "Grace", "last_name": "Hopper", "age": 40, "diploma": "PhD"});
+        let (_, _, grace) = send(&app, Method::POST, EMPLOYEES_PATH, None, Some(grace)).await;
+        let grace_id = grace["data"]["id"].as_str().unwrap().to_string();
+        let form =
+            format!("id={grace_id}&version=1&first_name=Grace&last_name=Hopper&age=40&diploma=PhD");
+        let request = Request::post("/onboard/employee")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8(body.to_vec())
+            .unwrap()
+            .contains("Plain Text Password"));
+        assert!(!get_html(format!("/select/employee/{grace_id}"))
+            .await
+            .contains("Plain Text Password"));
+    }
+}